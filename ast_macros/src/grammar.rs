@@ -0,0 +1,173 @@
+use syn::parse::{Parse, ParseStream, Result};
+use syn::punctuated::Punctuated;
+use syn::{Attribute, Ident, Token, Type};
+
+pub struct Grammar {
+    pub pointer: Pointer,
+    /// Types fields can hold that aren't rules, declared with `extern Token, f64;` before the rules.
+    pub externs: Vec<Ident>,
+    pub rules: Punctuated<Rule, Token![;]>,
+}
+
+impl Parse for Grammar {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let mut pointer = Pointer::Box;
+
+        for attribute in input.call(Attribute::parse_inner)? {
+            if attribute.path.is_ident("pointer") {
+                pointer = attribute.parse_args()?;
+            } else {
+                return Err(syn::Error::new_spanned(
+                    attribute,
+                    "unknown grammar attribute, expected `#![pointer(...)]`",
+                ));
+            }
+        }
+
+        let mut externs = Vec::new();
+
+        while input.peek(Token![extern]) {
+            input.parse::<Token![extern]>()?;
+
+            externs.extend(Punctuated::<Ident, Token![,]>::parse_separated_nonempty(
+                input,
+            )?);
+
+            input.parse::<Token![;]>()?;
+        }
+
+        Ok(Self {
+            pointer,
+            externs,
+            rules: Punctuated::<Rule, Token![;]>::parse_terminated(input)?,
+        })
+    }
+}
+
+/// How fields referring to a rule that contains them (directly or not) are stored, chosen for the
+/// whole grammar with `#![pointer(Box)]`, `#![pointer(Rc)]` or `#![pointer(Arena)]`. Box is the
+/// default.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Pointer {
+    Box,
+    /// Cheap to clone, trees can be shared.
+    Rc,
+    /// Nodes live in a generated `SyntaxArena` and are referred to by a `NodeId`.
+    Arena,
+}
+
+impl Parse for Pointer {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let ident: Ident = input.parse()?;
+
+        match ident.to_string().as_str() {
+            "Box" => Ok(Self::Box),
+            "Rc" => Ok(Self::Rc),
+            "Arena" => Ok(Self::Arena),
+            _ => Err(syn::Error::new_spanned(
+                ident,
+                "unknown pointer kind, expected one of `Box`, `Rc` or `Arena`",
+            )),
+        }
+    }
+}
+
+pub struct Rule {
+    pub name: Ident,
+    pub body: RuleBody,
+}
+
+impl Parse for Rule {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let name = input.parse()?;
+
+        input.parse::<Token![=>]>()?;
+
+        let body = input.parse()?;
+
+        Ok(Self { name, body })
+    }
+}
+
+pub enum RuleBody {
+    Unique {
+        fields: Punctuated<Field, Token![,]>,
+    },
+    Union {
+        variants: Punctuated<UnionVariant, Token![|]>,
+    },
+}
+
+impl Parse for RuleBody {
+    fn parse(input: ParseStream) -> Result<Self> {
+        if input.peek(Ident) && input.peek2(Token![:]) {
+            // Unique expresions start with a field that is: ``ident: Value``
+            let fields = Punctuated::<Field, Token![,]>::parse_separated_nonempty(input)?;
+
+            Ok(Self::Unique { fields })
+        } else {
+            let variants = Punctuated::<UnionVariant, Token![|]>::parse_separated_nonempty(input)?;
+
+            Ok(Self::Union { variants })
+        }
+    }
+}
+
+pub enum UnionVariant {
+    /// This variant name and type are the same.
+    ImplicitType(Type),
+
+    /// This variant doesn't really have a type, can be a reserved word, for example.
+    /// To distinguish from ImplicitTypes and Atoms, a '@' needs to be prefixed to the atom.
+    Atom(Ident),
+
+    /// The name of the variant and the type are different, usually for variants whose types
+    /// are primitive or 3p types. It can be desirable to add an alias that makes intent more
+    /// explicit or when 2 different productions of the rule may have the same underlying type, but
+    /// semantically are different.
+    ///
+    /// To Alias a type defined like: `AliasName as UnderlyingType`.
+    Aliased { alias: Ident, ty: Type },
+}
+
+impl Parse for UnionVariant {
+    fn parse(input: ParseStream) -> Result<Self> {
+        if input.peek(Token![@]) {
+            // Atoms start with @
+            input.parse::<Token![@]>()?;
+
+            let ident = input.parse()?;
+
+            Ok(Self::Atom(ident))
+        } else if input.peek(Ident) && input.peek2(Token![as]) {
+            // as is used to alias stuff
+
+            let alias = input.parse()?;
+
+            input.parse::<Token![as]>()?;
+
+            let ty = input.parse()?;
+
+            Ok(Self::Aliased { alias, ty })
+        } else {
+            let ty = input.parse()?;
+
+            Ok(Self::ImplicitType(ty))
+        }
+    }
+}
+
+pub struct Field {
+    pub name: Ident,
+    pub ty: Type,
+}
+
+impl Parse for Field {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let name: Ident = input.parse()?;
+        input.parse::<Token![:]>()?;
+        let ty: Type = input.parse()?;
+
+        Ok(Field { name, ty })
+    }
+}
@@ -1,207 +1,118 @@
 use convert_case::{Case, Casing};
-use proc_macro2::TokenStream;
 use quote::quote;
-use syn::parse::{Parse, ParseStream, Result};
-use syn::punctuated::Punctuated;
-use syn::{parse_macro_input, Ident, Token, Type};
+use syn::{parse_macro_input, DeriveInput, Ident, ItemImpl};
 
-// TODO:
-//   - Only box those types that are recursive
-//     - Probably actually use Rc instead of Box, as these will be shared and don't want to do deep
-//       copies all the time.
-//   - Error handling
-//   - docs?
-
-struct Grammar {
-    rules: Punctuated<Rule, Token![;]>,
-}
-
-impl Parse for Grammar {
-    fn parse(input: ParseStream) -> Result<Self> {
-        Ok(Self {
-            rules: Punctuated::<Rule, Token![;]>::parse_terminated(input)?,
-        })
-    }
-}
-
-struct Rule {
-    name: Ident,
-    body: RuleBody,
-}
-
-impl Parse for Rule {
-    fn parse(input: ParseStream) -> Result<Self> {
-        let name = input.parse()?;
-
-        input.parse::<Token![=>]>()?;
-
-        let body = input.parse()?;
-
-        Ok(Self { name, body })
-    }
-}
-
-enum RuleBody {
-    Unique {
-        fields: Punctuated<Field, Token![,]>,
-    },
-    Union {
-        variants: Punctuated<UnionVariant, Token![|]>,
-    },
-}
-
-impl Parse for RuleBody {
-    fn parse(input: ParseStream) -> Result<Self> {
-        if input.peek(Ident) && input.peek2(Token![:]) {
-            // Unique expresions start with a field that is: ``ident: Value``
-            let fields = Punctuated::<Field, Token![,]>::parse_separated_nonempty(input)?;
-
-            Ok(Self::Unique { fields })
-        } else {
-            let variants = Punctuated::<UnionVariant, Token![|]>::parse_separated_nonempty(input)?;
-
-            Ok(Self::Union { variants })
-        }
-    }
-}
-
-enum UnionVariant {
-    /// This variant name and type are the same.
-    ImplicitType(Type),
-
-    /// This variant doesn't really have a type, can be a reserved word, for example.
-    /// To distinguish from ImplicitTypes and Atoms, a '@' needs to be prefixed to the atom.
-    Atom(Ident),
-
-    /// The name of the variant and the type are different, usually for variants whose types
-    /// are primitive or 3p types. It can be desirable to add an alias that makes intent more
-    /// explicit or when 2 different productions of the rule may have the same underlying type, but
-    /// semantically are different.
-    ///
-    /// To Alias a type defined like: `AliasName as UnderlyingType`.
-    Aliased { alias: Ident, ty: Type },
-}
+mod grammar;
+mod model;
+mod nodes;
+mod object;
+mod structural;
+mod validate;
+mod visitors;
 
-impl Parse for UnionVariant {
-    fn parse(input: ParseStream) -> Result<Self> {
-        if input.peek(Token![@]) {
-            // Atoms start with @
-            input.parse::<Token![@]>()?;
+use grammar::Grammar;
+use model::Model;
 
-            let ident = input.parse()?;
-
-            Ok(Self::Atom(ident))
-        } else if input.peek(Ident) && input.peek2(Token![as]) {
-            // as is used to alias stuff
-
-            let alias = input.parse()?;
-
-            input.parse::<Token![as]>()?;
-
-            let ty = input.parse()?;
-
-            Ok(Self::Aliased { alias, ty })
-        } else {
-            let ty = input.parse()?;
-
-            Ok(Self::ImplicitType(ty))
-        }
-    }
-}
-
-struct Field {
-    name: Ident,
-    ty: Type,
-}
-
-impl Parse for Field {
-    fn parse(input: ParseStream) -> Result<Self> {
-        let name: Ident = input.parse()?;
-        input.parse::<Token![:]>()?;
-        let ty: Type = input.parse()?;
-
-        Ok(Field { name, ty })
-    }
-}
+// TODO:
+//   - docs?
 
+/// Generates the AST types for the given rules, every node carries the `span: TextSection` of the
+/// source it was parsed from and implements `Spanned`, both need to be in scope where the macro is
+/// invoked.
+///
+/// Fields can be a rule, a type declared with `extern Token, f64;` before the rules, a path like
+/// `std::string::String`, or any of them wrapped in `Vec` or `Option`. Only the fields that make a
+/// rule contain itself are stored behind the pointer set for the grammar with `#![pointer(...)]`,
+/// see [`grammar::Pointer`].
+///
+/// Besides the visitors, nodes compare ignoring their spans, display as S-expressions and can be
+/// written as JSON, see `structural::render_structural` for what the field types need for that.
 // TODO: rename to AST, this is for defining the AST structure, not the grammar.
 #[proc_macro]
 pub fn grammar(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
-    let Grammar { rules } = parse_macro_input!(input as Grammar);
+    let grammar = parse_macro_input!(input as Grammar);
+
+    if let Err(errors) = validate::validate(&grammar) {
+        return errors.to_compile_error().into();
+    }
 
-    let rendered_structs = rules.iter().map(render_rule_struct);
+    let model = Model::new(&grammar);
 
-    let names = rules.iter().map(|Rule { name, .. }| name).collect();
-    let visitor_definition = define_visitors(names);
+    let nodes = nodes::render_nodes(&model);
+    let visitors = visitors::render_visitors(&model);
+    let structural = structural::render_structural(&model);
 
     let expanded = quote! {
-        #(#rendered_structs)*
+        #nodes
 
-        #visitor_definition
+        #visitors
+
+        #structural
     };
 
     proc_macro::TokenStream::from(expanded)
 }
 
-fn render_rule_struct(Rule { name, body }: &Rule) -> TokenStream {
-    match body {
-        RuleBody::Unique { fields } => {
-            let rendered_fields = fields
-                .iter()
-                .map(|Field { name, ty }| quote! {#name: ::std::boxed::Box<#ty>});
-
-            quote! {
-                #[derive(Clone, Debug)]
-                pub struct #name {
-                    #(pub #rendered_fields),*
-                }
-            }
-        }
-        RuleBody::Union { variants: branches } => {
-            let rendered_variants = branches.iter().map(render_branched_variant);
-
-            quote! {
-                #[derive(Clone, Debug)]
-                pub enum #name {
-                    #(#rendered_variants),*
-                }
-            }
-        }
-    }
+/// Exposes a struct to Lox scripts run by the tree-walk interpreter, see `object::derive_object`
+/// for what becomes a property. Its methods come from [`macro@lox_methods`], a struct without any
+/// implements `LoxMethods` empty.
+///
+/// ```ignore
+/// #[derive(LoxObject)]
+/// struct Rectangle {
+///     width: f64,
+///     height: f64,
+///     #[lox(skip)]
+///     cache: Vec<f64>,
+/// }
+///
+/// #[lox_methods]
+/// impl Rectangle {
+///     #[lox]
+///     fn area(&self) -> NativeResult<f64> {
+///         Ok(self.width * self.height)
+///     }
+/// }
+/// ```
+#[proc_macro_derive(LoxObject, attributes(lox))]
+pub fn lox_object(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    object::derive_object(input)
+        .unwrap_or_else(|error| error.to_compile_error())
+        .into()
 }
 
-fn render_branched_variant(variant: &UnionVariant) -> TokenStream {
-    match variant {
-        UnionVariant::ImplicitType(ty) => quote! { #ty(#ty) },
-        UnionVariant::Atom(atom) => quote! { #atom },
-        UnionVariant::Aliased { alias, ty } => quote! { #alias(#ty) },
+/// Lets Lox scripts call the methods of the impl block marked `#[lox]`, on a struct deriving
+/// [`macro@LoxObject`]. See `object::object_methods`.
+#[proc_macro_attribute]
+pub fn lox_methods(
+    attribute: proc_macro::TokenStream,
+    input: proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+    let attribute = proc_macro2::TokenStream::from(attribute);
+
+    if !attribute.is_empty() {
+        return syn::Error::new_spanned(attribute, "`#[lox_methods]` takes no options")
+            .to_compile_error()
+            .into();
     }
+
+    let input = parse_macro_input!(input as ItemImpl);
+
+    object::object_methods(input)
+        .unwrap_or_else(|error| error.to_compile_error())
+        .into()
 }
 
-fn define_visitors(names: Vec<&Ident>) -> TokenStream {
-    let func_names = names
-        .iter()
-        .map(|name| {
-            Ident::new(
-                &format!("visit_{}", name.to_string().to_case(Case::Snake)),
-                name.span(),
-            )
-        })
-        .collect::<Vec<_>>();
-
-    quote! {
-        pub trait SyntaxVisitor<T> {
-            #(fn #func_names(&mut self, arg: &#names) -> T;)*
-        }
-
-        pub trait Visitable<T> {
-            fn accept(&self, visitor: &mut impl SyntaxVisitor<T>) -> T;
-        }
-
-        #(impl<T> Visitable<T> for #names {
-            fn accept(&self, visitor: &mut impl SyntaxVisitor<T>) -> T {
-                visitor.#func_names(&self)
-            }
-        })*
-    }
+fn method_name(prefix: &str, name: &Ident, suffix: &str) -> Ident {
+    Ident::new(
+        &format!(
+            "{}{}{}",
+            prefix,
+            name.to_string().to_case(Case::Snake),
+            suffix
+        ),
+        name.span(),
+    )
 }
@@ -0,0 +1,294 @@
+use crate::grammar::{Field, Grammar, Pointer, RuleBody, UnionVariant};
+use std::collections::{HashMap, HashSet};
+use syn::{GenericArgument, Ident, PathArguments, Type};
+
+/// The grammar after resolving which types refer to other rules and which of those references
+/// need to go through a pointer to break a cycle.
+pub struct Model<'a> {
+    pub pointer: Pointer,
+    pub rules: Vec<RuleModel<'a>>,
+}
+
+pub struct RuleModel<'a> {
+    pub name: &'a Ident,
+    pub kind: RuleKind<'a>,
+}
+
+pub enum RuleKind<'a> {
+    Struct(Vec<FieldModel<'a>>),
+    Enum(Vec<VariantModel<'a>>),
+}
+
+pub struct FieldModel<'a> {
+    pub name: &'a Ident,
+    /// The declared type, `Vec<T>` and `Option<T>` included.
+    pub ty: &'a Type,
+    pub shape: Shape<'a>,
+    /// Set when the (inner) type is a rule of the grammar.
+    pub rule: Option<&'a Ident>,
+    /// Whether the rule is stored behind the grammar's pointer.
+    pub indirect: bool,
+}
+
+#[derive(Clone, Copy)]
+pub enum Shape<'a> {
+    Plain,
+    Option(&'a Type),
+    Vec(&'a Type),
+}
+
+pub struct VariantModel<'a> {
+    pub name: &'a Ident,
+    pub content: VariantContent<'a>,
+}
+
+pub enum VariantContent<'a> {
+    /// Wraps another rule, which is boxed only for cycles made of unions alone.
+    Rule { rule: &'a Ident, boxed: bool },
+    /// Any other type, the variant carries its own span.
+    Leaf(&'a Type),
+    /// No value at all, the variant only carries its span.
+    Atom,
+}
+
+impl<'a> Model<'a> {
+    pub fn new(grammar: &'a Grammar) -> Self {
+        let rule_names = grammar
+            .rules
+            .iter()
+            .map(|rule| rule.name.to_string())
+            .collect::<HashSet<_>>();
+
+        let mut rules = grammar
+            .rules
+            .iter()
+            .map(|rule| RuleModel {
+                name: &rule.name,
+                kind: match &rule.body {
+                    RuleBody::Unique { fields } => RuleKind::Struct(
+                        fields
+                            .iter()
+                            .map(|field| FieldModel::new(field, &rule_names))
+                            .collect(),
+                    ),
+                    RuleBody::Union { variants } => RuleKind::Enum(
+                        variants
+                            .iter()
+                            .map(|variant| VariantModel::new(variant, &rule_names))
+                            .collect(),
+                    ),
+                },
+            })
+            .collect::<Vec<_>>();
+
+        break_cycles(&mut rules);
+
+        Self {
+            pointer: grammar.pointer,
+            rules,
+        }
+    }
+
+    /// Rules that are stored behind a pointer somewhere in the grammar.
+    pub fn pointed_rules(&self) -> Vec<&'a Ident> {
+        let mut pointed = Vec::new();
+
+        for rule in self.rules.iter() {
+            if let RuleKind::Struct(fields) = &rule.kind {
+                for field in fields.iter().filter(|field| field.indirect) {
+                    let target = field.rule.expect("only rules are indirect");
+
+                    if !pointed.contains(&target) {
+                        pointed.push(target);
+                    }
+                }
+            }
+        }
+
+        pointed
+    }
+}
+
+impl<'a> FieldModel<'a> {
+    fn new(Field { name, ty }: &'a Field, rule_names: &HashSet<String>) -> Self {
+        let shape = shape_of(ty);
+
+        let inner = match shape {
+            Shape::Plain => ty,
+            Shape::Option(inner) | Shape::Vec(inner) => inner,
+        };
+
+        Self {
+            name,
+            ty,
+            shape,
+            rule: rule_ident(inner, rule_names),
+            indirect: false,
+        }
+    }
+
+    /// The type of the field with `Vec` and `Option` removed.
+    pub fn inner_ty(&self) -> &'a Type {
+        match self.shape {
+            Shape::Plain => self.ty,
+            Shape::Option(inner) | Shape::Vec(inner) => inner,
+        }
+    }
+
+    /// Whether the field holds the rule by value, and so it can make the containing type infinite.
+    /// A `Vec` is already an indirection.
+    fn contains_directly(&self) -> Option<&'a Ident> {
+        match self.shape {
+            Shape::Plain | Shape::Option(_) => self.rule,
+            Shape::Vec(_) => None,
+        }
+    }
+}
+
+impl<'a> VariantModel<'a> {
+    fn new(variant: &'a UnionVariant, rule_names: &HashSet<String>) -> Self {
+        let (name, ty) = match variant {
+            UnionVariant::ImplicitType(ty) => (
+                type_ident(ty).expect("validated to be a simple type name"),
+                ty,
+            ),
+            UnionVariant::Aliased { alias, ty } => (alias, ty),
+            UnionVariant::Atom(atom) => {
+                return Self {
+                    name: atom,
+                    content: VariantContent::Atom,
+                }
+            }
+        };
+
+        let content = match rule_ident(ty, rule_names) {
+            Some(rule) => VariantContent::Rule { rule, boxed: false },
+            None => VariantContent::Leaf(ty),
+        };
+
+        Self { name, content }
+    }
+
+    pub fn rule(&self) -> Option<&'a Ident> {
+        match self.content {
+            VariantContent::Rule { rule, .. } => Some(rule),
+            _ => None,
+        }
+    }
+}
+
+/// Marks the fields that refer to a rule containing the field's own rule as indirect, so the
+/// generated types have a finite size. Unions are only boxed when they form a cycle by themselves,
+/// without any struct field in between.
+fn break_cycles(rules: &mut [RuleModel]) {
+    // Edges go from a rule to the rules it holds by value.
+    let edges = |rules: &[RuleModel]| {
+        let mut edges = HashMap::<String, Vec<String>>::new();
+
+        for rule in rules.iter() {
+            let targets = edges.entry(rule.name.to_string()).or_default();
+
+            match &rule.kind {
+                RuleKind::Struct(fields) => targets.extend(
+                    fields
+                        .iter()
+                        .filter(|field| !field.indirect)
+                        .filter_map(FieldModel::contains_directly)
+                        .map(Ident::to_string),
+                ),
+                RuleKind::Enum(variants) => targets.extend(
+                    variants
+                        .iter()
+                        .filter_map(VariantModel::rule)
+                        .map(Ident::to_string),
+                ),
+            }
+        }
+
+        edges
+    };
+
+    let all_edges = edges(rules);
+
+    for rule in rules.iter_mut() {
+        let name = rule.name.to_string();
+
+        if let RuleKind::Struct(fields) = &mut rule.kind {
+            for field in fields.iter_mut() {
+                if let Some(target) = field.contains_directly() {
+                    field.indirect = reaches(&all_edges, &target.to_string(), &name);
+                }
+            }
+        }
+    }
+
+    let union_edges = edges(rules);
+
+    for rule in rules.iter_mut() {
+        let name = rule.name.to_string();
+
+        if let RuleKind::Enum(variants) = &mut rule.kind {
+            for variant in variants.iter_mut() {
+                if let VariantContent::Rule { rule, boxed } = &mut variant.content {
+                    *boxed = reaches(&union_edges, &rule.to_string(), &name);
+                }
+            }
+        }
+    }
+}
+
+fn reaches(edges: &HashMap<String, Vec<String>>, from: &str, to: &str) -> bool {
+    let mut pending = vec![from];
+    let mut seen = HashSet::new();
+
+    while let Some(current) = pending.pop() {
+        if current == to {
+            return true;
+        }
+
+        if seen.insert(current) {
+            pending.extend(edges.get(current).into_iter().flatten().map(String::as_str));
+        }
+    }
+
+    false
+}
+
+fn shape_of(ty: &Type) -> Shape<'_> {
+    let Type::Path(path) = ty else {
+        return Shape::Plain;
+    };
+
+    let Some(segment) = path.path.segments.last() else {
+        return Shape::Plain;
+    };
+
+    let PathArguments::AngleBracketed(arguments) = &segment.arguments else {
+        return Shape::Plain;
+    };
+
+    let inner = match arguments.args.first() {
+        Some(GenericArgument::Type(inner)) if arguments.args.len() == 1 => inner,
+        _ => return Shape::Plain,
+    };
+
+    if segment.ident == "Option" {
+        Shape::Option(inner)
+    } else if segment.ident == "Vec" {
+        Shape::Vec(inner)
+    } else {
+        Shape::Plain
+    }
+}
+
+pub fn type_ident(ty: &Type) -> Option<&Ident> {
+    match ty {
+        Type::Path(path) => path.path.get_ident(),
+        _ => None,
+    }
+}
+
+/// Name of the rule the type refers to, if any.
+fn rule_ident<'a>(ty: &'a Type, rule_names: &HashSet<String>) -> Option<&'a Ident> {
+    type_ident(ty).filter(|ident| rule_names.contains(&ident.to_string()))
+}
@@ -0,0 +1,312 @@
+use crate::grammar::Pointer;
+use crate::model::{FieldModel, Model, RuleKind, RuleModel, Shape, VariantContent, VariantModel};
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Ident, Type};
+
+pub fn render_nodes(model: &Model) -> TokenStream {
+    let nodes = model
+        .rules
+        .iter()
+        .map(|rule| render_rule(model.pointer, rule));
+
+    let arena = match model.pointer {
+        Pointer::Arena => render_arena(&model.pointed_rules()),
+        Pointer::Box | Pointer::Rc => quote! {},
+    };
+
+    quote! {
+        #(#nodes)*
+
+        #arena
+    }
+}
+
+fn render_rule(pointer: Pointer, RuleModel { name, kind }: &RuleModel) -> TokenStream {
+    match kind {
+        RuleKind::Struct(fields) => render_struct(pointer, name, fields),
+        RuleKind::Enum(variants) => render_enum(name, variants),
+    }
+}
+
+fn render_struct(pointer: Pointer, name: &Ident, fields: &[FieldModel]) -> TokenStream {
+    let field_names = fields.iter().map(|field| field.name).collect::<Vec<_>>();
+    let field_types = fields.iter().map(|field| field.ty).collect::<Vec<_>>();
+    let stored_types = fields.iter().map(|field| stored_type(pointer, field));
+
+    let spans = fields
+        .iter()
+        .map(|FieldModel { name, shape, .. }| match shape {
+            Shape::Plain => quote! { ::std::iter::once(Spanned::span(&#name)) },
+            Shape::Option(_) | Shape::Vec(_) => quote! { #name.iter().map(Spanned::span) },
+        });
+
+    let stored_values = fields.iter().map(|field| {
+        let name = field.name;
+
+        match (field.indirect, field.shape) {
+            (false, _) => quote! { #name },
+            (true, Shape::Option(_)) => {
+                let wrapped = wrap(pointer, quote! { inner });
+
+                quote! { #name.map(|inner| #wrapped) }
+            }
+            (true, _) => wrap(pointer, quote! { #name }),
+        }
+    });
+
+    let arena_param = if pointer == Pointer::Arena && fields.iter().any(|field| field.indirect) {
+        quote! { arena: &mut SyntaxArena, }
+    } else {
+        quote! {}
+    };
+
+    quote! {
+        #[derive(Clone, Debug)]
+        pub struct #name {
+            #(pub #field_names: #stored_types,)*
+            pub span: TextSection,
+        }
+
+        impl #name {
+            /// Builds the node spanning from the start of its first field to the end of its last
+            /// one.
+            pub fn new(#arena_param #(#field_names: #field_types),*) -> Self {
+                let span = TextSection::covering(
+                    ::std::iter::empty()#(.chain(#spans))*
+                );
+
+                Self {
+                    #(#field_names: #stored_values,)*
+                    span,
+                }
+            }
+        }
+
+        impl Spanned for #name {
+            fn span(&self) -> TextSection {
+                self.span
+            }
+        }
+    }
+}
+
+/// Variants wrapping another rule use that rule's span, the rest carry their own span as the last
+/// element of the variant.
+fn render_enum(name: &Ident, variants: &[VariantModel]) -> TokenStream {
+    let rendered_variants = variants
+        .iter()
+        .map(|VariantModel { name, content }| match content {
+            VariantContent::Rule { rule, boxed: false } => quote! { #name(#rule) },
+            VariantContent::Rule { rule, boxed: true } => {
+                quote! { #name(::std::boxed::Box<#rule>) }
+            }
+            VariantContent::Leaf(ty) => quote! { #name(#ty, TextSection) },
+            VariantContent::Atom => quote! { #name(TextSection) },
+        });
+
+    let span_arms = variants.iter().map(
+        |VariantModel {
+             name: variant,
+             content,
+         }| match content {
+            VariantContent::Rule { .. } => quote! { #name::#variant(inner) => inner.span() },
+            VariantContent::Leaf(_) => quote! { #name::#variant(_, span) => *span },
+            VariantContent::Atom => quote! { #name::#variant(span) => *span },
+        },
+    );
+
+    quote! {
+        #[derive(Clone, Debug)]
+        pub enum #name {
+            #(#rendered_variants),*
+        }
+
+        impl Spanned for #name {
+            fn span(&self) -> TextSection {
+                match self {
+                    #(#span_arms),*
+                }
+            }
+        }
+    }
+}
+
+fn stored_type(pointer: Pointer, field: &FieldModel) -> TokenStream {
+    let ty = field.ty;
+
+    if !field.indirect {
+        return quote! { #ty };
+    }
+
+    let pointed = pointer_type(pointer, field.inner_ty());
+
+    match field.shape {
+        Shape::Option(_) => quote! { ::std::option::Option<#pointed> },
+        Shape::Plain | Shape::Vec(_) => pointed,
+    }
+}
+
+fn pointer_type(pointer: Pointer, ty: &Type) -> TokenStream {
+    match pointer {
+        Pointer::Box => quote! { ::std::boxed::Box<#ty> },
+        Pointer::Rc => quote! { ::std::rc::Rc<#ty> },
+        Pointer::Arena => quote! { NodeId<#ty> },
+    }
+}
+
+/// Stores the node `value` behind the pointer.
+pub fn wrap(pointer: Pointer, value: TokenStream) -> TokenStream {
+    match pointer {
+        Pointer::Box => quote! { ::std::boxed::Box::new(#value) },
+        Pointer::Rc => quote! { ::std::rc::Rc::new(#value) },
+        Pointer::Arena => quote! { arena.alloc(#value) },
+    }
+}
+
+fn render_arena(rules: &[&Ident]) -> TokenStream {
+    let fields = rules
+        .iter()
+        .map(|rule| crate::method_name("", rule, ""))
+        .collect::<Vec<_>>();
+
+    quote! {
+        /// Index of a node stored in a [`SyntaxArena`].
+        pub struct NodeId<T> {
+            index: usize,
+            node: ::std::marker::PhantomData<fn() -> T>,
+        }
+
+        impl<T> NodeId<T> {
+            pub fn index(&self) -> usize {
+                self.index
+            }
+        }
+
+        impl<T> ::std::clone::Clone for NodeId<T> {
+            fn clone(&self) -> Self {
+                *self
+            }
+        }
+
+        impl<T> ::std::marker::Copy for NodeId<T> {}
+
+        impl<T> ::std::cmp::PartialEq for NodeId<T> {
+            fn eq(&self, other: &Self) -> bool {
+                self.index == other.index
+            }
+        }
+
+        impl<T> ::std::cmp::Eq for NodeId<T> {}
+
+        impl<T> ::std::hash::Hash for NodeId<T> {
+            fn hash<H: ::std::hash::Hasher>(&self, state: &mut H) {
+                self.index.hash(state)
+            }
+        }
+
+        impl<T> ::std::fmt::Debug for NodeId<T> {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                write!(f, "NodeId({})", self.index)
+            }
+        }
+
+        /// Owns every node that is referred to through a [`NodeId`].
+        ///
+        /// Nodes are taken out of the arena while a `SyntaxVisitorMut` or a `Fold` works on them,
+        /// looking them up in the meantime panics.
+        #[derive(Clone, Debug, Default)]
+        pub struct SyntaxArena {
+            #(#fields: ::std::vec::Vec<::std::option::Option<#rules>>,)*
+        }
+
+        pub trait ArenaStorage<T> {
+            fn storage(&self) -> &::std::vec::Vec<::std::option::Option<T>>;
+            fn storage_mut(&mut self) -> &mut ::std::vec::Vec<::std::option::Option<T>>;
+        }
+
+        #(impl ArenaStorage<#rules> for SyntaxArena {
+            fn storage(&self) -> &::std::vec::Vec<::std::option::Option<#rules>> {
+                &self.#fields
+            }
+
+            fn storage_mut(&mut self) -> &mut ::std::vec::Vec<::std::option::Option<#rules>> {
+                &mut self.#fields
+            }
+        })*
+
+        impl SyntaxArena {
+            pub fn new() -> Self {
+                Self::default()
+            }
+
+            pub fn alloc<T>(&mut self, node: T) -> NodeId<T>
+            where
+                Self: ArenaStorage<T>,
+            {
+                let storage = self.storage_mut();
+                storage.push(::std::option::Option::Some(node));
+
+                NodeId {
+                    index: storage.len() - 1,
+                    node: ::std::marker::PhantomData,
+                }
+            }
+
+            pub fn get<T>(&self, id: NodeId<T>) -> &T
+            where
+                Self: ArenaStorage<T>,
+            {
+                self.storage()[id.index]
+                    .as_ref()
+                    .expect("node taken out of the arena")
+            }
+
+            pub fn get_mut<T>(&mut self, id: NodeId<T>) -> &mut T
+            where
+                Self: ArenaStorage<T>,
+            {
+                self.storage_mut()[id.index]
+                    .as_mut()
+                    .expect("node taken out of the arena")
+            }
+
+            /// Moves the node out of the arena until it is [`restore`](Self::restore)d.
+            pub fn take<T>(&mut self, id: NodeId<T>) -> T
+            where
+                Self: ArenaStorage<T>,
+            {
+                self.storage_mut()[id.index]
+                    .take()
+                    .expect("node taken out of the arena")
+            }
+
+            pub fn restore<T>(&mut self, id: NodeId<T>, node: T)
+            where
+                Self: ArenaStorage<T>,
+            {
+                self.storage_mut()[id.index] = ::std::option::Option::Some(node);
+            }
+        }
+
+        impl<T> ::std::ops::Index<NodeId<T>> for SyntaxArena
+        where
+            SyntaxArena: ArenaStorage<T>,
+        {
+            type Output = T;
+
+            fn index(&self, id: NodeId<T>) -> &T {
+                self.get(id)
+            }
+        }
+
+        impl<T> ::std::ops::IndexMut<NodeId<T>> for SyntaxArena
+        where
+            SyntaxArena: ArenaStorage<T>,
+        {
+            fn index_mut(&mut self, id: NodeId<T>) -> &mut T {
+                self.get_mut(id)
+            }
+        }
+    }
+}
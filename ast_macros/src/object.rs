@@ -0,0 +1,295 @@
+use proc_macro2::TokenStream;
+use quote::{quote, quote_spanned};
+use syn::{
+    Attribute, Data, DeriveInput, Error, Fields, FnArg, Ident, ImplItem, ItemImpl, LitStr, Meta,
+    NestedMeta, Result, Type,
+};
+
+/// A field scripts can see.
+struct Property {
+    ident: Ident,
+    readonly: bool,
+}
+
+/// Implements `jrlox::interpreter::LoxObject` for the struct, and turns it into a `Value` with
+/// `From`. Every named field is a property unless marked `#[lox(skip)]`, and `#[lox(readonly)]`
+/// ones can't be set. Methods come from [`object_methods`], the property names are left in a
+/// hidden `__LOX_PROPERTIES` constant so it can check they don't collide.
+pub fn derive_object(input: DeriveInput) -> Result<TokenStream> {
+    let name = &input.ident;
+    let class_name = LitStr::new(&name.to_string(), name.span());
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => fields.named.iter().collect(),
+            Fields::Unit => Vec::new(),
+            Fields::Unnamed(_) => {
+                return Err(Error::new_spanned(
+                    name,
+                    "only structs with named fields can be Lox objects",
+                ))
+            }
+        },
+        _ => return Err(Error::new_spanned(name, "only structs can be Lox objects")),
+    };
+
+    let mut properties = Vec::new();
+
+    for field in fields {
+        let ident = field.ident.clone().expect("named fields have a name");
+        let mut property = Some(Property {
+            ident,
+            readonly: false,
+        });
+
+        for option in lox_options(&field.attrs)? {
+            match option {
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("skip") => property = None,
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("readonly") => {
+                    if let Some(property) = property.as_mut() {
+                        property.readonly = true;
+                    }
+                }
+                other => {
+                    return Err(Error::new_spanned(
+                        other,
+                        "unknown field option, expected `skip` or `readonly`",
+                    ))
+                }
+            }
+        }
+
+        properties.extend(property);
+    }
+
+    if let Some(option) = lox_options(&input.attrs)?.into_iter().next() {
+        return Err(Error::new_spanned(
+            option,
+            "unknown struct option, methods are marked `#[lox]` in a `#[lox_methods]` impl block",
+        ));
+    }
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let property_arms = properties.iter().map(|Property { ident, .. }| {
+        let key = LitStr::new(&ident.to_string(), ident.span());
+
+        quote! {
+            #key => ::std::option::Option::Some(::jrlox::interpreter::IntoValue::into_value(
+                ::std::clone::Clone::clone(&self.#ident),
+            )),
+        }
+    });
+
+    let set_arms = properties.iter().map(|Property { ident, readonly }| {
+        let key = LitStr::new(&ident.to_string(), ident.span());
+
+        if *readonly {
+            quote! {
+                #key => ::std::option::Option::Some(::std::result::Result::Err(
+                    ::std::format!("Can't set read-only property '{}'", #key),
+                )),
+            }
+        } else {
+            quote! {
+                #key => ::std::option::Option::Some(
+                    ::jrlox::interpreter::FromValue::from_value(value).map(|value| self.#ident = value),
+                ),
+            }
+        }
+    });
+
+    let property_names = properties
+        .iter()
+        .map(|Property { ident, .. }| LitStr::new(&ident.to_string(), ident.span()));
+
+    Ok(quote! {
+        #[allow(unused_variables)]
+        impl #impl_generics ::jrlox::interpreter::LoxObject for #name #ty_generics #where_clause {
+            fn class_name(&self) -> &'static str {
+                #class_name
+            }
+
+            fn property(&self, name: &str) -> ::std::option::Option<::jrlox::interpreter::Value> {
+                match name {
+                    #(#property_arms)*
+                    _ => ::std::option::Option::None,
+                }
+            }
+
+            fn set_property(
+                &mut self,
+                name: &str,
+                value: &::jrlox::interpreter::Value,
+            ) -> ::std::option::Option<::jrlox::interpreter::NativeResult<()>> {
+                match name {
+                    #(#set_arms)*
+                    _ => ::std::option::Option::None,
+                }
+            }
+        }
+
+        impl #impl_generics #name #ty_generics #where_clause {
+            #[doc(hidden)]
+            pub const __LOX_PROPERTIES: &'static [&'static str] = &[#(#property_names),*];
+        }
+
+        impl #impl_generics ::std::convert::From<#name #ty_generics> for ::jrlox::interpreter::Value #where_clause {
+            fn from(object: #name #ty_generics) -> Self {
+                ::jrlox::interpreter::Value::Object(::jrlox::interpreter::Object::new(object))
+            }
+        }
+    })
+}
+
+/// Implements `jrlox::interpreter::LoxMethods` with the methods marked `#[lox]` in the impl block,
+/// their arguments and result converted as for natives. They must borrow `self`, and a method
+/// named like a property of the derived `LoxObject` fails the build.
+pub fn object_methods(mut input: ItemImpl) -> Result<TokenStream> {
+    let mut methods = Vec::new();
+
+    for item in input.items.iter_mut() {
+        let ImplItem::Method(method) = item else {
+            continue;
+        };
+
+        let marked = method
+            .attrs
+            .iter()
+            .filter(|attribute| attribute.path.is_ident("lox"))
+            .collect::<Vec<_>>();
+
+        let Some(attribute) = marked.first() else {
+            continue;
+        };
+
+        if !attribute.tokens.is_empty() {
+            return Err(Error::new_spanned(
+                attribute,
+                "methods take no options, expected `#[lox]`",
+            ));
+        }
+
+        match method.sig.inputs.first() {
+            Some(FnArg::Receiver(receiver)) if receiver.reference.is_some() => (),
+            _ => {
+                return Err(Error::new_spanned(
+                    &method.sig.ident,
+                    "Lox methods take `&self` or `&mut self`",
+                ))
+            }
+        }
+
+        methods.push(method.sig.ident.clone());
+        method
+            .attrs
+            .retain(|attribute| !attribute.path.is_ident("lox"));
+    }
+
+    let self_ty = &input.self_ty;
+    let (impl_generics, _, where_clause) = input.generics.split_for_impl();
+
+    let method_keys = methods
+        .iter()
+        .map(|method| LitStr::new(&method.to_string(), method.span()))
+        .collect::<Vec<_>>();
+
+    let class_name = match &*input.self_ty {
+        Type::Path(path) => path
+            .path
+            .segments
+            .last()
+            .map(|segment| segment.ident.to_string()),
+        _ => None,
+    }
+    .unwrap_or_else(|| quote!(#self_ty).to_string());
+    let collisions = methods.iter().zip(method_keys.iter()).map(|(method, key)| {
+        let message = LitStr::new(
+            &format!(
+                "`{}` is both a property and a method of `{}`",
+                method, class_name
+            ),
+            method.span(),
+        );
+
+        quote_spanned! {method.span()=>
+            if ::jrlox::interpreter::has_name(<#self_ty>::__LOX_PROPERTIES, #key) {
+                ::std::panic!(#message);
+            }
+        }
+    });
+
+    // Generic objects are only checked once they are used with some type.
+    let check = if input.generics.params.is_empty() {
+        quote! {
+            const _: () = { #(#collisions)* };
+        }
+    } else {
+        quote! {
+            impl #impl_generics #self_ty #where_clause {
+                const __LOX_METHODS_CHECKED: () = { #(#collisions)* };
+            }
+        }
+    };
+    let checked =
+        (!input.generics.params.is_empty()).then(|| quote!(let () = Self::__LOX_METHODS_CHECKED;));
+
+    Ok(quote! {
+        #input
+
+        #check
+
+        #[allow(unused_variables)]
+        impl #impl_generics ::jrlox::interpreter::LoxMethods for #self_ty #where_clause {
+            fn method_arity(&self, name: &str) -> ::std::option::Option<usize> {
+                #checked
+
+                match name {
+                    #(#method_keys => ::std::option::Option::Some(
+                        ::jrlox::interpreter::IntoMethod::<Self, _>::arity(&Self::#methods),
+                    ),)*
+                    _ => ::std::option::Option::None,
+                }
+            }
+
+            fn call_method(
+                &mut self,
+                name: &str,
+                arguments: &[::jrlox::interpreter::Value],
+            ) -> ::jrlox::interpreter::NativeResult<::jrlox::interpreter::Value> {
+                match name {
+                    #(#method_keys => ::jrlox::interpreter::IntoMethod::<Self, _>::call(
+                        &Self::#methods,
+                        self,
+                        arguments,
+                    ),)*
+                    _ => ::std::result::Result::Err(
+                        ::std::format!("Undefined property '{}'", name),
+                    ),
+                }
+            }
+        }
+    })
+}
+
+/// Options inside every `#[lox(...)]` attribute.
+fn lox_options(attributes: &[Attribute]) -> Result<Vec<NestedMeta>> {
+    let mut options = Vec::new();
+
+    for attribute in attributes
+        .iter()
+        .filter(|attribute| attribute.path.is_ident("lox"))
+    {
+        match attribute.parse_meta()? {
+            Meta::List(list) => options.extend(list.nested),
+            other => {
+                return Err(Error::new_spanned(
+                    other,
+                    "expected options, like `#[lox(skip)]`",
+                ))
+            }
+        }
+    }
+
+    Ok(options)
+}
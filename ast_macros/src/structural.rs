@@ -0,0 +1,440 @@
+use crate::grammar::Pointer;
+use crate::method_name;
+use crate::model::{FieldModel, Model, RuleKind, VariantContent, VariantModel};
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::Ident;
+
+/// Generic views of the tree, through a trait each so they go through fields of any type:
+///   - `SyntaxEq`, equality that ignores spans, which is also the `PartialEq` of the nodes.
+///   - `ToSexp`, a stable S-expression, which is also the `Display` of the nodes.
+///   - `ToJson`, a JSON document of the tree, spans included.
+///
+/// Containers and the primitive types are covered, the rest of the types used in fields need to
+/// implement the three of them by hand, `TextSection` only `ToJson`.
+///
+/// Nodes in an arena can't be reached without it, so arena grammars get none of them.
+pub fn render_structural(model: &Model) -> TokenStream {
+    if model.pointer == Pointer::Arena {
+        return quote! {};
+    }
+
+    let containers = render_containers();
+
+    let rules = model.rules.iter().map(|rule| {
+        let name = rule.name;
+
+        let (eq, sexp, json) = match &rule.kind {
+            RuleKind::Struct(fields) => render_struct(name, fields),
+            RuleKind::Enum(variants) => render_enum(name, variants),
+        };
+
+        quote! {
+            impl SyntaxEq for #name {
+                fn syntax_eq(&self, other: &Self) -> bool {
+                    #eq
+                }
+            }
+
+            impl ::std::cmp::PartialEq for #name {
+                fn eq(&self, other: &Self) -> bool {
+                    self.syntax_eq(other)
+                }
+            }
+
+            impl ToSexp for #name {
+                fn write_sexp(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                    #sexp
+                }
+            }
+
+            impl ::std::fmt::Display for #name {
+                fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                    self.write_sexp(f)
+                }
+            }
+
+            impl ToJson for #name {
+                fn write_json(&self, out: &mut ::std::string::String) {
+                    #json
+                }
+            }
+        }
+    });
+
+    quote! {
+        /// Equality of the syntax alone, spans are ignored.
+        pub trait SyntaxEq {
+            fn syntax_eq(&self, other: &Self) -> bool;
+        }
+
+        /// S-expression form, `(binary 1 + 2)`. Lists are written in brackets, missing optional
+        /// values as `_` and strings quoted and escaped as in JSON.
+        pub trait ToSexp {
+            fn write_sexp(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result;
+
+            /// Fields holding this value are left out, for what the node type already tells, like
+            /// punctuation.
+            fn skip_sexp(&self) -> bool {
+                false
+            }
+        }
+
+        /// JSON form, nodes are objects with their `type`, `span` and fields.
+        pub trait ToJson {
+            fn write_json(&self, out: &mut ::std::string::String);
+
+            fn to_json(&self) -> ::std::string::String {
+                let mut out = ::std::string::String::new();
+                self.write_json(&mut out);
+
+                out
+            }
+        }
+
+        /// Quotes and escapes the text as a JSON string.
+        pub fn write_json_string(text: &str, out: &mut ::std::string::String) {
+            out.push('"');
+
+            for c in text.chars() {
+                match c {
+                    '"' => out.push_str("\\\""),
+                    '\\' => out.push_str("\\\\"),
+                    '\n' => out.push_str("\\n"),
+                    '\r' => out.push_str("\\r"),
+                    '\t' => out.push_str("\\t"),
+                    c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+                    c => out.push(c),
+                }
+            }
+
+            out.push('"');
+        }
+
+        #(#rules)*
+
+        #containers
+    }
+}
+
+fn render_struct(name: &Ident, fields: &[FieldModel]) -> (TokenStream, TokenStream, TokenStream) {
+    let field_names = fields.iter().map(|field| field.name).collect::<Vec<_>>();
+    let sexp_name = method_name("", name, "").to_string();
+    let json_head = format!("{{\"type\":\"{}\",\"span\":", name);
+    let json_keys = field_names
+        .iter()
+        .map(|field| format!(",\"{}\":", field))
+        .collect::<Vec<_>>();
+
+    let eq = quote! {
+        true #(&& SyntaxEq::syntax_eq(&self.#field_names, &other.#field_names))*
+    };
+
+    let sexp = quote! {
+        f.write_str("(")?;
+        f.write_str(#sexp_name)?;
+        #(
+            if !ToSexp::skip_sexp(&self.#field_names) {
+                f.write_str(" ")?;
+                ToSexp::write_sexp(&self.#field_names, f)?;
+            }
+        )*
+        f.write_str(")")
+    };
+
+    let json = quote! {
+        out.push_str(#json_head);
+        ToJson::write_json(&self.span, out);
+        #(
+            out.push_str(#json_keys);
+            ToJson::write_json(&self.#field_names, out);
+        )*
+        out.push('}');
+    };
+
+    (eq, sexp, json)
+}
+
+/// Variants wrapping a rule are written as the rule itself, the rest as their value or name.
+fn render_enum(name: &Ident, variants: &[VariantModel]) -> (TokenStream, TokenStream, TokenStream) {
+    let eq_arms = variants.iter().map(
+        |VariantModel {
+             name: variant,
+             content,
+         }| match content {
+            VariantContent::Rule { .. } => {
+                quote! { (#name::#variant(left), #name::#variant(right)) => left.syntax_eq(right) }
+            }
+            VariantContent::Leaf(_) => quote! {
+                (#name::#variant(left, _), #name::#variant(right, _)) => left.syntax_eq(right)
+            },
+            VariantContent::Atom => quote! { (#name::#variant(_), #name::#variant(_)) => true },
+        },
+    );
+
+    let sexp_arms = variants.iter().map(
+        |VariantModel {
+             name: variant,
+             content,
+         }| match content {
+            VariantContent::Rule { .. } => quote! { #name::#variant(inner) => inner.write_sexp(f) },
+            VariantContent::Leaf(_) => quote! { #name::#variant(value, _) => value.write_sexp(f) },
+            VariantContent::Atom => {
+                let atom = method_name("", variant, "").to_string();
+
+                quote! { #name::#variant(_) => f.write_str(#atom) }
+            }
+        },
+    );
+
+    let json_arms = variants.iter().map(
+        |VariantModel {
+             name: variant,
+             content,
+         }| {
+            let json_head = format!("{{\"type\":\"{}\",\"span\":", variant);
+
+            match content {
+                VariantContent::Rule { .. } => {
+                    quote! { #name::#variant(inner) => inner.write_json(out) }
+                }
+                VariantContent::Leaf(_) => quote! {
+                    #name::#variant(value, span) => {
+                        out.push_str(#json_head);
+                        span.write_json(out);
+                        out.push_str(",\"value\":");
+                        value.write_json(out);
+                        out.push('}');
+                    }
+                },
+                VariantContent::Atom => quote! {
+                    #name::#variant(span) => {
+                        out.push_str(#json_head);
+                        span.write_json(out);
+                        out.push('}');
+                    }
+                },
+            }
+        },
+    );
+
+    let eq = quote! {
+        #[allow(unreachable_patterns)]
+        match (self, other) {
+            #(#eq_arms,)*
+            _ => false,
+        }
+    };
+
+    let sexp = quote! {
+        match self {
+            #(#sexp_arms,)*
+        }
+    };
+
+    let json = quote! {
+        match self {
+            #(#json_arms,)*
+        }
+    };
+
+    (eq, sexp, json)
+}
+
+/// Impls for the types that can hold nodes and for the primitive types.
+fn render_containers() -> TokenStream {
+    let pointers = [quote! { ::std::boxed::Box<T> }, quote! { ::std::rc::Rc<T> }];
+
+    let floats = [quote! { f32 }, quote! { f64 }];
+
+    let integers = [
+        quote! { i8 },
+        quote! { i16 },
+        quote! { i32 },
+        quote! { i64 },
+        quote! { isize },
+        quote! { u8 },
+        quote! { u16 },
+        quote! { u32 },
+        quote! { u64 },
+        quote! { usize },
+    ];
+
+    quote! {
+        #(
+            impl<T: SyntaxEq> SyntaxEq for #pointers {
+                fn syntax_eq(&self, other: &Self) -> bool {
+                    T::syntax_eq(self, other)
+                }
+            }
+
+            impl<T: ToSexp> ToSexp for #pointers {
+                fn write_sexp(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                    T::write_sexp(self, f)
+                }
+
+                fn skip_sexp(&self) -> bool {
+                    T::skip_sexp(self)
+                }
+            }
+
+            impl<T: ToJson> ToJson for #pointers {
+                fn write_json(&self, out: &mut ::std::string::String) {
+                    T::write_json(self, out)
+                }
+            }
+        )*
+
+        impl<T: SyntaxEq> SyntaxEq for ::std::vec::Vec<T> {
+            fn syntax_eq(&self, other: &Self) -> bool {
+                self.len() == other.len()
+                    && self.iter().zip(other.iter()).all(|(left, right)| left.syntax_eq(right))
+            }
+        }
+
+        impl<T: ToSexp> ToSexp for ::std::vec::Vec<T> {
+            fn write_sexp(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                f.write_str("[")?;
+
+                for (i, item) in self.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(" ")?;
+                    }
+
+                    item.write_sexp(f)?;
+                }
+
+                f.write_str("]")
+            }
+        }
+
+        impl<T: ToJson> ToJson for ::std::vec::Vec<T> {
+            fn write_json(&self, out: &mut ::std::string::String) {
+                out.push('[');
+
+                for (i, item) in self.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+
+                    item.write_json(out);
+                }
+
+                out.push(']');
+            }
+        }
+
+        impl<T: SyntaxEq> SyntaxEq for ::std::option::Option<T> {
+            fn syntax_eq(&self, other: &Self) -> bool {
+                match (self, other) {
+                    (::std::option::Option::Some(left), ::std::option::Option::Some(right)) => {
+                        left.syntax_eq(right)
+                    }
+                    (left, right) => left.is_none() && right.is_none(),
+                }
+            }
+        }
+
+        impl<T: ToSexp> ToSexp for ::std::option::Option<T> {
+            fn write_sexp(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                match self {
+                    ::std::option::Option::Some(value) => value.write_sexp(f),
+                    ::std::option::Option::None => f.write_str("_"),
+                }
+            }
+        }
+
+        impl<T: ToJson> ToJson for ::std::option::Option<T> {
+            fn write_json(&self, out: &mut ::std::string::String) {
+                match self {
+                    ::std::option::Option::Some(value) => value.write_json(out),
+                    ::std::option::Option::None => out.push_str("null"),
+                }
+            }
+        }
+
+        #(
+            impl SyntaxEq for #integers {
+                fn syntax_eq(&self, other: &Self) -> bool {
+                    self == other
+                }
+            }
+
+            impl ToSexp for #integers {
+                fn write_sexp(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                    write!(f, "{}", self)
+                }
+            }
+
+            impl ToJson for #integers {
+                fn write_json(&self, out: &mut ::std::string::String) {
+                    out.push_str(&self.to_string())
+                }
+            }
+        )*
+
+        #(
+            impl SyntaxEq for #floats {
+                fn syntax_eq(&self, other: &Self) -> bool {
+                    self == other
+                }
+            }
+
+            impl ToSexp for #floats {
+                fn write_sexp(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                    write!(f, "{}", self)
+                }
+            }
+
+            /// JSON has no infinities nor NaN.
+            impl ToJson for #floats {
+                fn write_json(&self, out: &mut ::std::string::String) {
+                    if self.is_finite() {
+                        out.push_str(&self.to_string())
+                    } else {
+                        out.push_str("null")
+                    }
+                }
+            }
+        )*
+
+        impl SyntaxEq for bool {
+            fn syntax_eq(&self, other: &Self) -> bool {
+                self == other
+            }
+        }
+
+        impl ToSexp for bool {
+            fn write_sexp(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                write!(f, "{}", self)
+            }
+        }
+
+        impl ToJson for bool {
+            fn write_json(&self, out: &mut ::std::string::String) {
+                out.push_str(&self.to_string())
+            }
+        }
+
+        impl SyntaxEq for ::std::string::String {
+            fn syntax_eq(&self, other: &Self) -> bool {
+                self == other
+            }
+        }
+
+        impl ToSexp for ::std::string::String {
+            fn write_sexp(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                let mut quoted = ::std::string::String::new();
+                write_json_string(self, &mut quoted);
+
+                f.write_str(&quoted)
+            }
+        }
+
+        impl ToJson for ::std::string::String {
+            fn write_json(&self, out: &mut ::std::string::String) {
+                write_json_string(self, out)
+            }
+        }
+    }
+}
@@ -0,0 +1,186 @@
+use crate::grammar::{Field, Grammar, RuleBody, UnionVariant};
+use crate::model::type_ident;
+use std::collections::HashSet;
+use syn::{Error, GenericArgument, Ident, PathArguments, Result, Type};
+
+/// Checks the grammar before generating anything, so mistakes are reported at the rule or field
+/// that has them instead of somewhere in the generated code. Every problem found is reported.
+pub fn validate(grammar: &Grammar) -> Result<()> {
+    let mut errors = Vec::new();
+
+    let rules = grammar
+        .rules
+        .iter()
+        .map(|rule| &rule.name)
+        .collect::<Vec<_>>();
+
+    for name in grammar.externs.iter() {
+        if rules.contains(&name) {
+            errors.push(Error::new_spanned(
+                name,
+                format!("`{}` is both a rule and an external type", name),
+            ));
+        }
+    }
+
+    let known = rules
+        .iter()
+        .copied()
+        .chain(grammar.externs.iter())
+        .collect::<Vec<_>>();
+
+    let mut defined = HashSet::new();
+
+    for rule in grammar.rules.iter() {
+        if !defined.insert(rule.name.to_string()) {
+            errors.push(Error::new_spanned(
+                &rule.name,
+                format!("rule `{}` is defined more than once", rule.name),
+            ));
+        }
+
+        match &rule.body {
+            RuleBody::Unique { fields } => {
+                let mut names = HashSet::new();
+
+                for Field { name, ty } in fields.iter() {
+                    if name == "span" {
+                        errors.push(Error::new_spanned(
+                            name,
+                            "`span` is reserved, every node already has a `span: TextSection` field",
+                        ));
+                    } else if !names.insert(name.to_string()) {
+                        errors.push(Error::new_spanned(
+                            name,
+                            format!("field `{}` appears more than once in `{}`", name, rule.name),
+                        ));
+                    }
+
+                    errors.extend(undefined_types(ty, &known, &rules));
+                }
+            }
+            RuleBody::Union { variants } => {
+                let mut names = HashSet::new();
+
+                for variant in variants.iter() {
+                    let (name, ty) = match variant {
+                        UnionVariant::ImplicitType(ty) => match type_ident(ty) {
+                            Some(name) => (name, Some(ty)),
+                            None => {
+                                errors.push(Error::new_spanned(
+                                    ty,
+                                    "only simple type names can be variants, name it with `Name as Type`",
+                                ));
+
+                                continue;
+                            }
+                        },
+                        UnionVariant::Aliased { alias, ty } => (alias, Some(ty)),
+                        UnionVariant::Atom(atom) => (atom, None),
+                    };
+
+                    if !names.insert(name.to_string()) {
+                        errors.push(Error::new_spanned(
+                            name,
+                            format!(
+                                "variant `{}` appears more than once in `{}`",
+                                name, rule.name
+                            ),
+                        ));
+                    }
+
+                    errors.extend(
+                        ty.into_iter()
+                            .flat_map(|ty| undefined_types(ty, &known, &rules)),
+                    );
+                }
+            }
+        }
+    }
+
+    match errors.into_iter().reduce(|mut all, error| {
+        all.combine(error);
+        all
+    }) {
+        Some(errors) => Err(errors),
+        None => Ok(()),
+    }
+}
+
+/// Every bare type name has to be a rule or declared `extern`, paths like `std::string::String`
+/// are taken as they are. Names that are a typo away from a rule are most likely meant to be one.
+fn undefined_types(ty: &Type, known: &[&Ident], rules: &[&Ident]) -> Vec<Error> {
+    let mut idents = Vec::new();
+    type_names(ty, &mut idents);
+
+    idents
+        .into_iter()
+        .filter(|ident| !known.contains(ident))
+        .map(|ident| {
+            let name = ident.to_string();
+
+            let rule = rules.iter().find(|rule| {
+                let rule = rule.to_string();
+
+                rule.eq_ignore_ascii_case(&name)
+                    || (rule.len() > 3 && distance(&rule, &name) <= rule.len() / 4)
+            });
+
+            let message = match rule {
+                Some(rule) => format!("undefined rule `{}`, did you mean `{}`?", ident, rule),
+                None => format!(
+                    "undefined rule `{}`, declare types that aren't rules with `extern {};`",
+                    ident, ident
+                ),
+            };
+
+            Error::new_spanned(ident, message)
+        })
+        .collect()
+}
+
+/// Bare names of the type and its generic arguments, `Vec<Option<Expression>>` has one since
+/// `Vec` and `Option` are understood by the grammar itself.
+fn type_names<'a>(ty: &'a Type, names: &mut Vec<&'a Ident>) {
+    let Type::Path(path) = ty else {
+        return;
+    };
+
+    let Some(segment) = path.path.segments.last() else {
+        return;
+    };
+
+    let wrapper = segment.ident == "Vec" || segment.ident == "Option";
+
+    if path.qself.is_none() && path.path.segments.len() == 1 && !wrapper {
+        names.push(&segment.ident);
+    }
+
+    if let PathArguments::AngleBracketed(arguments) = &segment.arguments {
+        for argument in arguments.args.iter() {
+            if let GenericArgument::Type(ty) = argument {
+                type_names(ty, names);
+            }
+        }
+    }
+}
+
+/// Levenshtein distance between both words.
+fn distance(left: &str, right: &str) -> usize {
+    let right = right.chars().collect::<Vec<_>>();
+    let mut previous = (0..=right.len()).collect::<Vec<_>>();
+
+    for (i, left) in left.chars().enumerate() {
+        let mut current = vec![i + 1];
+
+        for (j, right) in right.iter().enumerate() {
+            let substitution = previous[j] + usize::from(left != *right);
+
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+
+        previous = current;
+    }
+
+    previous[right.len()]
+}
@@ -0,0 +1,442 @@
+use crate::grammar::Pointer;
+use crate::method_name;
+use crate::model::{FieldModel, Model, RuleKind, RuleModel, Shape, VariantContent, VariantModel};
+use crate::nodes::wrap;
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::Ident;
+
+/// Every rule gets a method in each visitor:
+///   - `SyntaxVisitor<T>::visit_<rule>` computes a value out of a node. Only the rules that are a
+///     union of other rules have a default, which dispatches to the visitor of the variant.
+///   - `Visit::visit_<rule>` goes through nodes for their side effects, every rule has a default.
+///   - `SyntaxVisitorMut::visit_<rule>_mut` updates nodes in place.
+///   - `Fold::fold_<rule>` consumes a node and rebuilds it.
+///
+/// The default of each method is a `walk_<rule>` (`_visit`, `_mut`, `_fold`) function that recurses
+/// into the children of the node, so implementors can override a node and still call it to keep
+/// going.
+///
+/// With an arena, every visitor but `SyntaxVisitor` also takes the `SyntaxArena` the nodes live in.
+pub fn render_visitors(model: &Model) -> TokenStream {
+    let names = model.rules.iter().map(|rule| rule.name).collect::<Vec<_>>();
+
+    let visit_names = names
+        .iter()
+        .map(|name| method_name("visit_", name, ""))
+        .collect::<Vec<_>>();
+
+    let (arena_param, arena_ref_param, arena_arg) = match model.pointer {
+        Pointer::Arena => (
+            quote! { arena: &mut SyntaxArena, },
+            quote! { arena: &SyntaxArena, },
+            quote! { arena, },
+        ),
+        Pointer::Box | Pointer::Rc => (quote! {}, quote! {}, quote! {}),
+    };
+
+    let visitor_methods = model.rules.iter().map(|rule| {
+        let name = rule.name;
+        let visit = method_name("visit_", name, "");
+        let walk = method_name("walk_", name, "");
+
+        if dispatched_variants(rule).is_some() {
+            quote! {
+                fn #visit(&mut self, arg: &#name) -> T {
+                    #walk(self, arg)
+                }
+            }
+        } else {
+            quote! { fn #visit(&mut self, arg: &#name) -> T; }
+        }
+    });
+
+    let visitor_walks = model.rules.iter().filter_map(|rule| {
+        let name = rule.name;
+        let walk = method_name("walk_", name, "");
+
+        let arms = dispatched_variants(rule)?.into_iter().map(|(variant, ty)| {
+            let visit = method_name("visit_", ty, "");
+
+            quote! { #name::#variant(inner) => visitor.#visit(inner) }
+        });
+
+        Some(quote! {
+            pub fn #walk<T, V: SyntaxVisitor<T> + ?Sized>(visitor: &mut V, arg: &#name) -> T {
+                match arg {
+                    #(#arms),*
+                }
+            }
+        })
+    });
+
+    let visit_methods = names.iter().map(|name| {
+        let visit = method_name("visit_", name, "");
+        let walk = method_name("walk_", name, "_visit");
+
+        quote! {
+            fn #visit(&mut self, #arena_ref_param arg: &#name) {
+                #walk(self, #arena_arg arg)
+            }
+        }
+    });
+
+    let visit_walks = model.rules.iter().map(|rule| {
+        let name = rule.name;
+        let walk = method_name("walk_", name, "_visit");
+
+        let body = match &rule.kind {
+            RuleKind::Struct(fields) => {
+                let visits = fields
+                    .iter()
+                    .filter_map(|field| walk_field(model.pointer, field));
+
+                quote! { #(#visits)* }
+            }
+            RuleKind::Enum(variants) => {
+                let arms = rule_variants(variants).map(|(variant, ty)| {
+                    let visit = method_name("visit_", ty, "");
+
+                    quote! { #name::#variant(inner) => visitor.#visit(#arena_arg inner), }
+                });
+
+                quote! {
+                    #[allow(unreachable_patterns)]
+                    match arg {
+                        #(#arms)*
+                        _ => (),
+                    }
+                }
+            }
+        };
+
+        quote! {
+            #[allow(unused_variables)]
+            pub fn #walk<V: Visit + ?Sized>(visitor: &mut V, #arena_ref_param arg: &#name) {
+                #body
+            }
+        }
+    });
+
+    let visitor_mut_methods = names.iter().map(|name| {
+        let visit = method_name("visit_", name, "_mut");
+        let walk = method_name("walk_", name, "_mut");
+
+        quote! {
+            fn #visit(&mut self, #arena_param arg: &mut #name) {
+                #walk(self, #arena_arg arg)
+            }
+        }
+    });
+
+    let visitor_mut_walks = model.rules.iter().map(|rule| {
+        let name = rule.name;
+        let walk = method_name("walk_", name, "_mut");
+
+        let body = match &rule.kind {
+            RuleKind::Struct(fields) => {
+                let visits = fields
+                    .iter()
+                    .filter_map(|field| walk_field_mut(model.pointer, field));
+
+                quote! { #(#visits)* }
+            }
+            RuleKind::Enum(variants) => {
+                let arms = rule_variants(variants).map(|(variant, ty)| {
+                    let visit = method_name("visit_", ty, "_mut");
+
+                    quote! { #name::#variant(inner) => visitor.#visit(#arena_arg inner), }
+                });
+
+                quote! {
+                    #[allow(unreachable_patterns)]
+                    match arg {
+                        #(#arms)*
+                        _ => (),
+                    }
+                }
+            }
+        };
+
+        quote! {
+            #[allow(unused_variables)]
+            pub fn #walk<V: SyntaxVisitorMut + ?Sized>(
+                visitor: &mut V,
+                #arena_param
+                arg: &mut #name,
+            ) {
+                #body
+            }
+        }
+    });
+
+    let fold_methods = names.iter().map(|name| {
+        let fold = method_name("fold_", name, "");
+        let walk = method_name("walk_", name, "_fold");
+
+        quote! {
+            fn #fold(&mut self, #arena_param arg: #name) -> #name {
+                #walk(self, #arena_arg arg)
+            }
+        }
+    });
+
+    let fold_walks = model.rules.iter().map(|rule| {
+        let name = rule.name;
+        let walk = method_name("walk_", name, "_fold");
+
+        let body = match &rule.kind {
+            RuleKind::Struct(fields) => {
+                let field_names = fields.iter().map(|field| field.name);
+                let folded = fields.iter().map(|field| fold_field(model.pointer, field));
+
+                quote! {
+                    let #name { #(#field_names,)* span } = arg;
+
+                    #name {
+                        #(#folded,)*
+                        span,
+                    }
+                }
+            }
+            RuleKind::Enum(variants) => {
+                let arms = variants.iter().filter_map(|VariantModel { name: variant, content }| {
+                    let VariantContent::Rule { rule, boxed } = content else {
+                        return None;
+                    };
+
+                    let fold = method_name("fold_", rule, "");
+
+                    Some(if *boxed {
+                        quote! {
+                            #name::#variant(inner) => #name::#variant(
+                                ::std::boxed::Box::new(folder.#fold(#arena_arg *inner))
+                            ),
+                        }
+                    } else {
+                        quote! {
+                            #name::#variant(inner) => #name::#variant(folder.#fold(#arena_arg inner)),
+                        }
+                    })
+                });
+
+                quote! {
+                    #[allow(unreachable_patterns)]
+                    match arg {
+                        #(#arms)*
+                        other => other,
+                    }
+                }
+            }
+        };
+
+        quote! {
+            #[allow(unused_variables)]
+            pub fn #walk<F: Fold + ?Sized>(folder: &mut F, #arena_param arg: #name) -> #name {
+                #body
+            }
+        }
+    });
+
+    quote! {
+        pub trait SyntaxVisitor<T> {
+            #(#visitor_methods)*
+        }
+
+        #(#visitor_walks)*
+
+        pub trait Visitable<T> {
+            fn accept(&self, visitor: &mut impl SyntaxVisitor<T>) -> T;
+        }
+
+        #(impl<T> Visitable<T> for #names {
+            fn accept(&self, visitor: &mut impl SyntaxVisitor<T>) -> T {
+                visitor.#visit_names(&self)
+            }
+        })*
+
+        pub trait Visit {
+            #(#visit_methods)*
+        }
+
+        #(#visit_walks)*
+
+        pub trait SyntaxVisitorMut {
+            #(#visitor_mut_methods)*
+        }
+
+        #(#visitor_mut_walks)*
+
+        pub trait Fold {
+            #(#fold_methods)*
+        }
+
+        #(#fold_walks)*
+    }
+}
+
+/// Visits the rule held by the field, if any, without changing it.
+fn walk_field(pointer: Pointer, field: &FieldModel) -> Option<TokenStream> {
+    let name = field.name;
+    let visit = method_name("visit_", field.rule?, "");
+
+    // Boxes and `Rc`s deref to the node on their own.
+    let visit_place = |place: TokenStream| match pointer {
+        Pointer::Arena if field.indirect => quote! { visitor.#visit(arena, &arena[*#place]); },
+        Pointer::Arena => quote! { visitor.#visit(arena, #place); },
+        Pointer::Box | Pointer::Rc => quote! { visitor.#visit(#place); },
+    };
+
+    let visit = match field.shape {
+        Shape::Plain => visit_place(quote! { &arg.#name }),
+        Shape::Option(_) => {
+            let visit_inner = visit_place(quote! { inner });
+
+            quote! {
+                if let ::std::option::Option::Some(inner) = &arg.#name {
+                    #visit_inner
+                }
+            }
+        }
+        Shape::Vec(_) => {
+            let visit_item = visit_place(quote! { item });
+
+            quote! {
+                for item in arg.#name.iter() {
+                    #visit_item
+                }
+            }
+        }
+    };
+
+    Some(visit)
+}
+
+/// Visits the rule held by the field, if any.
+fn walk_field_mut(pointer: Pointer, field: &FieldModel) -> Option<TokenStream> {
+    let name = field.name;
+    let visit = method_name("visit_", field.rule?, "_mut");
+
+    let visit_place = |place: TokenStream| {
+        if !field.indirect {
+            return match pointer {
+                Pointer::Arena => quote! { visitor.#visit(arena, #place); },
+                Pointer::Box | Pointer::Rc => quote! { visitor.#visit(#place); },
+            };
+        }
+
+        match pointer {
+            Pointer::Box => quote! { visitor.#visit(&mut *#place); },
+            Pointer::Rc => quote! { visitor.#visit(::std::rc::Rc::make_mut(#place)); },
+            Pointer::Arena => quote! {
+                let id = *#place;
+                let mut node = arena.take(id);
+                visitor.#visit(arena, &mut node);
+                arena.restore(id, node);
+            },
+        }
+    };
+
+    let visit = match field.shape {
+        Shape::Plain => visit_place(quote! { &mut arg.#name }),
+        Shape::Option(_) => {
+            let visit_inner = visit_place(quote! { inner });
+
+            quote! {
+                if let ::std::option::Option::Some(inner) = &mut arg.#name {
+                    #visit_inner
+                }
+            }
+        }
+        Shape::Vec(_) => {
+            let visit_item = visit_place(quote! { item });
+
+            quote! {
+                for item in arg.#name.iter_mut() {
+                    #visit_item
+                }
+            }
+        }
+    };
+
+    Some(visit)
+}
+
+/// Expression rebuilding the field out of its own variable, after folding the rule it holds.
+fn fold_field(pointer: Pointer, field: &FieldModel) -> TokenStream {
+    let name = field.name;
+
+    let Some(rule) = field.rule else {
+        return quote! { #name };
+    };
+
+    let fold = method_name("fold_", rule, "");
+
+    let fold_value = |value: TokenStream| {
+        if !field.indirect {
+            return match pointer {
+                Pointer::Arena => quote! { folder.#fold(arena, #value) },
+                Pointer::Box | Pointer::Rc => quote! { folder.#fold(#value) },
+            };
+        }
+
+        match pointer {
+            Pointer::Box => wrap(pointer, quote! { folder.#fold(*#value) }),
+            Pointer::Rc => wrap(
+                pointer,
+                quote! {
+                    folder.#fold(
+                        ::std::rc::Rc::try_unwrap(#value).unwrap_or_else(|shared| (*shared).clone())
+                    )
+                },
+            ),
+            // Nodes keep their place in the arena.
+            Pointer::Arena => quote! {{
+                let id = #value;
+                let node = arena.take(id);
+                let node = folder.#fold(arena, node);
+                arena.restore(id, node);
+
+                id
+            }},
+        }
+    };
+
+    let folded = match field.shape {
+        Shape::Plain => fold_value(quote! { #name }),
+        Shape::Option(_) => {
+            let fold_inner = fold_value(quote! { inner });
+
+            quote! { #name.map(|inner| #fold_inner) }
+        }
+        Shape::Vec(_) => {
+            let fold_item = fold_value(quote! { item });
+
+            quote! { #name.into_iter().map(|item| #fold_item).collect() }
+        }
+    };
+
+    quote! { #name: #folded }
+}
+
+/// Variants wrapping another rule, along with the name of that rule.
+fn rule_variants<'a>(
+    variants: &'a [VariantModel<'a>],
+) -> impl Iterator<Item = (&'a Ident, &'a Ident)> {
+    variants
+        .iter()
+        .filter_map(|variant| Some((variant.name, variant.rule()?)))
+}
+
+/// The variants of a rule that only wraps other rules, so visiting it is a matter of dispatching
+/// to the visitor of the variant.
+fn dispatched_variants<'a>(rule: &'a RuleModel<'a>) -> Option<Vec<(&'a Ident, &'a Ident)>> {
+    match &rule.kind {
+        RuleKind::Enum(variants) => {
+            let dispatched = rule_variants(variants).collect::<Vec<_>>();
+
+            (dispatched.len() == variants.len()).then_some(dispatched)
+        }
+        RuleKind::Struct(_) => None,
+    }
+}
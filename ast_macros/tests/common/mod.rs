@@ -0,0 +1,82 @@
+//! Minimal stand-ins for the types `grammar!` expects to find in scope.
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TextSection {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl TextSection {
+    pub fn covering(sections: impl IntoIterator<Item = TextSection>) -> TextSection {
+        let mut sections = sections.into_iter();
+
+        match sections.next() {
+            Some(first) => TextSection {
+                start: first.start,
+                end: sections.last().unwrap_or(first).end,
+            },
+            None => TextSection::default(),
+        }
+    }
+}
+
+pub trait Spanned {
+    fn span(&self) -> TextSection;
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Name {
+    pub text: &'static str,
+    pub section: TextSection,
+}
+
+impl Spanned for Name {
+    fn span(&self) -> TextSection {
+        self.section
+    }
+}
+
+pub fn name(text: &'static str, start: usize) -> Name {
+    Name {
+        text,
+        section: TextSection {
+            start,
+            end: start + text.len(),
+        },
+    }
+}
+
+pub fn at(start: usize, end: usize) -> TextSection {
+    TextSection { start, end }
+}
+
+/// Implements the traits of the grammar generated in the calling module for the stand-ins.
+macro_rules! leaf_impls {
+    () => {
+        impl SyntaxEq for Name {
+            fn syntax_eq(&self, other: &Self) -> bool {
+                self.text == other.text
+            }
+        }
+
+        impl ToSexp for Name {
+            fn write_sexp(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                f.write_str(self.text)
+            }
+        }
+
+        impl ToJson for Name {
+            fn write_json(&self, out: &mut String) {
+                write_json_string(self.text, out)
+            }
+        }
+
+        impl ToJson for TextSection {
+            fn write_json(&self, out: &mut String) {
+                out.push_str(&format!("[{},{}]", self.start, self.end))
+            }
+        }
+    };
+}
+
+pub(crate) use leaf_impls;
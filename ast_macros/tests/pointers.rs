@@ -0,0 +1,253 @@
+// Bindings with explicit types check what the generated fields are stored as.
+#![allow(dead_code, clippy::borrowed_box)]
+
+mod common;
+
+use common::{at, name, Name, Spanned, TextSection};
+
+macro_rules! statements_grammar {
+    ($($pointer:tt)*) => {
+        ast_macros::grammar! {
+            $($pointer)*
+
+            extern Name, f64;
+
+            Statement => Var | Block | If | Print;
+
+            Var => name: Name, initializer: Option<Expression>;
+
+            Block => statements: Vec<Statement>;
+
+            If => condition: Expression, then_branch: Statement, else_branch: Option<Statement>;
+
+            Print => expression: Expression;
+
+            Expression => Negate | Variable | Number as f64;
+
+            Negate => expression: Expression;
+
+            Variable => name: Name;
+        }
+    };
+}
+
+mod boxed {
+    use super::*;
+
+    statements_grammar! {}
+    common::leaf_impls!();
+
+    #[test]
+    fn only_recursive_fields_are_boxed() {
+        let negate = Negate::new(Expression::Number(1.0, at(1, 2)));
+        let _: &Box<Expression> = &negate.expression;
+
+        let var = Var::new(name("a", 0), Some(Expression::Negate(negate)));
+        let _: &Name = &var.name;
+        let _: &Option<Expression> = &var.initializer;
+
+        let block = Block::new(vec![Statement::Var(var)]);
+        let _: &Vec<Statement> = &block.statements;
+
+        let print = Print::new(Expression::Variable(Variable::new(name("a", 20))));
+        let statement = If::new(
+            Expression::Number(0.0, at(10, 11)),
+            Statement::Block(block),
+            Some(Statement::Print(print)),
+        );
+        let _: &Box<Statement> = &statement.then_branch;
+        let _: &Option<Box<Statement>> = &statement.else_branch;
+
+        assert_eq!(at(10, 21), statement.span());
+    }
+
+    #[test]
+    fn visitors_go_through_vec_and_option() {
+        struct Increment;
+
+        impl SyntaxVisitorMut for Increment {
+            fn visit_expression_mut(&mut self, arg: &mut Expression) {
+                if let Expression::Number(value, _) = arg {
+                    *value += 1.0;
+                }
+
+                walk_expression_mut(self, arg)
+            }
+        }
+
+        struct Unnegate;
+
+        impl Fold for Unnegate {
+            fn fold_expression(&mut self, arg: Expression) -> Expression {
+                match walk_expression_fold(self, arg) {
+                    Expression::Negate(negate) => *negate.expression,
+                    other => other,
+                }
+            }
+        }
+
+        let initializer = Expression::Negate(Negate::new(Expression::Number(1.0, at(1, 2))));
+        let mut block = Block::new(vec![
+            Statement::Var(Var::new(name("a", 0), Some(initializer))),
+            Statement::Var(Var::new(name("b", 5), None)),
+        ]);
+
+        struct Names(Vec<&'static str>);
+
+        impl Visit for Names {
+            fn visit_var(&mut self, arg: &Var) {
+                self.0.push(arg.name.text);
+
+                walk_var_visit(self, arg)
+            }
+        }
+
+        let mut names = Names(Vec::new());
+        names.visit_block(&block);
+        assert_eq!(vec!["a", "b"], names.0);
+
+        Increment.visit_block_mut(&mut block);
+        let block = Unnegate.fold_block(block);
+
+        match &block.statements[0] {
+            Statement::Var(Var {
+                initializer: Some(Expression::Number(value, _)),
+                ..
+            }) => assert_eq!(2.0, *value),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
+
+mod shared {
+    use super::*;
+    use std::rc::Rc;
+
+    statements_grammar! { #![pointer(Rc)] }
+    common::leaf_impls!();
+
+    #[test]
+    fn recursive_fields_are_shared() {
+        let negate = Negate::new(Expression::Number(1.0, at(1, 2)));
+        let inner: Rc<Expression> = negate.expression.clone();
+
+        let mut copy = negate.clone();
+        assert!(Rc::ptr_eq(&inner, &copy.expression));
+
+        struct Double;
+
+        impl SyntaxVisitorMut for Double {
+            fn visit_expression_mut(&mut self, arg: &mut Expression) {
+                if let Expression::Number(value, _) = arg {
+                    *value *= 2.0;
+                }
+
+                walk_expression_mut(self, arg)
+            }
+        }
+
+        // Updating a shared node copies it instead of changing every tree holding it.
+        Double.visit_negate_mut(&mut copy);
+
+        assert!(matches!(*inner, Expression::Number(value, _) if value == 1.0));
+        assert!(matches!(*copy.expression, Expression::Number(value, _) if value == 2.0));
+    }
+}
+
+mod arena {
+    use super::*;
+
+    statements_grammar! { #![pointer(Arena)] }
+
+    #[test]
+    fn recursive_fields_are_arena_indices() {
+        let mut arena = SyntaxArena::new();
+
+        let negate = Negate::new(&mut arena, Expression::Number(1.0, at(1, 2)));
+        let id: NodeId<Expression> = negate.expression;
+
+        let print = Print::new(Expression::Negate(negate));
+        let statement = If::new(
+            &mut arena,
+            Expression::Variable(Variable::new(name("a", 0))),
+            Statement::Print(print),
+            None,
+        );
+
+        assert_eq!(at(0, 2), statement.span());
+        assert!(matches!(arena[id], Expression::Number(value, _) if value == 1.0));
+    }
+
+    #[test]
+    fn visitors_reach_nodes_in_the_arena() {
+        struct Double;
+
+        impl SyntaxVisitorMut for Double {
+            fn visit_expression_mut(&mut self, arena: &mut SyntaxArena, arg: &mut Expression) {
+                if let Expression::Number(value, _) = arg {
+                    *value *= 2.0;
+                }
+
+                walk_expression_mut(self, arena, arg)
+            }
+        }
+
+        struct Rename;
+
+        impl Fold for Rename {
+            fn fold_variable(&mut self, _: &mut SyntaxArena, arg: Variable) -> Variable {
+                Variable::new(name("renamed", arg.span.start))
+            }
+        }
+
+        let mut arena = SyntaxArena::new();
+
+        let negate = Negate::new(&mut arena, Expression::Number(1.0, at(1, 2)));
+        let id = negate.expression;
+        let then_branch = Statement::Print(Print::new(Expression::Negate(negate)));
+        let else_branch = Statement::Print(Print::new(Expression::Variable(Variable::new(name(
+            "a", 5,
+        )))));
+
+        let mut statement = Statement::If(If::new(
+            &mut arena,
+            Expression::Number(0.0, at(0, 1)),
+            then_branch,
+            Some(else_branch),
+        ));
+
+        struct Numbers(Vec<f64>);
+
+        impl Visit for Numbers {
+            fn visit_expression(&mut self, arena: &SyntaxArena, arg: &Expression) {
+                if let Expression::Number(value, _) = arg {
+                    self.0.push(*value);
+                }
+
+                walk_expression_visit(self, arena, arg)
+            }
+        }
+
+        let mut numbers = Numbers(Vec::new());
+        numbers.visit_statement(&arena, &statement);
+        assert_eq!(vec![0.0, 1.0], numbers.0);
+
+        Double.visit_statement_mut(&mut arena, &mut statement);
+        let statement = Rename.fold_statement(&mut arena, statement);
+
+        assert!(matches!(arena[id], Expression::Number(value, _) if value == 2.0));
+
+        let Statement::If(statement) = statement else {
+            panic!("expected an if");
+        };
+        let else_branch = statement.else_branch.expect("else branch");
+
+        match &arena[else_branch] {
+            Statement::Print(Print {
+                expression: Expression::Variable(variable),
+                ..
+            }) => assert_eq!("renamed", variable.name.text),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
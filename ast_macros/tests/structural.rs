@@ -0,0 +1,66 @@
+mod common;
+
+use common::{at, name, Name, Spanned, TextSection};
+
+ast_macros::grammar! {
+    extern Name, f64;
+
+    Statement => Var | Block;
+
+    Var => name: Name, initializer: Option<Expression>;
+
+    Block => statements: Vec<Statement>;
+
+    Expression => Negate | Variable | Number as f64 | @Nil;
+
+    Negate => expression: Expression;
+
+    Variable => name: Name;
+}
+
+common::leaf_impls!();
+
+fn program(offset: usize) -> Block {
+    Block::new(vec![
+        Statement::Var(Var::new(
+            name("a", offset),
+            Some(Expression::Negate(Negate::new(Expression::Number(
+                1.5,
+                at(offset + 4, offset + 7),
+            )))),
+        )),
+        Statement::Var(Var::new(name("b", offset + 10), None)),
+        Statement::Block(Block::new(vec![])),
+    ])
+}
+
+#[test]
+fn equality_ignores_spans() {
+    assert_eq!(program(0), program(100));
+    assert_ne!(program(0).span(), program(100).span());
+
+    let mut other = program(0);
+    other.statements.pop();
+    assert_ne!(program(0), other);
+
+    assert_ne!(Expression::Nil(at(0, 3)), Expression::Number(0.0, at(0, 1)));
+}
+
+#[test]
+fn display_is_an_s_expression() {
+    assert_eq!(
+        "(block [(var a (negate 1.5)) (var b _) (block [])])",
+        program(0).to_string()
+    );
+    assert_eq!("nil", Expression::Nil(at(0, 3)).to_string());
+}
+
+#[test]
+fn json_includes_types_and_spans() {
+    let var = Var::new(name("a\"", 0), Some(Expression::Nil(at(4, 7))));
+
+    assert_eq!(
+        r#"{"type":"Var","span":[0,7],"name":"a\"","initializer":{"type":"Nil","span":[4,7]}}"#,
+        var.to_json()
+    );
+}
@@ -0,0 +1,11 @@
+use ast_macros::grammar;
+
+grammar! {
+    extern f64;
+
+    Binary => left: f64, right: f64, left: f64;
+
+    Unary => span: f64;
+}
+
+fn main() {}
@@ -0,0 +1,13 @@
+use ast_macros::grammar;
+
+grammar! {
+    extern f64;
+
+    Expression => Negate | Number as f64;
+
+    Negate => operand: Expression;
+
+    Negate => value: Expression;
+}
+
+fn main() {}
@@ -0,0 +1,13 @@
+use ast_macros::grammar;
+
+grammar! {
+    extern f64, i64, u64;
+
+    Literal => Number as f64
+        | Integer as i64
+        | Number as u64
+        | @Nil
+        | @Nil;
+}
+
+fn main() {}
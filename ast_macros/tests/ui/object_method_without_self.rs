@@ -0,0 +1,13 @@
+use ast_macros::lox_methods;
+
+struct Counter;
+
+#[lox_methods]
+impl Counter {
+    #[lox]
+    fn zero() -> f64 {
+        0.0
+    }
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use ast_macros::LoxObject;
+
+#[derive(LoxObject)]
+struct Point {
+    #[lox(hidden)]
+    x: f64,
+}
+
+fn main() {}
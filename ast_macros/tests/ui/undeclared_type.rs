@@ -0,0 +1,11 @@
+use ast_macros::grammar;
+
+grammar! {
+    extern f64;
+
+    Expression => Negate | Number as f64;
+
+    Negate => operand: Expression, at: Position;
+}
+
+fn main() {}
@@ -0,0 +1,11 @@
+use ast_macros::grammar;
+
+grammar! {
+    #![pointer(Arc)]
+
+    extern f64;
+
+    Negate => value: f64;
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use ast_macros::grammar;
+
+grammar! {
+    extern f64;
+
+    Literal => Vec<f64> | std::string::String;
+}
+
+fn main() {}
@@ -0,0 +1,62 @@
+//! Runs the VM on number crunching and on closure and string heavy code. To compare the value
+//! representations, save a baseline with one and bench the other against it:
+//!
+//! ```text
+//! cargo bench --bench vm -- --save-baseline enum
+//! cargo bench --bench vm --features nan-boxing -- --baseline enum
+//! ```
+
+use criterion::criterion_group;
+use criterion::criterion_main;
+use criterion::Criterion;
+use jrlox::lexer::Scanner;
+use jrlox::parser::ast::Statement;
+use jrlox::parser::Parser;
+use jrlox::text::SourceMap;
+use jrlox::vm::Vm;
+
+const ARITHMETIC: &str = "
+    fun fib(n) { if (n < 2) return n; return fib(n - 1) + fib(n - 2); }
+
+    var total = 0;
+    for (var i = 0; i < 10000; i = i + 1) {
+        total = total + i * 2 / 3 - (i - 1);
+    }
+    total = total + fib(18);";
+
+const OBJECTS: &str = "
+    fun counter(name) {
+        var count = 0;
+        fun increment() { count = count + 1; return name + \"!\"; }
+        return increment;
+    }
+
+    var last;
+    for (var i = 0; i < 2000; i = i + 1) {
+        var next = counter(\"counter\");
+        next();
+        last = next() + \"?\";
+    }";
+
+fn parse(source: &str) -> Vec<Statement> {
+    let mut sources = SourceMap::new();
+    let file = sources.add("bench.lox", source);
+    let tokens = Scanner::new(sources.file(file)).scan_tokens().tokens;
+
+    Parser::new(tokens)
+        .parse_program()
+        .expect("benchmarks are valid programs")
+}
+
+fn workloads(c: &mut Criterion) {
+    for (name, source) in [("arithmetic", ARITHMETIC), ("objects", OBJECTS)] {
+        let program = parse(source);
+
+        c.bench_function(name, |b| {
+            b.iter(|| Vm::new().execute(&program).expect("benchmarks run"))
+        });
+    }
+}
+
+criterion_group!(benches, workloads);
+criterion_main!(benches);
@@ -0,0 +1,67 @@
+/*
+ * C interface to jrlox, the library built as the `cdylib` of the crate. See `src/ffi.rs`.
+ *
+ * An interpreter keeps the globals defined by every source it runs. It isn't thread safe, use it
+ * from the thread that made it. Every function accepts a null interpreter, doing nothing.
+ */
+#ifndef JRLOX_H
+#define JRLOX_H
+
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct jrlox_interpreter jrlox_interpreter;
+
+#define JRLOX_NIL 0
+#define JRLOX_BOOL 1
+#define JRLOX_NUMBER 2
+#define JRLOX_STRING 3
+/* Functions and objects, only readable as text. */
+#define JRLOX_OTHER 4
+
+/* A Lox value. Only the field for its kind means anything, but `string` is set for JRLOX_OTHER too. */
+typedef struct jrlox_value {
+    int kind;
+    int boolean;
+    double number;
+    const char *string;
+} jrlox_value;
+
+/*
+ * A C function scripts can call, always with the arity it was defined with. It sets `result` and
+ * returns 0, or fails by returning non-zero, with the message in `result` if it's a string. Strings
+ * in `arguments` only live until it returns, the one in `result` is copied.
+ */
+typedef int (*jrlox_native)(void *user_data, const jrlox_value *arguments, size_t count,
+                            jrlox_value *result);
+
+/* Null if the interpreter couldn't be made. */
+jrlox_interpreter *jrlox_new(void);
+
+/* Null is ignored. */
+void jrlox_free(jrlox_interpreter *interpreter);
+
+/* Runs the source, returning how many errors it had, -1 if the interpreter is null. `name` is where
+ * errors say they happened. A bug in the interpreter is reported as an error too, after which the
+ * interpreter may not work right. */
+int jrlox_run(jrlox_interpreter *interpreter, const char *name, const char *source);
+
+size_t jrlox_error_count(const jrlox_interpreter *interpreter);
+
+/* An error of the last run, prefixed with where it happened. It's freed by the next run, null if
+ * there's no error at `index`. */
+const char *jrlox_error(const jrlox_interpreter *interpreter, size_t index);
+
+/* Defines a global function, returning non-zero if the interpreter or the callback are null or
+ * the name isn't UTF-8. `user_data` is passed to every call and must outlive the interpreter. */
+int jrlox_define_native(jrlox_interpreter *interpreter, const char *name, size_t arity,
+                        jrlox_native callback, void *user_data);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
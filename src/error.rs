@@ -1,27 +1,67 @@
+use crate::text::SourceMap;
 use crate::text::TextSection;
 use std::borrow::Cow;
 
 #[derive(Debug, Clone)]
 pub struct Error {
     pub section: TextSection,
-    pub location: Cow<'static, str>,
     pub message: Cow<'static, str>,
+    pub kind: ErrorKind,
 }
 
-impl ToString for Error {
-    fn to_string(&self) -> String {
-        format!(
+/// Tells apart the errors a host may want to handle on its own, like a script going over its
+/// [`InterpreterLimits`](crate::interpreter::InterpreterLimits) or being interrupted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ErrorKind {
+    #[default]
+    Other,
+    StepLimit,
+    CallDepth,
+    HeapLimit,
+    Interrupted,
+}
+
+impl Error {
+    /// Renders the error prefixed with the file, line and column it points to.
+    pub fn located<'a>(&'a self, sources: &'a SourceMap) -> LocatedError<'a> {
+        LocatedError {
+            error: self,
+            sources,
+        }
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
             "{} at line: {}, column: {}",
             self.message, self.section.start.line, self.section.start.column
         )
     }
 }
 
+pub struct LocatedError<'a> {
+    error: &'a Error,
+    sources: &'a SourceMap,
+}
+
+impl std::fmt::Display for LocatedError<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "[{}] Error: {}",
+            self.sources.location(&self.error.section),
+            self.error.message
+        )
+    }
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct ErrorBuilder {
     section: Option<TextSection>,
-    location: Option<Cow<'static, str>>,
     message: Option<Cow<'static, str>>,
+    kind: ErrorKind,
 }
 
 impl ErrorBuilder {
@@ -29,21 +69,20 @@ impl ErrorBuilder {
         Self::default()
     }
 
-    // actually use loc instead
     pub fn section(mut self, section: TextSection) -> Self {
         self.section.replace(section);
 
         self
     }
 
-    pub fn location(mut self, location: impl Into<Cow<'static, str>>) -> Self {
-        self.location.replace(location.into());
+    pub fn message(mut self, message: impl Into<Cow<'static, str>>) -> Self {
+        self.message.replace(message.into());
 
         self
     }
 
-    pub fn message(mut self, message: impl Into<Cow<'static, str>>) -> Self {
-        self.message.replace(message.into());
+    pub fn kind(mut self, kind: ErrorKind) -> Self {
+        self.kind = kind;
 
         self
     }
@@ -51,8 +90,8 @@ impl ErrorBuilder {
     pub fn build(self) -> Error {
         Error {
             section: self.section.unwrap_or_default(),
-            location: self.location.unwrap_or_default(),
             message: self.message.unwrap_or_default(),
+            kind: self.kind,
         }
     }
 }
@@ -62,21 +101,28 @@ pub struct ErrorList {
     inner: Vec<Error>,
 }
 
+impl From<Error> for ErrorList {
+    fn from(error: Error) -> Self {
+        ErrorList { inner: vec![error] }
+    }
+}
+
 impl ErrorList {
     pub fn add(&mut self, error: Error) {
         self.inner.push(error)
     }
 
-    pub fn print(&self) {
+    pub fn print(&self, sources: &SourceMap) {
         for error in self.inner.iter() {
-            eprintln!(
-                "[{}] Error {}: {}",
-                error.section.start, error.location, error.message
-            );
+            eprintln!("{}", error.located(sources));
         }
     }
 
     pub fn size(&self) -> usize {
         self.inner.len()
     }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Error> {
+        self.inner.iter()
+    }
 }
@@ -0,0 +1,292 @@
+//! C bindings to the [`Interpreter`], declared in `include/jrlox.h`. Every function takes the
+//! interpreter made by [`jrlox_new`], which has to stay in the thread that made it. Null
+//! interpreters are rejected, and panics are caught before they reach C.
+
+use std::ffi::c_char;
+use std::ffi::c_int;
+use std::ffi::c_void;
+use std::ffi::CStr;
+use std::ffi::CString;
+use std::panic::AssertUnwindSafe;
+
+use crate::error::ErrorBuilder;
+use crate::error::ErrorList;
+use crate::interpreter::Interpreter;
+use crate::interpreter::NativeResult;
+use crate::interpreter::Value;
+
+pub const JRLOX_NIL: c_int = 0;
+pub const JRLOX_BOOL: c_int = 1;
+pub const JRLOX_NUMBER: c_int = 2;
+pub const JRLOX_STRING: c_int = 3;
+/// Functions and objects, only readable as text.
+pub const JRLOX_OTHER: c_int = 4;
+
+/// `jrlox_value`, a Lox value as seen from C. Only the field for its kind means anything, but
+/// `string` is set for `JRLOX_OTHER` too.
+#[repr(C)]
+pub struct CValue {
+    pub kind: c_int,
+    pub boolean: c_int,
+    pub number: f64,
+    pub string: *const c_char,
+}
+
+/// `jrlox_native`, a C function scripts can call. It fails by returning non-zero, with the message
+/// in `result` if it's a string. Taken as an `Option` since C can pass null.
+pub type CNative = extern "C" fn(
+    user_data: *mut c_void,
+    arguments: *const CValue,
+    count: usize,
+    result: *mut CValue,
+) -> c_int;
+
+/// `jrlox_interpreter`, the errors of the last run kept for C to read.
+pub struct Embedded {
+    interpreter: Interpreter,
+    errors: Vec<CString>,
+}
+
+/// Null if making the interpreter panicked.
+#[no_mangle]
+pub extern "C" fn jrlox_new() -> *mut Embedded {
+    std::panic::catch_unwind(|| {
+        Box::into_raw(Box::new(Embedded {
+            interpreter: Interpreter::new(),
+            errors: Vec::new(),
+        }))
+    })
+    .unwrap_or(std::ptr::null_mut())
+}
+
+/// # Safety
+///
+/// The interpreter must come from [`jrlox_new`] and not be used afterwards. Null is ignored.
+#[no_mangle]
+pub unsafe extern "C" fn jrlox_free(interpreter: *mut Embedded) {
+    if !interpreter.is_null() {
+        // A value panicking while dropped can only leak what's left.
+        let _ = std::panic::catch_unwind(AssertUnwindSafe(|| drop(Box::from_raw(interpreter))));
+    }
+}
+
+/// Runs the source, returning how many errors it had, -1 if the interpreter is null. A panic is
+/// reported as an error, the interpreter may be left in a bad state by it.
+///
+/// # Safety
+///
+/// The interpreter must come from [`jrlox_new`] or be null, `name` and `source` must be NUL
+/// terminated.
+#[no_mangle]
+pub unsafe extern "C" fn jrlox_run(
+    interpreter: *mut Embedded,
+    name: *const c_char,
+    source: *const c_char,
+) -> c_int {
+    let Some(embedded) = interpreter.as_mut() else {
+        return -1;
+    };
+
+    let run = std::panic::catch_unwind(AssertUnwindSafe(|| {
+        let result = match (text(name), text(source)) {
+            (Some(name), Some(source)) => embedded.interpreter.run_source(name, source),
+            _ => Err(ErrorBuilder::new()
+                .message("The name and source must be UTF-8 text")
+                .build()
+                .into()),
+        };
+
+        match result {
+            Ok(()) => Vec::new(),
+            Err(errors) => located(&embedded.interpreter, &errors),
+        }
+    }));
+
+    embedded.errors = run.unwrap_or_else(|panic| {
+        let message = panic
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("unknown cause");
+
+        vec![c_string(format!(
+            "Error: The interpreter panicked: {}",
+            message
+        ))]
+    });
+
+    embedded.errors.len() as c_int
+}
+
+/// Zero if the interpreter is null.
+///
+/// # Safety
+///
+/// The interpreter must come from [`jrlox_new`] or be null.
+#[no_mangle]
+pub unsafe extern "C" fn jrlox_error_count(interpreter: *const Embedded) -> usize {
+    interpreter
+        .as_ref()
+        .map_or(0, |embedded| embedded.errors.len())
+}
+
+/// An error of the last run, prefixed with where it happened. It's freed by the next run, null if
+/// there's no error at `index` or the interpreter is null.
+///
+/// # Safety
+///
+/// The interpreter must come from [`jrlox_new`] or be null.
+#[no_mangle]
+pub unsafe extern "C" fn jrlox_error(interpreter: *const Embedded, index: usize) -> *const c_char {
+    interpreter
+        .as_ref()
+        .and_then(|embedded| embedded.errors.get(index))
+        .map_or(std::ptr::null(), |error| error.as_ptr())
+}
+
+/// Defines a global function calling back into C with exactly `arity` arguments, returning
+/// non-zero if the interpreter or the callback are null or the name isn't UTF-8. Strings given to
+/// the callback only live until it returns.
+///
+/// # Safety
+///
+/// The interpreter must come from [`jrlox_new`] or be null, `name` must be NUL terminated, and
+/// `user_data` must be valid for as long as scripts can call the function.
+#[no_mangle]
+pub unsafe extern "C" fn jrlox_define_native(
+    interpreter: *mut Embedded,
+    name: *const c_char,
+    arity: usize,
+    callback: Option<CNative>,
+    user_data: *mut c_void,
+) -> c_int {
+    let (Some(embedded), Some(name), Some(callback)) = (interpreter.as_mut(), text(name), callback)
+    else {
+        return 1;
+    };
+
+    let defined = std::panic::catch_unwind(AssertUnwindSafe(|| {
+        embedded
+            .interpreter
+            .define_raw_native(name, arity, move |arguments| {
+                call(callback, user_data, arguments)
+            });
+    }));
+
+    defined.is_err() as c_int
+}
+
+fn call(callback: CNative, user_data: *mut c_void, arguments: &[Value]) -> NativeResult<Value> {
+    // Keeps the argument strings alive until the callback returns.
+    let strings = arguments
+        .iter()
+        .map(|argument| match argument {
+            Value::Nil | Value::Bool(_) | Value::Number(_) => Ok(None),
+            other => CString::new(other.to_string())
+                .map(Some)
+                .map_err(|_| "Strings passed to C can't have NUL characters".to_string()),
+        })
+        .collect::<NativeResult<Vec<_>>>()?;
+
+    let values = arguments
+        .iter()
+        .zip(strings.iter())
+        .map(|(argument, string)| {
+            let string = string.as_ref().map_or(std::ptr::null(), |s| s.as_ptr());
+
+            match argument {
+                Value::Nil => c_value(JRLOX_NIL, string),
+                Value::Bool(boolean) => CValue {
+                    boolean: *boolean as c_int,
+                    ..c_value(JRLOX_BOOL, string)
+                },
+                Value::Number(number) => CValue {
+                    number: *number,
+                    ..c_value(JRLOX_NUMBER, string)
+                },
+                Value::String(_) => c_value(JRLOX_STRING, string),
+                _ => c_value(JRLOX_OTHER, string),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let mut result = c_value(JRLOX_NIL, std::ptr::null());
+    let status = callback(user_data, values.as_ptr(), values.len(), &mut result);
+
+    // SAFETY: the callback only sets `string` to a NUL terminated string, or leaves it null.
+    let string = unsafe { text(result.string) }.map(str::to_string);
+
+    if status != 0 {
+        return Err(match (result.kind, string) {
+            (JRLOX_STRING, Some(message)) => message,
+            _ => "Native function failed".to_string(),
+        });
+    }
+
+    match (result.kind, string) {
+        (JRLOX_NIL, _) => Ok(Value::Nil),
+        (JRLOX_BOOL, _) => Ok(Value::Bool(result.boolean != 0)),
+        (JRLOX_NUMBER, _) => Ok(Value::Number(result.number)),
+        (JRLOX_STRING, Some(string)) => Ok(Value::from(string)),
+        _ => Err("Native function returned an invalid value".to_string()),
+    }
+}
+
+fn c_value(kind: c_int, string: *const c_char) -> CValue {
+    CValue {
+        kind,
+        boolean: 0,
+        number: 0.0,
+        string,
+    }
+}
+
+/// # Safety
+///
+/// The pointer must be null or NUL terminated.
+unsafe fn text<'a>(pointer: *const c_char) -> Option<&'a str> {
+    if pointer.is_null() {
+        return None;
+    }
+
+    CStr::from_ptr(pointer).to_str().ok()
+}
+
+fn located(interpreter: &Interpreter, errors: &ErrorList) -> Vec<CString> {
+    errors
+        .iter()
+        .map(|error| c_string(error.located(interpreter.sources()).to_string()))
+        .collect()
+}
+
+fn c_string(message: String) -> CString {
+    CString::new(message.replace('\0', "")).expect("NUL characters removed")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn panics_become_errors() {
+        let interpreter = jrlox_new();
+
+        // SAFETY: the interpreter comes from `jrlox_new` and the strings are NUL terminated.
+        unsafe {
+            (*interpreter)
+                .interpreter
+                .define_raw_native("boom", 0, |_| panic!("boom"));
+
+            assert_eq!(
+                1,
+                jrlox_run(interpreter, c"test.lox".as_ptr(), c"boom();".as_ptr())
+            );
+            assert_eq!(
+                Ok("Error: The interpreter panicked: boom"),
+                CStr::from_ptr(jrlox_error(interpreter, 0)).to_str()
+            );
+
+            jrlox_free(interpreter);
+        }
+    }
+}
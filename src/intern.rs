@@ -0,0 +1,171 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::rc::Rc;
+
+/// An interned string. Every symbol with the same text shares the same allocation, so cloning is
+/// a reference count bump and comparing or hashing only looks at the pointer. The pointer is a thin
+/// one so it can be packed into a NaN-boxed value.
+#[derive(Clone)]
+pub struct Symbol(Rc<String>);
+
+/// Below this many strings the table isn't worth purging.
+const MIN_PURGE: usize = 1024;
+
+/// Strings interned in this thread. Entries nobody else holds are dropped once the table doubles,
+/// so strings built at runtime don't pile up forever.
+struct Interner {
+    strings: HashSet<Entry>,
+    next_purge: usize,
+}
+
+/// Looked up by its text.
+#[derive(PartialEq, Eq, Hash)]
+struct Entry(Rc<String>);
+
+impl std::borrow::Borrow<str> for Entry {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+thread_local! {
+    static INTERNER: RefCell<Interner> = RefCell::new(Interner {
+        strings: HashSet::new(),
+        next_purge: MIN_PURGE,
+    });
+}
+
+impl Symbol {
+    pub fn intern(text: &str) -> Self {
+        INTERNER.with(|interner| {
+            let mut interner = interner.borrow_mut();
+
+            if let Some(Entry(string)) = interner.strings.get(text) {
+                return Symbol(string.clone());
+            }
+
+            if interner.strings.len() >= interner.next_purge {
+                interner
+                    .strings
+                    .retain(|Entry(string)| Rc::strong_count(string) > 1);
+                interner.next_purge = (interner.strings.len() * 2).max(MIN_PURGE);
+            }
+
+            let string = Rc::new(text.to_string());
+            interner.strings.insert(Entry(string.clone()));
+
+            Symbol(string)
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Gives up the reference for a pointer, to be turned back with [`Symbol::from_raw`].
+    pub fn into_raw(self) -> *const () {
+        Rc::into_raw(self.0) as *const ()
+    }
+
+    /// # Safety
+    ///
+    /// The pointer must come from [`Symbol::into_raw`] and be turned back only once.
+    pub unsafe fn from_raw(pointer: *const ()) -> Self {
+        Symbol(Rc::from_raw(pointer as *const String))
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(text: &str) -> Self {
+        Symbol::intern(text)
+    }
+}
+
+impl From<String> for Symbol {
+    fn from(text: String) -> Self {
+        Symbol::intern(&text)
+    }
+}
+
+impl std::ops::Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for Symbol {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Symbol {}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl std::hash::Hash for Symbol {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        std::ptr::hash(Rc::as_ptr(&self.0), state)
+    }
+}
+
+impl std::fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
+
+impl std::fmt::Display for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_text_shares_storage() {
+        let built = format!("{}{}", "hel", "lo");
+
+        let first = Symbol::intern("hello");
+        let second = Symbol::from(built);
+
+        assert!(Rc::ptr_eq(&first.0, &second.0));
+        assert_eq!(first, second);
+        assert_ne!(first, Symbol::intern("world"));
+        assert_eq!("\"hello\"", format!("{:?}", first));
+    }
+
+    #[test]
+    fn unused_strings_are_purged() {
+        let kept = Symbol::intern("kept");
+
+        for i in 0..MIN_PURGE * 4 {
+            Symbol::intern(&format!("temporary {}", i));
+        }
+
+        let size = INTERNER.with(|interner| interner.borrow().strings.len());
+        assert!(size <= MIN_PURGE * 2, "{} strings interned", size);
+        assert_eq!(kept, Symbol::intern("kept"));
+    }
+}
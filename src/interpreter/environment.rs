@@ -0,0 +1,301 @@
+use std::cell::Cell;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::rc::Weak;
+
+use crate::error::Error;
+use crate::error::ErrorBuilder;
+use crate::intern::Symbol;
+use crate::interpreter::function::LoxFunction;
+use crate::interpreter::Value;
+use crate::lexer::token::Token;
+
+/// Variables of a scope, scopes are shared with the closures created inside them. A closure stored
+/// in a scope it captures makes a cycle reference counting can't free, [`Environment::collect`]
+/// does.
+pub struct Environment {
+    values: HashMap<Symbol, Value>,
+    enclosing: Option<Rc<RefCell<Environment>>>,
+    heap: Rc<Heap>,
+}
+
+/// Every scope made along with the first one, shared by all of them.
+#[derive(Default)]
+struct Heap {
+    /// Bytes taken by every scope alive.
+    bytes: Cell<usize>,
+    scopes: RefCell<Vec<Weak<RefCell<Environment>>>>,
+}
+
+const VARIABLE_SIZE: usize = std::mem::size_of::<(Symbol, Value)>();
+
+/// Bytes a variable holding the value takes, counting the text of strings.
+fn size(value: &Value) -> usize {
+    match value {
+        Value::String(string) => VARIABLE_SIZE + string.len(),
+        _ => VARIABLE_SIZE,
+    }
+}
+
+impl Environment {
+    pub fn new() -> Rc<RefCell<Self>> {
+        Self::with(None, Rc::default())
+    }
+
+    pub fn enclosed_by(enclosing: Rc<RefCell<Environment>>) -> Rc<RefCell<Self>> {
+        let heap = enclosing.borrow().heap.clone();
+
+        Self::with(Some(enclosing), heap)
+    }
+
+    fn with(enclosing: Option<Rc<RefCell<Environment>>>, heap: Rc<Heap>) -> Rc<RefCell<Self>> {
+        heap.bytes
+            .set(heap.bytes.get() + std::mem::size_of::<Self>());
+
+        let environment = Rc::new(RefCell::new(Self {
+            values: HashMap::new(),
+            enclosing,
+            heap: heap.clone(),
+        }));
+
+        heap.scopes.borrow_mut().push(Rc::downgrade(&environment));
+
+        environment
+    }
+
+    /// Bytes taken by this scope and every other one alive along with it.
+    pub fn bytes_alive(&self) -> usize {
+        self.heap.bytes.get()
+    }
+
+    /// Defining a variable again just replaces it.
+    pub fn define(&mut self, name: impl Into<Symbol>, value: Value) {
+        self.grow(size(&value));
+
+        if let Some(old) = self.values.insert(name.into(), value) {
+            self.shrink(size(&old));
+        }
+    }
+
+    /// The variable defined in this scope, without looking further up.
+    pub fn lookup(&self, name: &Symbol) -> Option<Value> {
+        self.values.get(name).cloned()
+    }
+
+    pub fn get(&self, name: &Token) -> Result<Value, Error> {
+        match (self.values.get(&name.lexeme), &self.enclosing) {
+            (Some(value), _) => Ok(value.clone()),
+            (None, Some(enclosing)) => enclosing.borrow().get(name),
+            (None, None) => Err(undefined(name)),
+        }
+    }
+
+    pub fn assign(&mut self, name: &Token, value: Value) -> Result<(), Error> {
+        if let Some(slot) = self.values.get_mut(&name.lexeme) {
+            let old = std::mem::replace(slot, value);
+            let new = size(slot);
+
+            self.grow(new);
+            self.shrink(size(&old));
+
+            return Ok(());
+        }
+
+        match &self.enclosing {
+            Some(enclosing) => enclosing.borrow_mut().assign(name, value),
+            None => Err(undefined(name)),
+        }
+    }
+}
+
+impl Environment {
+    fn grow(&self, bytes: usize) {
+        self.heap.bytes.set(self.heap.bytes.get() + bytes);
+    }
+
+    fn shrink(&self, bytes: usize) {
+        self.heap.bytes.set(self.heap.bytes.get() - bytes);
+    }
+
+    /// Frees the scopes made along with this one that can't be reached anymore, returning how many.
+    ///
+    /// Mark and sweep, with the roots found by counting: a scope or function held more times than
+    /// the heap itself holds it is held from outside, by the interpreter, a value being evaluated
+    /// or the host. Everything reachable from those is kept, the rest only keep each other alive
+    /// and are emptied, which breaks their cycles. No scope can be borrowed while collecting.
+    pub fn collect(environment: &Rc<RefCell<Self>>) -> usize {
+        let heap = environment.borrow().heap.clone();
+
+        let scopes = {
+            let mut all = heap.scopes.borrow_mut();
+            all.retain(|scope| scope.strong_count() > 0);
+            all.iter().filter_map(Weak::upgrade).collect::<Vec<_>>()
+        };
+
+        let index = scopes
+            .iter()
+            .enumerate()
+            .map(|(i, scope)| (Rc::as_ptr(scope), i))
+            .collect::<HashMap<_, _>>();
+
+        // Functions can be handed by the host to another interpreter, whose scopes aren't here.
+        let at = |scope: &Rc<RefCell<Environment>>| index.get(&Rc::as_ptr(scope)).copied();
+
+        // Holders of each scope besides `scopes`, then besides the heap.
+        let mut outside = scopes
+            .iter()
+            .map(|scope| Rc::strong_count(scope) - 1)
+            .collect::<Vec<_>>();
+
+        let mut functions = HashMap::<*const LoxFunction, (Rc<LoxFunction>, usize)>::new();
+
+        for scope in scopes.iter() {
+            let scope = scope.borrow();
+
+            if let Some(enclosing) = scope.enclosing.as_ref().and_then(at) {
+                outside[enclosing] -= 1;
+            }
+
+            for function in scope.values.values().filter_map(lox_function) {
+                functions
+                    .entry(Rc::as_ptr(function))
+                    .or_insert_with(|| (function.clone(), 0))
+                    .1 += 1;
+            }
+        }
+
+        for closure in functions
+            .values()
+            .filter_map(|(function, _)| at(&function.closure))
+        {
+            outside[closure] -= 1;
+        }
+
+        let mut marked = vec![false; scopes.len()];
+        let mut gray = (0..scopes.len())
+            .filter(|i| outside[*i] > 0)
+            .collect::<Vec<_>>();
+
+        // Functions held from outside keep the scope they were declared in.
+        gray.extend(
+            functions
+                .values()
+                .filter(|(function, held)| Rc::strong_count(function) - 1 > *held)
+                .filter_map(|(function, _)| at(&function.closure)),
+        );
+
+        while let Some(i) = gray.pop() {
+            if std::mem::replace(&mut marked[i], true) {
+                continue;
+            }
+
+            let scope = scopes[i].borrow();
+
+            gray.extend(
+                scope
+                    .enclosing
+                    .iter()
+                    .chain(
+                        scope
+                            .values
+                            .values()
+                            .filter_map(lox_function)
+                            .map(|function| &function.closure),
+                    )
+                    .filter_map(at),
+            );
+        }
+
+        drop(functions);
+
+        let mut freed = Vec::new();
+
+        for (scope, _) in scopes.iter().zip(marked).filter(|(_, marked)| !marked) {
+            let mut scope = scope.borrow_mut();
+            let values = std::mem::take(&mut scope.values);
+
+            scope.shrink(values.values().map(size).sum());
+            freed.push((values, scope.enclosing.take()));
+        }
+
+        // Only dropped once no scope is borrowed, as they may free other scopes.
+        let count = freed.len();
+        drop(freed);
+
+        count
+    }
+
+    /// The environment `distance` scopes up from this one.
+    fn ancestor(environment: &Rc<RefCell<Self>>, distance: usize) -> Rc<RefCell<Self>> {
+        let mut current = environment.clone();
+
+        for _ in 0..distance {
+            let enclosing = current
+                .borrow()
+                .enclosing
+                .clone()
+                .expect("resolved scopes exist at runtime");
+
+            current = enclosing;
+        }
+
+        current
+    }
+
+    pub fn get_at(
+        environment: &Rc<RefCell<Self>>,
+        distance: usize,
+        name: &Token,
+    ) -> Result<Value, Error> {
+        let ancestor = Self::ancestor(environment, distance);
+        let value = ancestor.borrow().values.get(&name.lexeme).cloned();
+
+        value.ok_or_else(|| undefined(name))
+    }
+
+    pub fn assign_at(
+        environment: &Rc<RefCell<Self>>,
+        distance: usize,
+        name: &Token,
+        value: Value,
+    ) -> Result<(), Error> {
+        let ancestor = Self::ancestor(environment, distance);
+        let mut ancestor = ancestor.borrow_mut();
+
+        match ancestor.values.get_mut(&name.lexeme) {
+            Some(slot) => {
+                let old = std::mem::replace(slot, value);
+                let new = size(slot);
+
+                ancestor.grow(new);
+                ancestor.shrink(size(&old));
+
+                Ok(())
+            }
+            None => Err(undefined(name)),
+        }
+    }
+}
+
+impl Drop for Environment {
+    fn drop(&mut self) {
+        let variables: usize = self.values.values().map(size).sum();
+
+        self.shrink(std::mem::size_of::<Self>() + variables);
+    }
+}
+
+fn lox_function(value: &Value) -> Option<&Rc<LoxFunction>> {
+    match value {
+        Value::Function(function) => Some(function),
+        _ => None,
+    }
+}
+
+fn undefined(name: &Token) -> Error {
+    ErrorBuilder::new()
+        .message(format!("Undefined variable '{}'", name.lexeme))
+        .section(name.section)
+        .build()
+}
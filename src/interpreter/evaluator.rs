@@ -1,27 +1,84 @@
+use std::cell::RefCell;
+use std::io::Write;
+use std::rc::Rc;
+
+use crate::error::Error;
+use crate::error::ErrorBuilder;
+use crate::intern::Symbol;
+use crate::interpreter::environment::Environment;
+use crate::interpreter::function::clock;
+use crate::interpreter::function::LoxFunction;
+use crate::interpreter::function::NativeFunction;
+use crate::interpreter::limits::InterpreterLimits;
+use crate::interpreter::limits::InterruptHandle;
+use crate::interpreter::native::IntoNative;
+use crate::interpreter::native::NativeResult;
+use crate::interpreter::resolver::Resolutions;
+use crate::interpreter::Value;
+use crate::lexer::token::Token;
 use crate::lexer::token::TokenKind;
+use crate::parser::ast::walk_expression;
+use crate::parser::ast::walk_statement;
+use crate::parser::ast::Assign;
 use crate::parser::ast::Binary;
+use crate::parser::ast::Block;
+use crate::parser::ast::Call;
 use crate::parser::ast::Expression;
+use crate::parser::ast::ExpressionStatement;
+use crate::parser::ast::Function;
+use crate::parser::ast::Get;
 use crate::parser::ast::Grouping;
+use crate::parser::ast::If;
 use crate::parser::ast::Literal;
+use crate::parser::ast::Logical;
+use crate::parser::ast::Print;
+use crate::parser::ast::Return;
+use crate::parser::ast::Set;
+use crate::parser::ast::Statement;
 use crate::parser::ast::SyntaxVisitor;
 use crate::parser::ast::Unary;
+use crate::parser::ast::Var;
+use crate::parser::ast::Variable;
 use crate::parser::ast::Visitable;
+use crate::parser::ast::While;
+use crate::text::Spanned;
+use crate::text::TextSection;
+use crate::vm::heap::GcConfig;
+
+/// Ways evaluation can stop early. `return` is one of them, it unwinds up to the function call.
+enum Unwind {
+    Error(Error),
+    Return(Value, TextSection),
+}
 
-// TODO: use actual error type
-type Error = String;
-type Result<T> = std::result::Result<T, Error>;
+impl From<Error> for Unwind {
+    fn from(error: Error) -> Self {
+        Unwind::Error(error)
+    }
+}
 
-#[derive(Default)]
-pub struct Evaluator {}
+type Result<T> = std::result::Result<T, Unwind>;
 
-#[derive(Debug)]
-pub struct ExpressionValue {
-    literal: Literal,
+pub struct Evaluator {
+    globals: Rc<RefCell<Environment>>,
+    environment: Rc<RefCell<Environment>>,
+    resolutions: Resolutions,
+    limits: InterpreterLimits,
+    /// Nodes evaluated in the current run.
+    steps: u64,
+    /// Calls currently running.
+    depth: usize,
+    interrupt: InterruptHandle,
+    /// Where `print` writes to.
+    output: Box<dyn Write>,
+    gc: GcConfig,
+    /// Bytes alive over which the next scope made collects first.
+    next_gc: usize,
 }
 
-impl ToString for ExpressionValue {
-    fn to_string(&self) -> String {
-        self.literal.to_string()
+impl Default for Evaluator {
+    fn default() -> Self {
+        Self::with_gc(GcConfig::default())
     }
 }
 
@@ -30,125 +87,819 @@ impl Evaluator {
         Self::default()
     }
 
-    pub fn eval(&mut self, expression: &Expression) -> Result<ExpressionValue> {
-        expression.accept(self)
+    /// An evaluator whose scopes are collected as configured.
+    pub fn with_gc(config: GcConfig) -> Self {
+        let globals = Environment::new();
+
+        globals
+            .borrow_mut()
+            .define("clock", Value::Native(Rc::new(clock.into_native("clock"))));
+
+        Self {
+            environment: globals.clone(),
+            globals,
+            resolutions: Resolutions::default(),
+            limits: InterpreterLimits::default(),
+            steps: 0,
+            depth: 0,
+            interrupt: InterruptHandle::default(),
+            output: Box::new(std::io::stdout()),
+            gc: config,
+            next_gc: config.initial_threshold,
+        }
+    }
+
+    /// Adds where the variables of the code about to run are declared, without it every variable
+    /// is looked up as a global.
+    pub fn resolve(&mut self, resolutions: Resolutions) {
+        self.resolutions.extend(resolutions);
+    }
+
+    /// Bounds what the code run from now on can use.
+    pub fn set_limits(&mut self, limits: InterpreterLimits) {
+        self.limits = limits;
+    }
+
+    /// Lets another thread stop the code running.
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.interrupt.clone()
+    }
+
+    /// Sends what `print` writes somewhere else than stdout.
+    pub fn set_output(&mut self, output: impl Write + 'static) {
+        self.output = Box::new(output);
+    }
+
+    /// The value of a global variable, if it's defined.
+    pub fn global(&self, name: &str) -> Option<Value> {
+        self.globals.borrow().lookup(&Symbol::intern(name))
+    }
+
+    /// Defines a global variable, replacing it if it already exists.
+    pub fn define_global(&mut self, name: &str, value: Value) {
+        self.globals.borrow_mut().define(name, value);
+    }
+
+    /// Defines a global function implemented in Rust, its arguments converted from the Lox values
+    /// it's called with.
+    pub fn define_native<Args>(&mut self, name: &str, function: impl IntoNative<Args>) {
+        let native = function.into_native(name);
+
+        self.define_global(name, Value::Native(Rc::new(native)));
+    }
+
+    /// Defines a global function implemented in Rust that takes the Lox values as they come. It's
+    /// only called with `arity` arguments.
+    pub fn define_raw_native(
+        &mut self,
+        name: &str,
+        arity: usize,
+        function: impl Fn(&[Value]) -> NativeResult<Value> + 'static,
+    ) {
+        let native = NativeFunction {
+            name: name.into(),
+            arity,
+            function: Box::new(function),
+        };
+
+        self.define_global(name, Value::Native(Rc::new(native)));
+    }
+
+    /// Calls a function from outside any script, errors point nowhere in the source.
+    pub fn call_function(
+        &mut self,
+        callee: Value,
+        arguments: Vec<Value>,
+    ) -> std::result::Result<Value, Error> {
+        self.steps = 0;
+
+        self.call(callee, arguments, TextSection::default())
+            .map_err(top_level)
+    }
+
+    pub fn eval(&mut self, expression: &Expression) -> std::result::Result<Value, Error> {
+        self.steps = 0;
+
+        expression.accept(self).map_err(top_level)
+    }
+
+    /// Runs the statements in the global scope, definitions stay around for the next call.
+    pub fn execute(&mut self, statements: &[Statement]) -> std::result::Result<(), Error> {
+        self.steps = 0;
+
+        statements
+            .iter()
+            .try_for_each(|statement| statement.accept(self).map(|_| ()))
+            .map_err(top_level)
+    }
+
+    /// Runs the statements in the given scope, restoring the current one afterwards even if they
+    /// fail.
+    fn execute_block(
+        &mut self,
+        statements: &[Statement],
+        environment: Rc<RefCell<Environment>>,
+    ) -> Result<Value> {
+        let previous = std::mem::replace(&mut self.environment, environment);
+
+        let result = statements
+            .iter()
+            .try_for_each(|statement| statement.accept(self).map(|_| ()));
+
+        self.environment = previous;
+
+        result.map(|_| Value::Nil)
+    }
+
+    /// Errors point at `section`, the closing parenthesis of the call.
+    fn call(
+        &mut self,
+        callee: Value,
+        arguments: Vec<Value>,
+        section: TextSection,
+    ) -> Result<Value> {
+        let arity = match &callee {
+            Value::Function(function) => function.arity(),
+            Value::Native(native) => native.arity,
+            _ => {
+                return Err(error_in("Can only call functions and classes", section).into());
+            }
+        };
+
+        if arity != arguments.len() {
+            return Err(error_in(
+                format!("Expected {} arguments but got {}", arity, arguments.len()),
+                section,
+            )
+            .into());
+        }
+
+        match callee {
+            Value::Function(function) => {
+                // The top level counts as a call, the same as in the VM.
+                if self.depth + 1 >= self.limits.max_call_depth {
+                    return Err(self.limits.call_depth_exceeded(section).into());
+                }
+
+                self.allocating();
+                let environment = Environment::enclosed_by(function.closure.clone());
+
+                for (param, argument) in function.declaration.params.iter().zip(arguments) {
+                    environment
+                        .borrow_mut()
+                        .define(param.lexeme.clone(), argument);
+                }
+
+                self.depth += 1;
+                let result = self.execute_block(&function.declaration.body.statements, environment);
+                self.depth -= 1;
+
+                match result {
+                    Err(Unwind::Return(value, _)) => Ok(value),
+                    other => other,
+                }
+            }
+            Value::Native(native) => {
+                (native.function)(&arguments).map_err(|message| error_in(message, section).into())
+            }
+            _ => unreachable!("only callables have an arity"),
+        }
+    }
+
+    /// Counts the node about to be evaluated against the limits.
+    fn step(&mut self, section: TextSection) -> Result<()> {
+        if self.interrupt.take() {
+            return Err(self.interrupt.interrupted(section).into());
+        }
+
+        self.steps += 1;
+
+        if self.limits.max_steps.is_some_and(|max| self.steps > max) {
+            return Err(self.limits.steps_exceeded(section).into());
+        }
+
+        if !self.fits(0) {
+            return Err(self.limits.heap_exceeded(section).into());
+        }
+
+        Ok(())
+    }
+
+    fn bytes_alive(&self) -> usize {
+        self.globals.borrow().bytes_alive()
+    }
+
+    /// Whether `bytes` more fit in the heap, collecting first if they don't.
+    fn fits(&mut self, bytes: usize) -> bool {
+        if self.limits.fits(self.bytes_alive(), bytes) {
+            return true;
+        }
+
+        self.collect_garbage();
+
+        self.limits.fits(self.bytes_alive(), bytes)
+    }
+
+    /// Called before making a scope or a string.
+    fn allocating(&mut self) {
+        if self.gc.stress || self.bytes_alive() > self.next_gc {
+            self.collect_garbage();
+        }
+    }
+
+    fn collect_garbage(&mut self) {
+        Environment::collect(&self.globals);
+
+        self.next_gc = (self.bytes_alive() * self.gc.growth_factor).max(self.gc.initial_threshold);
     }
 }
 
-impl SyntaxVisitor<Result<ExpressionValue>> for Evaluator {
-    fn visit_expression(&mut self, arg: &Expression) -> Result<ExpressionValue> {
-        match arg {
-            Expression::Binary(binary) => self.visit_binary(binary),
-            Expression::Unary(unary) => self.visit_unary(unary),
-            Expression::Grouping(grouping) => self.visit_grouping(grouping),
-            Expression::Literal(literal) => self.visit_literal(literal),
+impl SyntaxVisitor<Result<Value>> for Evaluator {
+    fn visit_statement(&mut self, statement: &Statement) -> Result<Value> {
+        self.step(statement.span())?;
+
+        walk_statement(self, statement)
+    }
+
+    fn visit_expression(&mut self, expression: &Expression) -> Result<Value> {
+        self.step(expression.span())?;
+
+        walk_expression(self, expression)
+    }
+
+    fn visit_expression_statement(&mut self, statement: &ExpressionStatement) -> Result<Value> {
+        statement.expression.accept(self)?;
+
+        Ok(Value::Nil)
+    }
+
+    fn visit_print(&mut self, print: &Print) -> Result<Value> {
+        let value = print.expression.accept(self)?;
+
+        writeln!(self.output, "{}", value)
+            .map_err(|e| error_at(format!("Failed printing: {}", e), &print.keyword))?;
+
+        Ok(Value::Nil)
+    }
+
+    fn visit_var(&mut self, var: &Var) -> Result<Value> {
+        let value = match &var.initializer {
+            Some(initializer) => initializer.accept(self)?,
+            None => Value::Nil,
+        };
+
+        self.environment
+            .borrow_mut()
+            .define(var.name.lexeme.clone(), value);
+
+        Ok(Value::Nil)
+    }
+
+    fn visit_block(&mut self, block: &Block) -> Result<Value> {
+        self.allocating();
+        let environment = Environment::enclosed_by(self.environment.clone());
+
+        self.execute_block(&block.statements, environment)
+    }
+
+    fn visit_if(&mut self, statement: &If) -> Result<Value> {
+        if statement.condition.accept(self)?.is_truthy() {
+            statement.then_branch.accept(self)?;
+        } else if let Some(else_branch) = &statement.else_branch {
+            else_branch.accept(self)?;
         }
+
+        Ok(Value::Nil)
+    }
+
+    fn visit_while(&mut self, statement: &While) -> Result<Value> {
+        while statement.condition.accept(self)?.is_truthy() {
+            statement.body.accept(self)?;
+        }
+
+        Ok(Value::Nil)
+    }
+
+    fn visit_function(&mut self, function: &Function) -> Result<Value> {
+        let value = Value::Function(Rc::new(LoxFunction {
+            declaration: Rc::new(function.clone()),
+            closure: self.environment.clone(),
+        }));
+
+        self.environment
+            .borrow_mut()
+            .define(function.name.lexeme.clone(), value);
+
+        Ok(Value::Nil)
+    }
+
+    fn visit_return(&mut self, statement: &Return) -> Result<Value> {
+        let value = match &statement.value {
+            Some(value) => value.accept(self)?,
+            None => Value::Nil,
+        };
+
+        Err(Unwind::Return(value, statement.keyword.section))
     }
 
-    fn visit_grouping(&mut self, grouping: &Grouping) -> Result<ExpressionValue> {
-        self.eval(&grouping.expression)
+    fn visit_grouping(&mut self, grouping: &Grouping) -> Result<Value> {
+        grouping.expression.accept(self)
     }
 
-    fn visit_binary(&mut self, binary: &Binary) -> Result<ExpressionValue> {
+    fn visit_binary(&mut self, binary: &Binary) -> Result<Value> {
         let lval = binary.left.accept(self)?;
         let rval = binary.right.accept(self)?;
 
-        let literal = match binary.operator.kind {
-                TokenKind::Minus => match (lval.literal, rval.literal) {
-                    (Literal::Number(left), Literal::Number(right)) => {
-                        Literal::Number(left - right)
-                    }
-                    (left, right) => return Err(
-                        format!(
-                            "Binary operator '{}' expects two numbers, instead got: left='{:?}' right='{:?}'",
-                            binary.operator.lexeme,
-                            left,
-                            right,
-                        )
-                    ),
-                },
+        let value = match binary.operator.kind {
+            TokenKind::Minus => {
+                let (left, right) = numbers(binary, lval, rval)?;
 
-                TokenKind::Slash => match (lval.literal, rval.literal) {
-                    (Literal::Number(left), Literal::Number(right)) => {
-                        Literal::Number(left / right)
-                    }
-                    (left, right) => return Err(
-                        format!(
-                            "Binary operator '{}' expects two numbers, instead got: left='{:?}' right='{:?}'",
-                            binary.operator.lexeme,
-                            left,
-                            right,
-                        )
-                    ),
-                },
+                Value::Number(left - right)
+            }
+            TokenKind::Slash => {
+                let (left, right) = numbers(binary, lval, rval)?;
 
-                TokenKind::Star => match (lval.literal, rval.literal) {
-                    (Literal::Number(left), Literal::Number(right)) => {
-                        Literal::Number(left * right)
-                    }
-                    (left, right) => return Err(
-                        format!(
-                            "Binary operator '{}' expects two numbers, instead got: left='{:?}' right='{:?}'",
-                            binary.operator.lexeme,
-                            left,
-                            right,
-                        )
-                    ),
-                },
+                Value::Number(left / right)
+            }
+            TokenKind::Star => {
+                let (left, right) = numbers(binary, lval, rval)?;
 
-                TokenKind::Plus => match (lval.literal, rval.literal) {
-                    (Literal::Number(left), Literal::Number(right)) => {
-                        Literal::Number(left + right)
-                    }
+                Value::Number(left * right)
+            }
+            TokenKind::Plus => match (lval, rval) {
+                (Value::Number(left), Value::Number(right)) => Value::Number(left + right),
+
+                (Value::String(left), Value::String(right)) => {
+                    self.allocating();
 
-                    (Literal::String(left), Literal::String(right)) => {
-                        Literal::String(format!("{}{}", left, right))
+                    if !self.fits(left.len() + right.len()) {
+                        return Err(self.limits.heap_exceeded(binary.operator.section).into());
                     }
 
-                    (left, right) => return Err(
-                        format!(
-                            "Binary operator '{}' expects two numbers or two strings, instead got: left='{:?}' right='{:?}'",
-                            binary.operator.lexeme,
-                            left,
-                            right,
-                        )
+                    Value::String(format!("{}{}", left, right).into())
+                }
+
+                (left, right) => return Err(error_at(
+                    format!(
+                        "Binary operator '{}' expects two numbers or two strings, instead got: left='{:?}' right='{:?}'",
+                        binary.operator.lexeme,
+                        left,
+                        right,
                     ),
-                },
+                    &binary.operator,
+                ).into()),
+            },
+            TokenKind::Greater => {
+                let (left, right) = numbers(binary, lval, rval)?;
+
+                Value::Bool(left > right)
+            }
+            TokenKind::GreaterEqual => {
+                let (left, right) = numbers(binary, lval, rval)?;
 
-                // Comparisons... and other boolean binary ops
+                Value::Bool(left >= right)
+            }
+            TokenKind::Less => {
+                let (left, right) = numbers(binary, lval, rval)?;
 
-                _ => return Err(format!("Binary operator {} not supported", binary.operator.lexeme)),
-            };
+                Value::Bool(left < right)
+            }
+            TokenKind::LessEqual => {
+                let (left, right) = numbers(binary, lval, rval)?;
 
-        Ok(ExpressionValue { literal })
+                Value::Bool(left <= right)
+            }
+            TokenKind::EqualEqual => Value::Bool(lval == rval),
+            TokenKind::BangEqual => Value::Bool(lval != rval),
+
+            _ => {
+                return Err(error_at(
+                    format!("Binary operator {} not supported", binary.operator.lexeme),
+                    &binary.operator,
+                )
+                .into())
+            }
+        };
+
+        Ok(value)
     }
 
-    fn visit_literal(&mut self, literal: &Literal) -> Result<ExpressionValue> {
-        Ok(ExpressionValue {
-            literal: literal.clone(),
-        })
+    fn visit_literal(&mut self, literal: &Literal) -> Result<Value> {
+        Ok(Value::from(literal))
     }
 
-    fn visit_unary(&mut self, unary: &Unary) -> Result<ExpressionValue> {
-        let rval = self.eval(&unary.expression)?.literal;
+    fn visit_unary(&mut self, unary: &Unary) -> Result<Value> {
+        let rval = unary.expression.accept(self)?;
 
-        let literal = match unary.operator.kind {
+        let value = match unary.operator.kind {
             TokenKind::Minus => match rval {
-                Literal::Number(num) => Literal::Number(-num),
-                _ => panic!(),
+                Value::Number(num) => Value::Number(-num),
+                _ => {
+                    return Err(error_at(
+                        format!(
+                            "Unary operator '{}' expects a number, instead got: '{:?}'",
+                            unary.operator.lexeme, rval
+                        ),
+                        &unary.operator,
+                    )
+                    .into())
+                }
             },
-            TokenKind::Bang => not(&rval),
+            TokenKind::Bang => Value::Bool(!rval.is_truthy()),
 
-            _ => panic!(),
+            _ => {
+                return Err(error_at(
+                    format!("Unary operator {} not supported", unary.operator.lexeme),
+                    &unary.operator,
+                )
+                .into())
+            }
         };
 
-        Ok(ExpressionValue { literal })
+        Ok(value)
+    }
+
+    fn visit_variable(&mut self, variable: &Variable) -> Result<Value> {
+        let value = match self.resolutions.depth(&variable.name) {
+            Some(depth) => Environment::get_at(&self.environment, depth, &variable.name)?,
+            None => self.globals.borrow().get(&variable.name)?,
+        };
+
+        Ok(value)
+    }
+
+    fn visit_assign(&mut self, assign: &Assign) -> Result<Value> {
+        let value = assign.value.accept(self)?;
+
+        match self.resolutions.depth(&assign.name) {
+            Some(depth) => {
+                Environment::assign_at(&self.environment, depth, &assign.name, value.clone())?
+            }
+            None => self
+                .globals
+                .borrow_mut()
+                .assign(&assign.name, value.clone())?,
+        }
+
+        Ok(value)
+    }
+
+    fn visit_logical(&mut self, logical: &Logical) -> Result<Value> {
+        let left = logical.left.accept(self)?;
+
+        let short_circuits = match logical.operator.kind {
+            TokenKind::Or => left.is_truthy(),
+            _ => !left.is_truthy(),
+        };
+
+        if short_circuits {
+            Ok(left)
+        } else {
+            logical.right.accept(self)
+        }
+    }
+
+    fn visit_call(&mut self, call: &Call) -> Result<Value> {
+        let callee = call.callee.accept(self)?;
+
+        let arguments = call
+            .arguments
+            .iter()
+            .map(|argument| argument.accept(self))
+            .collect::<Result<Vec<_>>>()?;
+
+        self.call(callee, arguments, call.paren.section)
+    }
+
+    fn visit_get(&mut self, get: &Get) -> Result<Value> {
+        match get.object.accept(self)? {
+            Value::Object(object) => object
+                .get(&get.name.lexeme)
+                .map_err(|message| error_at(message, &get.name).into()),
+            _ => Err(error_at("Only instances have properties", &get.name).into()),
+        }
+    }
+
+    fn visit_set(&mut self, set: &Set) -> Result<Value> {
+        let Value::Object(object) = set.object.accept(self)? else {
+            return Err(error_at("Only instances have fields", &set.name).into());
+        };
+
+        let value = set.value.accept(self)?;
+
+        object
+            .set(&set.name.lexeme, &value)
+            .map_err(|message| error_at(message, &set.name))?;
+
+        Ok(value)
+    }
+}
+
+/// Operands of an arithmetic or comparison operator, which only work on numbers.
+fn numbers(binary: &Binary, lval: Value, rval: Value) -> Result<(f64, f64)> {
+    match (lval, rval) {
+        (Value::Number(left), Value::Number(right)) => Ok((left, right)),
+        (left, right) => Err(error_at(
+            format!(
+                "Binary operator '{}' expects two numbers, instead got: left='{:?}' right='{:?}'",
+                binary.operator.lexeme, left, right,
+            ),
+            &binary.operator,
+        )
+        .into()),
     }
 }
 
-fn not(literal: &Literal) -> Literal {
-    match literal {
-        Literal::False | Literal::Nil => Literal::True,
-        _ => Literal::False, // everything is truthy but false
+fn top_level(unwind: Unwind) -> Error {
+    match unwind {
+        Unwind::Error(error) => error,
+        Unwind::Return(_, section) => ErrorBuilder::new()
+            .message("Can't return from top-level code")
+            .section(section)
+            .build(),
+    }
+}
+
+fn error_at(msg: impl Into<std::borrow::Cow<'static, str>>, token: &Token) -> Error {
+    error_in(msg, token.section)
+}
+
+fn error_in(msg: impl Into<std::borrow::Cow<'static, str>>, section: TextSection) -> Error {
+    ErrorBuilder::new().message(msg).section(section).build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorKind;
+    use crate::interpreter::Resolver;
+    use crate::lexer::Scanner;
+    use crate::parser::Parser;
+    use crate::text::SourceMap;
+
+    /// Runs the program and evaluates the expression afterwards, in the same global scope.
+    fn run(program: &str, expression: &str) -> std::result::Result<Value, Error> {
+        let mut sources = SourceMap::new();
+        let program = sources.add("test.lox", program);
+        let expression = sources.add("expression.lox", expression);
+
+        let mut evaluator = Evaluator::new();
+
+        let tokens = Scanner::new(sources.file(program)).scan_tokens().tokens;
+        let statements = Parser::new(tokens).parse_program().expect("valid program");
+        evaluator.resolve(
+            Resolver::new()
+                .resolve(&statements)
+                .expect("resolved program"),
+        );
+        evaluator.execute(&statements)?;
+
+        let tokens = Scanner::new(sources.file(expression)).scan_tokens().tokens;
+        evaluator.eval(&Parser::new(tokens).parse().expect("valid expression"))
+    }
+
+    #[test]
+    fn blocks_shadow_and_assign_outer_variables() {
+        let program = "var a = 1; var b = 1; { var a = 10; b = a + 1; }";
+
+        assert_eq!(Value::Number(12.0), run(program, "a + b").unwrap());
+    }
+
+    #[test]
+    fn loops_and_logical_operators() {
+        let program = "
+            var total = 0;
+            for (var i = 0; i < 5; i = i + 1) {
+                if (i == 2 or i == 4 and false) total = total + 100;
+                else total = total + i;
+            }";
+
+        assert_eq!(Value::Number(108.0), run(program, "total").unwrap());
+        assert_eq!(Value::String("x".into()), run("", "nil or \"x\"").unwrap());
+        assert_eq!(Value::Nil, run("", "nil and undefined").unwrap());
+    }
+
+    #[test]
+    fn functions_return_and_capture_their_scope() {
+        let program = "
+            fun fib(n) { if (n < 2) return n; return fib(n - 1) + fib(n - 2); }
+            fun counter() {
+                var count = 0;
+                fun increment() { count = count + 1; return count; }
+                return increment;
+            }
+            var next = counter();
+            next();";
+
+        assert_eq!(Value::Number(55.0), run(program, "fib(10)").unwrap());
+        assert_eq!(Value::Number(2.0), run(program, "next()").unwrap());
+        assert_eq!(Value::Bool(true), run(program, "fib == fib").unwrap());
+    }
+
+    /// Keeps `count` strings of about 1 KiB alive when `kept` is true, only the last one otherwise.
+    fn strings(kept: bool) -> String {
+        let keep = if kept {
+            "kept = keep(kept, base + piece);"
+        } else {
+            "kept = base + piece;"
+        };
+
+        format!(
+            "var base = \"x\";
+            for (var i = 0; i < 10; i = i + 1) base = base + base;
+            fun keep(previous, text) {{ fun link() {{ previous; return text; }} return link; }}
+            var kept = nil;
+            var piece = \"\";
+            for (var i = 0; i < 100; i = i + 1) {{ piece = piece + \"y\"; {} }}",
+            keep
+        )
+    }
+    #[test]
+    fn strings_alive_count_towards_the_heap() {
+        let run = |program: &str| {
+            let mut sources = SourceMap::new();
+            let file = sources.add("test.lox", program);
+
+            let tokens = Scanner::new(sources.file(file)).scan_tokens().tokens;
+            let statements = Parser::new(tokens).parse_program().expect("valid program");
+
+            let mut evaluator = Evaluator::new();
+            evaluator.resolve(Resolver::new().resolve(&statements).unwrap());
+            evaluator.set_limits(InterpreterLimits {
+                max_heap_bytes: Some(64 * 1024),
+                ..InterpreterLimits::default()
+            });
+
+            evaluator.execute(&statements)
+        };
+
+        let error = run(&strings(true)).unwrap_err();
+        assert_eq!(ErrorKind::HeapLimit, error.kind);
+
+        assert!(run(&strings(false)).is_ok());
+    }
+
+    #[test]
+    fn interrupts_stop_the_running_script() {
+        let mut sources = SourceMap::new();
+        let file = sources.add("test.lox", "while (true) {}");
+
+        let tokens = Scanner::new(sources.file(file)).scan_tokens().tokens;
+        let statements = Parser::new(tokens).parse_program().expect("valid program");
+
+        let mut evaluator = Evaluator::new();
+        let interrupt = evaluator.interrupt_handle();
+
+        std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            interrupt.interrupt();
+        });
+
+        let error = evaluator.execute(&statements).unwrap_err();
+        assert_eq!(ErrorKind::Interrupted, error.kind);
+
+        // The interrupt is spent, the next run goes on.
+        assert!(evaluator.execute(&[]).is_ok());
+    }
+
+    #[test]
+    fn runtime_errors_point_at_the_culprit() {
+        let cases = [
+            ("", "missing", "Undefined variable 'missing'", 1),
+            ("fun f(a) {}", "f()", "Expected 1 arguments but got 0", 3),
+            ("", "\"f\"()", "Can only call functions and classes", 5),
+            ("", "1 < \"2\"", "Binary operator '<' expects two numbers, instead got: left='Number(1.0)' right='String(\"2\")'", 3),
+        ];
+
+        for (program, expression, message, column) in cases {
+            let error = run(program, expression).expect_err(expression);
+
+            assert_eq!(message, error.message);
+            assert_eq!(column, error.section.start.column, "{}", expression);
+        }
+    }
+
+    #[test]
+    fn limits_stop_runaway_programs() {
+        let limited = |program: &str, limits: InterpreterLimits| {
+            let mut sources = SourceMap::new();
+            let file = sources.add("test.lox", program);
+
+            let tokens = Scanner::new(sources.file(file)).scan_tokens().tokens;
+            let statements = Parser::new(tokens).parse_program().expect("valid program");
+
+            let mut evaluator = Evaluator::new();
+            evaluator.resolve(Resolver::new().resolve(&statements).unwrap());
+            evaluator.set_limits(limits);
+
+            evaluator.execute(&statements).expect_err(program)
+        };
+
+        let error = limited(
+            "while (true) {}",
+            InterpreterLimits {
+                max_steps: Some(1000),
+                ..InterpreterLimits::default()
+            },
+        );
+        assert_eq!(ErrorKind::StepLimit, error.kind);
+        assert_eq!("Step limit of 1000 exceeded", error.message);
+
+        let error = limited(
+            "fun f(n) { return f(n + 1); } f(0);",
+            InterpreterLimits::default(),
+        );
+        assert_eq!(ErrorKind::CallDepth, error.kind);
+        assert_eq!(26, error.section.start.column);
+
+        let heap = InterpreterLimits {
+            max_heap_bytes: Some(64 * 1024),
+            ..InterpreterLimits::default()
+        };
+
+        let error = limited("var s = \"s\"; while (true) s = s + s;", heap);
+        assert_eq!(ErrorKind::HeapLimit, error.kind);
+        assert_eq!(33, error.section.start.column);
+
+        let chain = "
+            var chain = nil;
+            while (true) {
+                var previous = chain;
+                fun link() { return previous; }
+                chain = link;
+            }";
+        assert_eq!(ErrorKind::HeapLimit, limited(chain, heap).kind);
+    }
+
+    #[test]
+    fn closures_keep_the_variable_they_captured() {
+        let program = "
+            var a = \"global\";
+            var seen = \"\";
+            {
+                fun show() { seen = seen + a; }
+                show();
+                var a = \"block\";
+                show();
+            }";
+
+        assert_eq!(
+            Value::String("globalglobal".into()),
+            run(program, "seen").unwrap()
+        );
+    }
+
+    #[test]
+    fn scopes_only_reachable_from_each_other_are_collected() {
+        let run = |program: &str, config: GcConfig| {
+            let mut sources = SourceMap::new();
+            let file = sources.add("test.lox", program);
+            let count = sources.add("count.lox", "count");
+
+            let tokens = Scanner::new(sources.file(file)).scan_tokens().tokens;
+            let statements = Parser::new(tokens).parse_program().expect("valid program");
+
+            let mut evaluator = Evaluator::with_gc(config);
+            evaluator.resolve(Resolver::new().resolve(&statements).unwrap());
+            evaluator.execute(&statements)?;
+
+            let tokens = Scanner::new(sources.file(count)).scan_tokens().tokens;
+            let count = evaluator.eval(&Parser::new(tokens).parse().expect("valid expression"));
+
+            count.map(|count| (evaluator, count))
+        };
+
+        // Every block leaves behind a function holding the scope it is declared in.
+        let cycles = "
+            var count = 0;
+            for (var i = 0; i < 1000; i = i + 1) {
+                var text = \"y\";
+                fun again() { text; return again; }
+            }";
+
+        // Counting references alone would keep all of them.
+        let (mut evaluator, _) = run(cycles, GcConfig::default()).unwrap();
+        evaluator.collect_garbage();
+        let alive = evaluator.bytes_alive();
+        assert!(alive < 4 * 1024, "{} bytes alive", alive);
+
+        // Collecting all the time frees what can't be reached and nothing else.
+        let closures = "
+            fun counter() {
+                var count = 0;
+                fun increment() { count = count + 1; return count; }
+                return increment;
+            }
+            var next = counter();
+            next();
+            next();
+            var count = next();";
+        let stress = GcConfig {
+            stress: true,
+            ..GcConfig::default()
+        };
+
+        let (_, count) = run(closures, stress).unwrap();
+        assert_eq!(Value::Number(3.0), count);
     }
 }
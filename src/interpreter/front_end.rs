@@ -0,0 +1,103 @@
+use crate::error::ErrorList;
+use crate::interpreter::Optimizer;
+use crate::interpreter::Resolutions;
+use crate::interpreter::Resolver;
+use crate::lexer::token::Token;
+use crate::lexer::ScanResult;
+use crate::lexer::Scanner;
+use crate::parser::ast::Expression;
+use crate::parser::ast::Statement;
+use crate::parser::Parser;
+use crate::text::SourceFile;
+
+/// Code that went through the front end, ready for either backend, with where each of its
+/// variables is declared for the tree walker.
+pub struct Analyzed<T> {
+    pub code: T,
+    pub resolutions: Resolutions,
+}
+
+/// A line typed in a prompt, a lone expression has its value printed.
+pub enum Line {
+    Expression(Analyzed<Expression>),
+    Program(Analyzed<Vec<Statement>>),
+}
+
+/// Scans, parses, resolves and optimizes a program, stopping at the first stage with errors.
+pub fn analyze(file: &SourceFile) -> Result<Analyzed<Vec<Statement>>, ErrorList> {
+    program(scan(file)?)
+}
+
+/// Like [`analyze`], but a source that parses as a lone expression is kept as one.
+pub fn analyze_line(file: &SourceFile) -> Result<Line, ErrorList> {
+    let tokens = scan(file)?;
+
+    match Parser::new(tokens.clone()).parse() {
+        Ok(expression) => {
+            let resolutions = Resolver::new().resolve_expression(&expression)?;
+            let code = Optimizer::new().optimize_expression(expression)?;
+
+            Ok(Line::Expression(Analyzed { code, resolutions }))
+        }
+        Err(_) => program(tokens).map(Line::Program),
+    }
+}
+
+fn scan(file: &SourceFile) -> Result<Vec<Token>, ErrorList> {
+    let ScanResult { tokens, errors } = Scanner::new(file).scan_tokens();
+
+    if errors.size() > 0 {
+        return Err(errors);
+    }
+
+    Ok(tokens)
+}
+
+fn program(tokens: Vec<Token>) -> Result<Analyzed<Vec<Statement>>, ErrorList> {
+    let statements = Parser::new(tokens).parse_program()?;
+    let resolutions = Resolver::new().resolve(&statements)?;
+    let code = Optimizer::new().optimize(statements)?;
+
+    Ok(Analyzed { code, resolutions })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::ast::prefix_printer::PrefixPrinter;
+    use crate::text::SourceMap;
+
+    fn line(source: &str) -> Result<String, Vec<String>> {
+        let mut sources = SourceMap::new();
+        let file = sources.add("test.lox", source);
+
+        match analyze_line(sources.file(file)) {
+            Ok(Line::Expression(expression)) => Ok(PrefixPrinter::new().print(&expression.code)),
+            Ok(Line::Program(program)) => Ok(PrefixPrinter::new().print_program(&program.code)),
+            Err(errors) => Err(errors
+                .iter()
+                .map(|error| error.message.to_string())
+                .collect()),
+        }
+    }
+
+    #[test]
+    fn lone_expressions_stay_expressions() {
+        assert_eq!(Ok("3".to_string()), line("1 + 2"));
+        assert_eq!(Ok("(print 3)".to_string()), line("print 1 + 2;"));
+    }
+
+    #[test]
+    fn stops_at_the_first_stage_with_errors() {
+        let cases = [
+            ("print \"a;", "Unterminated string."),
+            ("print ;", "Unexpected token ';'"),
+            ("{ var a = a; }", "Can't read local variable in its own initializer"),
+            ("print \"a\" - 1;", "Binary operator '-' expects two numbers, instead got: left='String(\"a\")' right='Number(1.0)'"),
+        ];
+
+        for (source, message) in cases {
+            assert_eq!(Err(vec![message.to_string()]), line(source), "{}", source);
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::intern::Symbol;
+use crate::interpreter::environment::Environment;
+use crate::interpreter::NativeResult;
+use crate::interpreter::Value;
+use crate::parser::ast::Function;
+
+/// A function declared in Lox, along with the scope it was declared in.
+pub struct LoxFunction {
+    pub declaration: Rc<Function>,
+    pub closure: Rc<RefCell<Environment>>,
+}
+
+impl LoxFunction {
+    pub fn name(&self) -> &str {
+        &self.declaration.name.lexeme
+    }
+
+    pub fn arity(&self) -> usize {
+        self.declaration.params.len()
+    }
+}
+
+impl std::fmt::Debug for LoxFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "LoxFunction({})", self.name())
+    }
+}
+
+/// Body of a native function, taking the Lox values as they come.
+pub type NativeBody = Box<dyn Fn(&[Value]) -> NativeResult<Value>>;
+
+/// A function implemented in Rust, called with exactly `arity` arguments.
+pub struct NativeFunction {
+    pub name: Symbol,
+    pub arity: usize,
+    pub function: NativeBody,
+}
+
+impl std::fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "NativeFunction({})", self.name)
+    }
+}
+
+/// Seconds since the epoch, `clock()` in Lox.
+pub fn clock() -> NativeResult<f64> {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default();
+
+    Ok(now.as_secs_f64())
+}
@@ -0,0 +1,101 @@
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use crate::error::Error;
+use crate::error::ErrorBuilder;
+use crate::error::ErrorKind;
+use crate::text::TextSection;
+
+/// What running code is allowed to use, so a script fails with an error instead of hanging or
+/// taking the host down with it. Both backends take them, each error has its own [`ErrorKind`].
+#[derive(Clone, Copy, Debug)]
+pub struct InterpreterLimits {
+    /// Nodes the tree walker evaluates, or instructions the VM runs, in a single run.
+    pub max_steps: Option<u64>,
+    /// How deep calls can nest, there's always a limit since the tree walker recurses on the Rust
+    /// stack. The default of 256 fits in the 2 MiB stack of a spawned thread.
+    pub max_call_depth: usize,
+    /// Bytes the objects alive can take, scopes in the tree walker and the heap in the VM. Strings
+    /// count from when they're built, one longer than what's left fails, until they're dropped:
+    /// the tree walker counts them in every variable holding them, the VM until a collection finds
+    /// them unreachable.
+    pub max_heap_bytes: Option<usize>,
+}
+
+impl Default for InterpreterLimits {
+    fn default() -> Self {
+        Self {
+            max_steps: None,
+            max_call_depth: 256,
+            max_heap_bytes: None,
+        }
+    }
+}
+
+impl InterpreterLimits {
+    /// Whether a string of `length` bytes fits with `used` bytes already taken.
+    pub(crate) fn fits(&self, used: usize, length: usize) -> bool {
+        self.max_heap_bytes
+            .is_none_or(|max| used.saturating_add(length) <= max)
+    }
+
+    pub(crate) fn steps_exceeded(&self, section: TextSection) -> Error {
+        ErrorBuilder::new()
+            .message(format!(
+                "Step limit of {} exceeded",
+                self.max_steps.unwrap_or_default()
+            ))
+            .section(section)
+            .kind(ErrorKind::StepLimit)
+            .build()
+    }
+
+    pub(crate) fn call_depth_exceeded(&self, section: TextSection) -> Error {
+        ErrorBuilder::new()
+            .message("Stack overflow")
+            .section(section)
+            .kind(ErrorKind::CallDepth)
+            .build()
+    }
+
+    pub(crate) fn heap_exceeded(&self, section: TextSection) -> Error {
+        ErrorBuilder::new()
+            .message(format!(
+                "Heap limit of {} bytes exceeded",
+                self.max_heap_bytes.unwrap_or_default()
+            ))
+            .section(section)
+            .kind(ErrorKind::HeapLimit)
+            .build()
+    }
+}
+
+/// Stops a running script from another thread. The script fails with [`ErrorKind::Interrupted`]
+/// at the next step it takes, and the interrupt is cleared for the next run.
+#[derive(Clone, Debug, Default)]
+pub struct InterruptHandle(Arc<AtomicBool>);
+
+impl InterruptHandle {
+    pub fn interrupt(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Forgets an interrupt no script was running to see.
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+
+    /// Whether there's an interrupt, clearing it.
+    pub(crate) fn take(&self) -> bool {
+        self.0.load(Ordering::Relaxed) && self.0.swap(false, Ordering::Relaxed)
+    }
+
+    pub(crate) fn interrupted(&self, section: TextSection) -> Error {
+        ErrorBuilder::new()
+            .message("Interrupted")
+            .section(section)
+            .kind(ErrorKind::Interrupted)
+            .build()
+    }
+}
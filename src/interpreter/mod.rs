@@ -1,5 +1,43 @@
+mod environment;
 mod evaluator;
+mod front_end;
+mod function;
+mod limits;
+mod native;
+mod object;
+mod optimizer;
+mod resolver;
+mod session;
+mod value;
 
-pub fn eval(expr: &crate::parser::ast::Expression) -> Result<evaluator::ExpressionValue, String> {
+pub use ast_macros::lox_methods;
+pub use ast_macros::LoxObject;
+pub use evaluator::Evaluator;
+pub use front_end::analyze;
+pub use front_end::analyze_line;
+pub use front_end::Analyzed;
+pub use front_end::Line;
+pub use limits::InterpreterLimits;
+pub use limits::InterruptHandle;
+pub use native::FromValue;
+pub use native::IntoNative;
+pub use native::IntoValue;
+pub use native::NativeResult;
+#[doc(hidden)]
+pub use object::has_name;
+pub use object::Exclusive;
+pub use object::IntoMethod;
+pub use object::LoxMethods;
+pub use object::LoxObject;
+pub use object::Object;
+pub use object::Shared;
+pub use optimizer::Optimizer;
+pub use resolver::Resolution;
+pub use resolver::Resolutions;
+pub use resolver::Resolver;
+pub use session::Interpreter;
+pub use value::Value;
+
+pub fn eval(expr: &crate::parser::ast::Expression) -> Result<Value, crate::error::Error> {
     evaluator::Evaluator::new().eval(expr)
 }
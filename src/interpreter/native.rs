@@ -0,0 +1,146 @@
+use crate::intern::Symbol;
+use crate::interpreter::function::NativeFunction;
+use crate::interpreter::Value;
+
+/// What a native function returns, failing with a message reported where it was called.
+pub type NativeResult<T> = std::result::Result<T, String>;
+
+/// Rust types a native function can take as an argument.
+pub trait FromValue: Sized {
+    fn from_value(value: &Value) -> NativeResult<Self>;
+}
+
+/// Rust types a native function can return.
+pub trait IntoValue {
+    fn into_value(self) -> Value;
+}
+
+impl<T: Into<Value>> IntoValue for T {
+    fn into_value(self) -> Value {
+        self.into()
+    }
+}
+
+impl FromValue for Value {
+    fn from_value(value: &Value) -> NativeResult<Self> {
+        Ok(value.clone())
+    }
+}
+
+impl FromValue for f64 {
+    fn from_value(value: &Value) -> NativeResult<Self> {
+        match value {
+            Value::Number(number) => Ok(*number),
+            other => Err(mismatch("a number", other)),
+        }
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: &Value) -> NativeResult<Self> {
+        match value {
+            Value::Bool(boolean) => Ok(*boolean),
+            other => Err(mismatch("a boolean", other)),
+        }
+    }
+}
+
+impl FromValue for Symbol {
+    fn from_value(value: &Value) -> NativeResult<Self> {
+        match value {
+            Value::String(string) => Ok(string.clone()),
+            other => Err(mismatch("a string", other)),
+        }
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: &Value) -> NativeResult<Self> {
+        Symbol::from_value(value).map(|string| string.to_string())
+    }
+}
+
+/// `nil` is `None`, anything else has to convert to `T`.
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: &Value) -> NativeResult<Self> {
+        match value {
+            Value::Nil => Ok(None),
+            other => T::from_value(other).map(Some),
+        }
+    }
+}
+
+fn mismatch(expected: &str, got: &Value) -> String {
+    format!("Expected {} but got '{}'", expected, got)
+}
+
+/// Rust functions that can be called from Lox, `Args` being the tuple of their argument types.
+/// Implemented for closures of up to six [`FromValue`] arguments returning a [`NativeResult`] of
+/// something [`IntoValue`].
+pub trait IntoNative<Args> {
+    fn into_native(self, name: &str) -> NativeFunction;
+}
+
+macro_rules! into_native {
+    ($($arg:ident),*) => {
+        impl<Native, R, $($arg,)*> IntoNative<($($arg,)*)> for Native
+        where
+            Native: Fn($($arg),*) -> NativeResult<R> + 'static,
+            R: IntoValue,
+            $($arg: FromValue,)*
+        {
+            #[allow(unused_mut, unused_variables)]
+            fn into_native(self, name: &str) -> NativeFunction {
+                NativeFunction {
+                    name: name.into(),
+                    arity: <[&str]>::len(&[$(stringify!($arg)),*]),
+                    function: Box::new(move |arguments| {
+                        let mut arguments = arguments.iter();
+
+                        self($($arg::from_value(arguments.next().expect("arity is checked"))?),*)
+                            .map(IntoValue::into_value)
+                    }),
+                }
+            }
+        }
+    };
+}
+
+into_native!();
+into_native!(A);
+into_native!(A, B);
+into_native!(A, B, C);
+into_native!(A, B, C, D);
+into_native!(A, B, C, D, E);
+into_native!(A, B, C, D, E, F);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call<Args>(function: impl IntoNative<Args>, arguments: &[Value]) -> NativeResult<Value> {
+        let native = function.into_native("test");
+        assert_eq!(arguments.len(), native.arity);
+
+        (native.function)(arguments)
+    }
+
+    #[test]
+    fn arguments_are_converted_to_rust_types() {
+        let repeat = |text: String, times: f64| Ok(text.repeat(times as usize));
+
+        assert_eq!(
+            Ok(Value::from("abab")),
+            call(repeat, &[Value::from("ab"), Value::Number(2.0)])
+        );
+        assert_eq!(
+            Err("Expected a number but got 'true'".to_string()),
+            call(repeat, &[Value::from("ab"), Value::Bool(true)])
+        );
+
+        let or_zero = |number: Option<f64>| Ok(number.unwrap_or(0.0));
+
+        assert_eq!(Ok(Value::Number(0.0)), call(or_zero, &[Value::Nil]));
+        assert_eq!(Ok(Value::Nil), call(|| Ok(()), &[]));
+    }
+}
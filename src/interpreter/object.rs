@@ -0,0 +1,192 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::interpreter::function::NativeFunction;
+use crate::interpreter::native::FromValue;
+use crate::interpreter::native::IntoValue;
+use crate::interpreter::NativeResult;
+use crate::interpreter::Value;
+
+/// A host type scripts use like an instance of a class, with properties to get and set and methods
+/// to call. Usually derived, see `ast_macros::LoxObject`.
+pub trait LoxObject: LoxMethods + 'static {
+    fn class_name(&self) -> &'static str;
+
+    /// The value of the property, if there's one by that name.
+    fn property(&self, name: &str) -> Option<Value>;
+
+    /// Changes the property, `None` if there's no property by that name.
+    fn set_property(&mut self, name: &str, value: &Value) -> Option<NativeResult<()>>;
+}
+
+/// The methods of a [`LoxObject`] scripts can call, usually made by `#[lox_methods]` on the impl
+/// block defining them, see `ast_macros::lox_methods`. Objects without methods implement it empty.
+pub trait LoxMethods {
+    /// Arguments the method takes, if there's one by that name.
+    fn method_arity(&self, name: &str) -> Option<usize> {
+        let _ = name;
+
+        None
+    }
+
+    /// Only called with the arity of an existing method.
+    fn call_method(&mut self, name: &str, arguments: &[Value]) -> NativeResult<Value> {
+        let _ = arguments;
+
+        Err(undefined(name))
+    }
+}
+
+/// Whether the name is one of the names, for the checks `#[lox_methods]` makes while compiling.
+#[doc(hidden)]
+pub const fn has_name(names: &[&str], name: &str) -> bool {
+    let mut i = 0;
+
+    while i < names.len() {
+        let candidate = names[i].as_bytes();
+        let name = name.as_bytes();
+
+        if candidate.len() == name.len() {
+            let mut j = 0;
+
+            while j < name.len() && candidate[j] == name[j] {
+                j += 1;
+            }
+
+            if j == name.len() {
+                return true;
+            }
+        }
+
+        i += 1;
+    }
+
+    false
+}
+
+/// A host object shared by every value holding it. A method running has it borrowed, so getting
+/// or setting its properties from inside fails instead of panicking.
+#[derive(Clone)]
+pub struct Object {
+    /// Kept apart so printing never has to borrow the object.
+    class_name: &'static str,
+    inner: Rc<RefCell<dyn LoxObject>>,
+}
+
+impl Object {
+    pub fn new(object: impl LoxObject) -> Self {
+        Object {
+            class_name: object.class_name(),
+            inner: Rc::new(RefCell::new(object)),
+        }
+    }
+
+    pub fn class_name(&self) -> &'static str {
+        self.class_name
+    }
+
+    /// The property, or a method bound to this object.
+    pub fn get(&self, name: &str) -> NativeResult<Value> {
+        let object = self.inner.try_borrow().map_err(|_| in_use())?;
+
+        if let Some(value) = object.property(name) {
+            return Ok(value);
+        }
+
+        let arity = object.method_arity(name).ok_or_else(|| undefined(name))?;
+
+        let this = self.clone();
+        let method = name.to_string();
+
+        Ok(Value::Native(Rc::new(NativeFunction {
+            name: name.into(),
+            arity,
+            function: Box::new(move |arguments| {
+                this.inner
+                    .try_borrow_mut()
+                    .map_err(|_| in_use())?
+                    .call_method(&method, arguments)
+            }),
+        })))
+    }
+
+    pub fn set(&self, name: &str, value: &Value) -> NativeResult<()> {
+        self.inner
+            .try_borrow_mut()
+            .map_err(|_| in_use())?
+            .set_property(name, value)
+            .unwrap_or_else(|| Err(undefined(name)))
+    }
+}
+
+/// Objects are only equal to themselves.
+impl PartialEq for Object {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.inner, &other.inner)
+    }
+}
+
+impl std::fmt::Debug for Object {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Object({})", self.class_name())
+    }
+}
+
+fn undefined(name: &str) -> String {
+    format!("Undefined property '{}'", name)
+}
+
+fn in_use() -> String {
+    "Object is already in use".to_string()
+}
+
+/// Marks methods taking `&self`.
+pub struct Shared;
+
+/// Marks methods taking `&mut self`.
+pub struct Exclusive;
+
+/// Methods of `T` that can be called from Lox, `Args` being what they borrow `self` as followed by
+/// their argument types. Implemented for methods of up to six [`FromValue`] arguments returning a
+/// [`NativeResult`] of something [`IntoValue`].
+pub trait IntoMethod<T, Args> {
+    fn arity(&self) -> usize;
+
+    /// Only called with `arity` arguments.
+    fn call(&self, object: &mut T, arguments: &[Value]) -> NativeResult<Value>;
+}
+
+macro_rules! into_method {
+    ($($arg:ident),*) => {
+        into_method!(Shared, []; $($arg),*);
+        into_method!(Exclusive, [mut]; $($arg),*);
+    };
+    ($borrow:ident, [$($mutability:tt)?]; $($arg:ident),*) => {
+        impl<Method, T, R, $($arg,)*> IntoMethod<T, ($borrow, $($arg,)*)> for Method
+        where
+            Method: Fn(&$($mutability)? T, $($arg),*) -> NativeResult<R>,
+            R: IntoValue,
+            $($arg: FromValue,)*
+        {
+            fn arity(&self) -> usize {
+                <[&str]>::len(&[$(stringify!($arg)),*])
+            }
+
+            #[allow(unused_mut, unused_variables)]
+            fn call(&self, object: &mut T, arguments: &[Value]) -> NativeResult<Value> {
+                let mut arguments = arguments.iter();
+
+                self(&$($mutability)? *object, $($arg::from_value(arguments.next().expect("arity is checked"))?),*)
+                    .map(IntoValue::into_value)
+            }
+        }
+    };
+}
+
+into_method!();
+into_method!(A);
+into_method!(A, B);
+into_method!(A, B, C);
+into_method!(A, B, C, D);
+into_method!(A, B, C, D, E);
+into_method!(A, B, C, D, E, F);
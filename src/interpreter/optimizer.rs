@@ -0,0 +1,313 @@
+use std::rc::Rc;
+
+use crate::error::ErrorList;
+use crate::interpreter::Evaluator;
+use crate::interpreter::Value;
+use crate::lexer::token::TokenKind;
+use crate::parser::ast::walk_expression_fold;
+use crate::parser::ast::walk_statement_fold;
+use crate::parser::ast::Block;
+use crate::parser::ast::Expression;
+use crate::parser::ast::Fold;
+use crate::parser::ast::Literal;
+use crate::parser::ast::Logical;
+use crate::parser::ast::Statement;
+use crate::text::Spanned;
+use crate::text::TextSection;
+
+/// Simplifies the code before running it. Operators on literals are computed ahead of time, by the
+/// tree walker so they work the same as at runtime, and the branches constant conditions never
+/// take are dropped. Folded values span the whole expression they replace, and an operator that
+/// would fail at runtime is reported right away.
+///
+/// Runs after the [`Resolver`](crate::interpreter::Resolver), variables are left alone so their
+/// resolutions stay valid.
+pub struct Optimizer {
+    evaluator: Evaluator,
+    errors: ErrorList,
+}
+
+impl Default for Optimizer {
+    fn default() -> Self {
+        Self {
+            evaluator: Evaluator::new(),
+            errors: ErrorList::default(),
+        }
+    }
+}
+
+impl Optimizer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn optimize(mut self, statements: Vec<Statement>) -> Result<Vec<Statement>, ErrorList> {
+        let statements = self.fold_statements(statements);
+
+        self.finish(statements)
+    }
+
+    pub fn optimize_expression(mut self, expression: Expression) -> Result<Expression, ErrorList> {
+        let expression = self.fold_expression(expression);
+
+        self.finish(expression)
+    }
+
+    fn finish<T>(self, result: T) -> Result<T, ErrorList> {
+        if self.errors.size() > 0 {
+            Err(self.errors)
+        } else {
+            Ok(result)
+        }
+    }
+
+    /// Folds each statement, leaving out the ones that turned out to do nothing.
+    fn fold_statements(&mut self, statements: Vec<Statement>) -> Vec<Statement> {
+        statements
+            .into_iter()
+            .map(|statement| self.fold_statement(statement))
+            .filter(|statement| !is_nothing(statement))
+            .collect()
+    }
+
+    /// Computes the expression, made only of literals, as a literal spanning all of it.
+    fn compute(&mut self, expression: Expression) -> Expression {
+        let span = expression.span();
+
+        match self.evaluator.eval(&expression) {
+            Ok(value) => Expression::Literal(literal(value, span)),
+            Err(error) => {
+                self.errors.add(error);
+
+                expression
+            }
+        }
+    }
+
+    /// Folds the right side only if the left one doesn't decide the result, like running it.
+    fn short_circuit(&mut self, mut logical: Logical) -> Expression {
+        logical.left = Rc::new(self.fold_expression(unwrap(logical.left)));
+
+        let Expression::Literal(left) = &*logical.left else {
+            logical.right = Rc::new(self.fold_expression(unwrap(logical.right)));
+
+            return Expression::Logical(logical);
+        };
+
+        let short_circuits = match logical.operator.kind {
+            TokenKind::Or => Value::from(left).is_truthy(),
+            _ => !Value::from(left).is_truthy(),
+        };
+
+        if short_circuits {
+            Expression::Literal(literal(Value::from(left), logical.span))
+        } else {
+            self.fold_expression(unwrap(logical.right))
+        }
+    }
+}
+
+impl Fold for Optimizer {
+    fn fold_block(&mut self, block: Block) -> Block {
+        Block {
+            statements: self.fold_statements(block.statements),
+            span: block.span,
+        }
+    }
+
+    fn fold_statement(&mut self, statement: Statement) -> Statement {
+        // The condition goes first, a branch that never runs can't fail either.
+        match statement {
+            Statement::If(mut statement) => {
+                statement.condition = self.fold_expression(statement.condition);
+
+                match (constant(&statement.condition), statement.else_branch) {
+                    (Some(true), _) => self.fold_statement(unwrap(statement.then_branch)),
+                    (Some(false), Some(branch)) => self.fold_statement(unwrap(branch)),
+                    (Some(false), None) => nothing(statement.span),
+                    (None, else_branch) => {
+                        statement.then_branch =
+                            Rc::new(self.fold_statement(unwrap(statement.then_branch)));
+                        statement.else_branch =
+                            else_branch.map(|branch| Rc::new(self.fold_statement(unwrap(branch))));
+
+                        Statement::If(statement)
+                    }
+                }
+            }
+            Statement::While(mut statement) => {
+                statement.condition = self.fold_expression(statement.condition);
+
+                if constant(&statement.condition) == Some(false) {
+                    return nothing(statement.span);
+                }
+
+                statement.body = Rc::new(self.fold_statement(unwrap(statement.body)));
+
+                Statement::While(statement)
+            }
+            statement => walk_statement_fold(self, statement),
+        }
+    }
+
+    fn fold_expression(&mut self, expression: Expression) -> Expression {
+        if let Expression::Logical(logical) = expression {
+            return self.short_circuit(logical);
+        }
+
+        match walk_expression_fold(self, expression) {
+            Expression::Grouping(grouping) => match &*grouping.expression {
+                Expression::Literal(inner) => {
+                    Expression::Literal(literal(Value::from(inner), grouping.span))
+                }
+                _ => Expression::Grouping(grouping),
+            },
+            Expression::Unary(unary) if is_literal(&unary.expression) => {
+                self.compute(Expression::Unary(unary))
+            }
+            Expression::Binary(binary) if is_literal(&binary.left) && is_literal(&binary.right) => {
+                self.compute(Expression::Binary(binary))
+            }
+            expression => expression,
+        }
+    }
+}
+
+fn literal(value: Value, span: TextSection) -> Literal {
+    match value {
+        Value::Number(value) => Literal::Number(value, span),
+        Value::String(value) => Literal::String(value, span),
+        Value::Bool(true) => Literal::True(span),
+        Value::Bool(false) => Literal::False(span),
+        Value::Nil => Literal::Nil(span),
+        Value::Function(_) | Value::Native(_) | Value::Object(_) => {
+            unreachable!("operators on literals only make literals")
+        }
+    }
+}
+
+/// Whether the condition is always true or always false.
+fn constant(condition: &Expression) -> Option<bool> {
+    match condition {
+        Expression::Literal(literal) => Some(Value::from(literal).is_truthy()),
+        _ => None,
+    }
+}
+
+/// Stands for a statement that was dropped.
+fn nothing(span: TextSection) -> Statement {
+    Statement::Block(Block {
+        statements: Vec::new(),
+        span,
+    })
+}
+
+fn is_nothing(statement: &Statement) -> bool {
+    matches!(statement, Statement::Block(block) if block.statements.is_empty())
+}
+
+fn is_literal(expression: &Expression) -> bool {
+    matches!(expression, Expression::Literal(_))
+}
+
+/// Takes the node out of its pointer, cloning it only if it's shared.
+fn unwrap<T: Clone>(node: Rc<T>) -> T {
+    Rc::try_unwrap(node).unwrap_or_else(|shared| (*shared).clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Scanner;
+    use crate::parser::ast::prefix_printer::PrefixPrinter;
+    use crate::parser::Parser;
+    use crate::text::SourceMap;
+
+    fn optimize(program: &str) -> Result<String, Vec<(String, usize)>> {
+        let mut sources = SourceMap::new();
+        let file = sources.add("test.lox", program);
+
+        let tokens = Scanner::new(sources.file(file)).scan_tokens().tokens;
+        let statements = Parser::new(tokens).parse_program().expect("valid program");
+
+        match Optimizer::new().optimize(statements) {
+            Ok(statements) => Ok(PrefixPrinter::new().print_program(&statements)),
+            Err(errors) => Err(errors
+                .iter()
+                .map(|error| (error.message.to_string(), error.section.start.column))
+                .collect()),
+        }
+    }
+
+    #[test]
+    fn operators_on_literals_are_computed() {
+        let cases = [
+            ("print 1 + 2 * 3;", "(print 7)"),
+            ("print -(4 - 6) / 2;", "(print 1)"),
+            ("print \"a\" + \"b\" + \"c\";", "(print \"abc\")"),
+            ("print 1 < 2 == !nil;", "(print true)"),
+            ("print a + (1 + 2);", "(print (+ a 3))"),
+            ("print nil or a;", "(print a)"),
+            ("print 1 and a;", "(print a)"),
+            ("print false and a;", "(print false)"),
+        ];
+
+        for (program, expected) in cases {
+            assert_eq!(Ok(expected.to_string()), optimize(program), "{}", program);
+        }
+    }
+
+    #[test]
+    fn branches_never_taken_are_dropped() {
+        let cases = [
+            ("if (true) print 1; else print 2;", "(print 1)"),
+            ("if (1 > 2) print 1; else print 2;", "(print 2)"),
+            ("if (nil) print 1; print 3;", "(print 3)"),
+            ("while (false) print 1;", ""),
+            ("if (a) print 1 + 1;", "(if a (print 2))"),
+            ("if (false) print \"a\" - 1;", ""),
+            ("print false and \"a\" - 1;", "(print false)"),
+            ("print 1 or -\"a\";", "(print 1)"),
+            ("print a or 1 + 2;", "(print (or a 3))"),
+        ];
+
+        for (program, expected) in cases {
+            assert_eq!(Ok(expected.to_string()), optimize(program), "{}", program);
+        }
+    }
+
+    #[test]
+    fn folded_values_span_the_expression() {
+        let mut sources = SourceMap::new();
+        let file = sources.add("test.lox", "1 + (2 * 3)");
+
+        let tokens = Scanner::new(sources.file(file)).scan_tokens().tokens;
+        let expression = Parser::new(tokens).parse().expect("valid expression");
+        let span = expression.span();
+
+        let folded = Optimizer::new().optimize_expression(expression).unwrap();
+
+        assert!(matches!(folded, Expression::Literal(Literal::Number(value, _)) if value == 7.0));
+        assert_eq!(span.start.offset, folded.span().start.offset);
+        assert_eq!(span.end.offset, folded.span().end.offset);
+    }
+
+    #[test]
+    fn operators_that_would_fail_are_reported() {
+        assert_eq!(
+            Err(vec![(
+                "Binary operator '-' expects two numbers, instead got: left='String(\"a\")' right='Number(1.0)'"
+                    .to_string(),
+                16
+            )]),
+            optimize("print 1 + (\"a\" - 1);")
+        );
+        assert_eq!(
+            Err(vec![(
+                "Unary operator '-' expects a number, instead got: 'Bool(true)'".to_string(),
+                7
+            )]),
+            optimize("print -true;")
+        );
+    }
+}
@@ -0,0 +1,282 @@
+use std::collections::HashMap;
+
+use crate::error::Error;
+use crate::error::ErrorBuilder;
+use crate::error::ErrorList;
+use crate::intern::Symbol;
+use crate::lexer::token::Token;
+use crate::parser::ast::walk_assign_visit;
+use crate::parser::ast::walk_block_visit;
+use crate::parser::ast::walk_return_visit;
+use crate::parser::ast::walk_var_visit;
+use crate::parser::ast::Assign;
+use crate::parser::ast::Block;
+use crate::parser::ast::Expression;
+use crate::parser::ast::Function;
+use crate::parser::ast::Return;
+use crate::parser::ast::Statement;
+use crate::parser::ast::Var;
+use crate::parser::ast::Variable;
+use crate::parser::ast::Visit;
+use crate::text::FileId;
+
+/// A variable is identified by where its name is in the source.
+type Key = (FileId, usize);
+
+fn key(name: &Token) -> Key {
+    (name.section.file, name.section.start.offset)
+}
+
+/// What a variable use or assignment refers to.
+#[derive(Clone, Debug)]
+pub struct Resolution {
+    pub name: Token,
+    /// How many scopes up the variable is declared, `None` for globals.
+    pub depth: Option<usize>,
+}
+
+/// Where every variable use of the resolved code is declared.
+#[derive(Clone, Debug, Default)]
+pub struct Resolutions {
+    references: Vec<Resolution>,
+    depths: HashMap<Key, usize>,
+}
+
+impl Resolutions {
+    pub fn depth(&self, name: &Token) -> Option<usize> {
+        self.depths.get(&key(name)).copied()
+    }
+
+    /// Every reference in source order.
+    pub fn iter(&self) -> impl Iterator<Item = &Resolution> {
+        self.references.iter()
+    }
+
+    pub fn extend(&mut self, other: Resolutions) {
+        self.references.extend(other.references);
+        self.depths.extend(other.depths);
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum FunctionKind {
+    None,
+    Function,
+}
+
+/// Static pass binding each variable to the scope it's declared in, so closures keep seeing the
+/// variables they captured even if a new one with the same name is declared later on.
+pub struct Resolver {
+    /// Local scopes only, the variables are marked as defined once their initializer is done.
+    scopes: Vec<HashMap<Symbol, bool>>,
+    function: FunctionKind,
+    resolutions: Resolutions,
+    errors: ErrorList,
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Self {
+            scopes: Vec::new(),
+            function: FunctionKind::None,
+            resolutions: Resolutions::default(),
+            errors: ErrorList::default(),
+        }
+    }
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn resolve(mut self, statements: &[Statement]) -> Result<Resolutions, ErrorList> {
+        for statement in statements {
+            self.visit_statement(statement);
+        }
+
+        self.finish()
+    }
+
+    pub fn resolve_expression(mut self, expression: &Expression) -> Result<Resolutions, ErrorList> {
+        self.visit_expression(expression);
+
+        self.finish()
+    }
+
+    fn finish(self) -> Result<Resolutions, ErrorList> {
+        if self.errors.size() > 0 {
+            Err(self.errors)
+        } else {
+            Ok(self.resolutions)
+        }
+    }
+
+    fn declare(&mut self, name: &Token) {
+        let Some(scope) = self.scopes.last_mut() else {
+            return;
+        };
+
+        if scope.insert(name.lexeme.clone(), false).is_some() {
+            self.errors.add(error_at(
+                format!("Already a variable named '{}' in this scope", name.lexeme),
+                name,
+            ));
+        }
+    }
+
+    fn define(&mut self, name: &Token) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.lexeme.clone(), true);
+        }
+    }
+
+    fn resolve_local(&mut self, name: &Token) {
+        let depth = self
+            .scopes
+            .iter()
+            .rev()
+            .position(|scope| scope.contains_key(&name.lexeme));
+
+        if let Some(depth) = depth {
+            self.resolutions.depths.insert(key(name), depth);
+        }
+
+        self.resolutions.references.push(Resolution {
+            name: name.clone(),
+            depth,
+        });
+    }
+
+    fn resolve_function(&mut self, function: &Function) {
+        let enclosing = std::mem::replace(&mut self.function, FunctionKind::Function);
+        self.scopes.push(HashMap::new());
+
+        for param in function.params.iter() {
+            self.declare(param);
+            self.define(param);
+        }
+
+        for statement in function.body.statements.iter() {
+            self.visit_statement(statement);
+        }
+
+        self.scopes.pop();
+        self.function = enclosing;
+    }
+}
+
+impl Visit for Resolver {
+    fn visit_var(&mut self, var: &Var) {
+        self.declare(&var.name);
+        walk_var_visit(self, var);
+        self.define(&var.name);
+    }
+
+    fn visit_block(&mut self, block: &Block) {
+        self.scopes.push(HashMap::new());
+        walk_block_visit(self, block);
+        self.scopes.pop();
+    }
+
+    fn visit_function(&mut self, function: &Function) {
+        // Declared right away so the function can call itself.
+        self.declare(&function.name);
+        self.define(&function.name);
+
+        self.resolve_function(function);
+    }
+
+    fn visit_return(&mut self, statement: &Return) {
+        if self.function == FunctionKind::None {
+            self.errors.add(error_at(
+                "Can't return from top-level code",
+                &statement.keyword,
+            ));
+        }
+
+        walk_return_visit(self, statement);
+    }
+
+    fn visit_variable(&mut self, variable: &Variable) {
+        let declaring = self
+            .scopes
+            .last()
+            .and_then(|scope| scope.get(&variable.name.lexeme));
+
+        if declaring == Some(&false) {
+            self.errors.add(error_at(
+                "Can't read local variable in its own initializer",
+                &variable.name,
+            ));
+        }
+
+        self.resolve_local(&variable.name);
+    }
+
+    fn visit_assign(&mut self, assign: &Assign) {
+        walk_assign_visit(self, assign);
+
+        self.resolve_local(&assign.name);
+    }
+}
+
+fn error_at(msg: impl Into<std::borrow::Cow<'static, str>>, token: &Token) -> Error {
+    ErrorBuilder::new()
+        .message(msg)
+        .section(token.section)
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Scanner;
+    use crate::parser::Parser;
+    use crate::text::SourceMap;
+
+    fn resolve(source: &str) -> Result<Resolutions, ErrorList> {
+        let mut sources = SourceMap::new();
+        let file = sources.add("test.lox", source);
+
+        let tokens = Scanner::new(sources.file(file)).scan_tokens().tokens;
+        let statements = Parser::new(tokens).parse_program().expect("valid program");
+
+        Resolver::new().resolve(&statements)
+    }
+
+    #[test]
+    fn references_resolve_to_the_closest_declaration() {
+        let resolutions =
+            resolve("var a; fun f(b) { var c; { a; b; c = f; } }").expect("resolved program");
+
+        let depths = resolutions
+            .iter()
+            .map(|resolution| (resolution.name.lexeme.as_str(), resolution.depth))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            vec![("a", None), ("b", Some(1)), ("f", None), ("c", Some(1))],
+            depths
+        );
+    }
+
+    #[test]
+    fn misuses_of_scopes_are_reported() {
+        let errors = resolve("return; { var a = a; var b; var b; }").unwrap_err();
+
+        let messages = errors
+            .iter()
+            .map(|error| (error.message.as_ref(), error.section.start.column))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            vec![
+                ("Can't return from top-level code", 1),
+                ("Can't read local variable in its own initializer", 19),
+                ("Already a variable named 'b' in this scope", 33),
+            ],
+            messages
+        );
+    }
+}
@@ -0,0 +1,314 @@
+use std::io::Write;
+
+use crate::error::Error;
+use crate::error::ErrorBuilder;
+use crate::error::ErrorList;
+use crate::interpreter::analyze;
+use crate::interpreter::Analyzed;
+use crate::interpreter::Evaluator;
+use crate::interpreter::InterpreterLimits;
+use crate::interpreter::InterruptHandle;
+use crate::interpreter::IntoNative;
+use crate::interpreter::NativeResult;
+use crate::interpreter::Value;
+use crate::text::SourceMap;
+
+/// A tree-walking interpreter to embed in a host. Globals defined by one source stay around for
+/// the next one, and every source run is kept so errors can be located with [`Self::sources`].
+#[derive(Default)]
+pub struct Interpreter {
+    sources: SourceMap,
+    evaluator: Evaluator,
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Scans, parses, resolves and runs the source, stopping at the first stage with errors.
+    pub fn run_source(
+        &mut self,
+        name: impl Into<String>,
+        source: impl Into<String>,
+    ) -> Result<(), ErrorList> {
+        let file = self.sources.add(name, source);
+
+        let Analyzed { code, resolutions } = analyze(self.sources.file(file))?;
+        self.evaluator.resolve(resolutions);
+
+        Ok(self.evaluator.execute(&code)?)
+    }
+
+    /// The value of a global variable, if it's defined.
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        self.evaluator.global(name)
+    }
+
+    /// Defines a global variable for the scripts run from now on, replacing it if it exists.
+    pub fn set_global(&mut self, name: &str, value: impl Into<Value>) {
+        self.evaluator.define_global(name, value.into());
+    }
+
+    /// Lets scripts call a Rust function, like `interpreter.define_native("hypot", |x: f64, y: f64|
+    /// Ok(x.hypot(y)))`. Arguments of the wrong type fail the call.
+    pub fn define_native<Args>(&mut self, name: &str, function: impl IntoNative<Args>) {
+        self.evaluator.define_native(name, function);
+    }
+
+    /// Lets scripts call a Rust function taking the Lox values as they come, always `arity` of
+    /// them.
+    pub fn define_raw_native(
+        &mut self,
+        name: &str,
+        arity: usize,
+        function: impl Fn(&[Value]) -> NativeResult<Value> + 'static,
+    ) {
+        self.evaluator.define_raw_native(name, arity, function);
+    }
+
+    /// Calls the function stored in a global variable.
+    pub fn call(&mut self, function: &str, arguments: Vec<Value>) -> Result<Value, Error> {
+        let callee = self.get_global(function).ok_or_else(|| {
+            ErrorBuilder::new()
+                .message(format!("Undefined variable '{}'", function))
+                .build()
+        })?;
+
+        self.evaluator.call_function(callee, arguments)
+    }
+
+    /// Sends what `print` writes somewhere else than stdout.
+    pub fn set_output(&mut self, output: impl Write + 'static) {
+        self.evaluator.set_output(output);
+    }
+
+    /// Bounds what the scripts run from now on can use.
+    pub fn set_limits(&mut self, limits: InterpreterLimits) {
+        self.evaluator.set_limits(limits);
+    }
+
+    /// Lets another thread stop the script running.
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.evaluator.interrupt_handle()
+    }
+
+    /// Every source run so far, for locating errors.
+    pub fn sources(&self) -> &SourceMap {
+        &self.sources
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::interpreter::lox_methods;
+    use crate::interpreter::FromValue;
+    use crate::interpreter::LoxObject;
+
+    /// Output kept where the test can still read it.
+    #[derive(Clone, Default)]
+    struct Captured(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Captured {
+        fn text(&self) -> String {
+            String::from_utf8(self.0.borrow().clone()).expect("utf-8 output")
+        }
+    }
+
+    #[test]
+    fn globals_outlive_each_source() {
+        let mut interpreter = Interpreter::new();
+
+        interpreter.run_source("first.lox", "var a = 1;").unwrap();
+        interpreter.run_source("second.lox", "a = a + 1;").unwrap();
+
+        assert_eq!(Some(Value::Number(2.0)), interpreter.get_global("a"));
+        assert_eq!(None, interpreter.get_global("b"));
+    }
+
+    #[test]
+    fn hosts_set_globals_and_call_functions() {
+        let mut interpreter = Interpreter::new();
+        let output = Captured::default();
+        interpreter.set_output(output.clone());
+
+        interpreter.set_global("greeting", "hello");
+        interpreter
+            .run_source(
+                "test.lox",
+                "fun greet(name) { print greeting + \" \" + name; return name; }",
+            )
+            .unwrap();
+
+        let result = interpreter.call("greet", vec![Value::from("lox")]).unwrap();
+
+        assert_eq!(Value::from("lox"), result);
+        assert_eq!("hello lox\n", output.text());
+
+        let error = interpreter.call("greet", vec![]).unwrap_err();
+        assert_eq!("Expected 1 arguments but got 0", error.message);
+
+        let error = interpreter.call("missing", vec![]).unwrap_err();
+        assert_eq!("Undefined variable 'missing'", error.message);
+    }
+
+    #[test]
+    fn scripts_call_native_functions() {
+        let mut interpreter = Interpreter::new();
+
+        interpreter.define_native("starts_with", |text: String, prefix: String| {
+            Ok(text.starts_with(&prefix))
+        });
+        interpreter.define_raw_native("first", 2, |arguments| Ok(arguments[0].clone()));
+
+        interpreter
+            .run_source(
+                "test.lox",
+                "var a = starts_with(\"lox\", \"l\"); var b = first(1, 2);",
+            )
+            .unwrap();
+
+        assert_eq!(Some(Value::Bool(true)), interpreter.get_global("a"));
+        assert_eq!(Some(Value::Number(1.0)), interpreter.get_global("b"));
+
+        let errors = interpreter
+            .run_source("test.lox", "starts_with(\"lox\", 1);")
+            .unwrap_err();
+        let error = errors.iter().next().unwrap();
+
+        assert_eq!(
+            "[test.lox:1:21] Error: Expected a string but got '1'",
+            error.located(interpreter.sources()).to_string()
+        );
+
+        let errors = interpreter.run_source("test.lox", "first(1);").unwrap_err();
+        let error = errors.iter().next().unwrap();
+
+        assert_eq!("Expected 2 arguments but got 1", error.message);
+    }
+
+    #[derive(LoxObject)]
+    struct Rectangle {
+        width: f64,
+        height: f64,
+        #[lox(readonly)]
+        label: String,
+        #[lox(skip)]
+        #[allow(dead_code)]
+        scaled: usize,
+    }
+
+    #[lox_methods]
+    impl Rectangle {
+        #[lox]
+        fn area(&self) -> NativeResult<f64> {
+            Ok(self.width * self.height)
+        }
+
+        #[lox]
+        fn scale(&mut self, by: f64) -> NativeResult<()> {
+            self.width *= by;
+            self.height *= by;
+            self.scaled += 1;
+
+            Ok(())
+        }
+
+        #[lox]
+        fn beside(&self, other: Value) -> NativeResult<String> {
+            Ok(format!("{} beside {}", self.label, other))
+        }
+
+        /// Puts the other rectangle on top, reading its properties.
+        #[lox]
+        fn stack(&mut self, other: Value) -> NativeResult<()> {
+            let Value::Object(other) = other else {
+                return Err("Expected a rectangle".to_string());
+            };
+
+            let height = f64::from_value(&other.get("height")?)?;
+            self.height += height;
+
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn scripts_use_host_objects() {
+        let mut interpreter = Interpreter::new();
+        let output = Captured::default();
+        interpreter.set_output(output.clone());
+
+        interpreter.set_global(
+            "r",
+            Rectangle {
+                width: 2.0,
+                height: 3.0,
+                label: "box".to_string(),
+                scaled: 0,
+            },
+        );
+
+        interpreter
+            .run_source(
+                "test.lox",
+                "print r; print r.label; r.width = 4; r.scale(2); print r.area(); print r.beside(r);",
+            )
+            .unwrap();
+
+        assert_eq!(
+            "Rectangle instance\nbox\n48\nbox beside Rectangle instance\n",
+            output.text()
+        );
+
+        let cases = [
+            (
+                "r.label = \"other\";",
+                "Can't set read-only property 'label'",
+            ),
+            ("r.width = \"wide\";", "Expected a number but got 'wide'"),
+            ("r.scaled;", "Undefined property 'scaled'"),
+            ("r.scale();", "Expected 1 arguments but got 0"),
+            ("1.5.x;", "Only instances have properties"),
+            ("r.stack(r);", "Object is already in use"),
+        ];
+
+        for (source, message) in cases {
+            let errors = interpreter.run_source("test.lox", source).unwrap_err();
+
+            assert_eq!(message, errors.iter().next().unwrap().message, "{}", source);
+        }
+    }
+
+    #[test]
+    fn errors_come_from_the_failing_stage() {
+        let mut interpreter = Interpreter::new();
+
+        let errors = interpreter.run_source("test.lox", "var = 1;").unwrap_err();
+        assert_eq!(1, errors.size());
+
+        let errors = interpreter
+            .run_source("test.lox", "print nope;")
+            .unwrap_err();
+        let error = errors.iter().next().unwrap();
+
+        assert_eq!(
+            "[test.lox:1:7] Error: Undefined variable 'nope'",
+            error.located(interpreter.sources()).to_string()
+        );
+    }
+}
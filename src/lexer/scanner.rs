@@ -2,6 +2,7 @@ use crate::error::ErrorBuilder;
 use crate::error::ErrorList;
 use crate::lexer::token::Token;
 use crate::lexer::token::TokenKind;
+use crate::text::SourceFile;
 use crate::text::TextCursor;
 
 pub struct Scanner {
@@ -15,9 +16,9 @@ pub struct ScanResult {
 }
 
 impl Scanner {
-    pub fn new(source: String) -> Self {
+    pub fn new(source: &SourceFile) -> Self {
         Self {
-            cursor: TextCursor::new(&source),
+            cursor: TextCursor::new(source.id(), source.text()),
         }
     }
 
@@ -37,6 +38,7 @@ impl Scanner {
             }
         }
 
+        self.cursor.new_section();
         tokens.push(self.add_context(TokenKind::Eof));
 
         ScanResult { tokens, errors }
@@ -98,7 +100,7 @@ impl Scanner {
             lexeme: if kind == TokenKind::Eof {
                 "EOF".into()
             } else {
-                self.cursor.section_slice().iter().collect()
+                self.cursor.section_slice().into()
             },
             kind,
             section: self.cursor.section(),
@@ -114,11 +116,11 @@ impl Scanner {
         }
 
         // skip opening '"' char
-        let string = self.cursor.section_slice()[1..].iter().collect();
+        let string = self.cursor.section_slice()[1..].to_string();
         // consume closing '"' char
         self.cursor.consume();
 
-        Ok(TokenKind::String(string))
+        Ok(TokenKind::String(string.into()))
     }
 
     fn scan_slash_or_comment(&mut self) -> Result<TokenKind, ErrorBuilder> {
@@ -163,7 +165,8 @@ impl Scanner {
     fn scan_number(&mut self) -> TokenKind {
         self.cursor.consume_while(is_digit);
 
-        if self.cursor.match_next('.') {
+        // A dot not followed by digits is property access, as in `1.x`.
+        if self.cursor.match_next('.') && self.cursor.lookahead().is_some_and(|c| is_digit(&c)) {
             self.cursor.consume();
             self.cursor.consume_while(is_digit);
         }
@@ -184,13 +187,13 @@ impl Scanner {
 
         match TokenKind::keyword_token(&text) {
             Some(token) => token,
-            None => TokenKind::Identifier(text),
+            None => TokenKind::Identifier(text.into()),
         }
     }
 }
 
 fn is_digit(c: &char) -> bool {
-    matches!(c, '0'..='9')
+    c.is_ascii_digit()
 }
 
 fn is_alpha(c: &char) -> bool {
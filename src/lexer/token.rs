@@ -1,12 +1,20 @@
+use crate::intern::Symbol;
+use crate::text::Spanned;
 use crate::text::TextSection;
 
 #[derive(Clone, Debug)]
 pub struct Token {
     pub kind: TokenKind,
-    pub lexeme: String, // Cow?
+    pub lexeme: Symbol,
     pub section: TextSection,
 }
 
+impl Spanned for Token {
+    fn span(&self) -> TextSection {
+        self.section
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum TokenKind {
     /// Single character tokens
@@ -35,8 +43,8 @@ pub enum TokenKind {
     LessEqual,
 
     /// Literals
-    Identifier(String),
-    String(String),
+    Identifier(Symbol),
+    String(Symbol),
     Number(f64),
 
     /// Keywords
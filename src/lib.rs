@@ -1,6 +1,11 @@
+// Lets the derives in `ast_macros` name this crate as `jrlox` from inside it too.
+extern crate self as jrlox;
+
 pub mod error;
+pub mod ffi;
+pub mod intern;
 pub mod interpreter;
 pub mod lexer;
 pub mod parser;
-
-mod text;
+pub mod text;
+pub mod vm;
@@ -1,25 +1,313 @@
 use std::env;
 use std::io::Write;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::Context;
 use anyhow::Result;
+use jrlox::error::Error;
+use jrlox::error::ErrorList;
+use jrlox::interpreter::analyze;
+use jrlox::interpreter::analyze_line;
+use jrlox::interpreter::Analyzed;
+use jrlox::interpreter::Evaluator;
+use jrlox::interpreter::InterruptHandle;
+use jrlox::interpreter::Line;
+use jrlox::interpreter::Resolutions;
+use jrlox::lexer::token::Token;
+use jrlox::lexer::ScanResult;
+use jrlox::lexer::Scanner;
+use jrlox::parser::ast::prefix_printer::PrefixPrinter;
+use jrlox::parser::ast::tree_printer::TreePrinter;
+use jrlox::parser::ast::Expression;
+use jrlox::parser::ast::Statement;
+use jrlox::parser::ast::ToJson;
+use jrlox::text::FileId;
+use jrlox::text::SourceMap;
+use jrlox::vm::compiler::Compiler;
+use jrlox::vm::disassembler::disassemble;
+use jrlox::vm::heap::GcConfig;
+use jrlox::vm::loxc;
+use jrlox::vm::Vm;
+
+const USAGE: &str = "Usage: jrlox [--backend=tree|vm] [--trace-execution] [--gc-stress] [--timeout=<seconds>] [file]
+       jrlox compile <file> [-o <output>]
+       jrlox dump [--tokens] [--ast[=prefix|tree|json]] [--resolutions] [--bytecode] <file>
+
+Without a file it opens a prompt, where Ctrl-C stops the line running and Ctrl-D exits.";
 
 fn main() -> Result<()> {
-    let mut args = env::args();
+    match parse_args(env::args().skip(1))? {
+        Command::Prompt(options) => run_prompt(&options),
+        Command::Run(options, file) => run_file(&options, file),
+        Command::Compile(file, output) => compile(file, output),
+        Command::Dump(options, file) => dump(&options, file),
+    }
+}
+
+enum Command {
+    Prompt(RunOptions),
+    Run(RunOptions, String),
+    Compile(String, String),
+    Dump(DumpOptions, String),
+}
+
+struct RunOptions {
+    backend: BackendKind,
+    trace_execution: bool,
+    gc_stress: bool,
+    /// Scripts running longer than this are interrupted.
+    timeout: Option<Duration>,
+}
+
+impl RunOptions {
+    fn gc(&self) -> GcConfig {
+        GcConfig {
+            stress: self.gc_stress,
+            ..GcConfig::default()
+        }
+    }
+
+    fn vm(&self) -> Vm {
+        let mut vm = Vm::with_gc(self.gc());
+        vm.trace_execution(self.trace_execution);
+
+        vm
+    }
+}
+
+/// Which interpreter runs the code, the tree walker or the bytecode VM.
+#[derive(Clone, Copy)]
+enum BackendKind {
+    Tree,
+    Vm,
+}
+
+enum Backend {
+    Tree(Evaluator),
+    Vm(Box<Vm>),
+}
+
+impl Backend {
+    fn new(options: &RunOptions) -> Self {
+        match options.backend {
+            BackendKind::Tree => Backend::Tree(Evaluator::with_gc(options.gc())),
+            BackendKind::Vm => Backend::Vm(Box::new(options.vm())),
+        }
+    }
+
+    /// The VM compiles variables to slots on its own, only the tree walker needs resolving.
+    fn resolve(&mut self, resolutions: Resolutions) {
+        if let Backend::Tree(evaluator) = self {
+            evaluator.resolve(resolutions)
+        }
+    }
+
+    /// Evaluates the expression, returning its value printed.
+    fn eval(&mut self, expression: &Expression) -> Result<String, Error> {
+        match self {
+            Backend::Tree(evaluator) => evaluator.eval(expression).map(|value| value.to_string()),
+            Backend::Vm(vm) => vm.eval(expression).map(|value| vm.display(&value)),
+        }
+    }
+
+    fn execute(&mut self, statements: &[Statement]) -> Result<(), Error> {
+        match self {
+            Backend::Tree(evaluator) => evaluator.execute(statements),
+            Backend::Vm(vm) => vm.execute(statements),
+        }
+    }
+
+    fn interrupt_handle(&self) -> InterruptHandle {
+        match self {
+            Backend::Tree(evaluator) => evaluator.interrupt_handle(),
+            Backend::Vm(vm) => vm.interrupt_handle(),
+        }
+    }
+}
+
+/// Interrupts the script once the timeout is over, unless the returned sender is dropped before.
+fn watchdog(timeout: Option<Duration>, interrupt: InterruptHandle) -> Option<mpsc::Sender<()>> {
+    let timeout = timeout?;
+    let (done, finished) = mpsc::channel();
+
+    std::thread::spawn(move || {
+        if let Err(mpsc::RecvTimeoutError::Timeout) = finished.recv_timeout(timeout) {
+            interrupt.interrupt();
+        }
+    });
+
+    Some(done)
+}
+
+/// What `jrlox dump` prints, everything unless some of it is asked for.
+struct DumpOptions {
+    tokens: bool,
+    ast: Option<AstFormat>,
+    resolutions: bool,
+    bytecode: bool,
+}
+
+#[derive(Clone, Copy)]
+enum AstFormat {
+    Prefix,
+    Tree,
+    Json,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Command> {
+    let mut options = RunOptions {
+        backend: BackendKind::Tree,
+        trace_execution: false,
+        gc_stress: false,
+        timeout: None,
+    };
+    let mut file = None;
+
+    match args.next() {
+        Some(command) if command == "compile" => {
+            let mut output = None;
+
+            while let Some(arg) = args.next() {
+                match arg.as_str() {
+                    "-o" => {
+                        let path = args
+                            .next()
+                            .with_context(|| format!("Missing output file after -o\n{}", USAGE))?;
+
+                        output = Some(path);
+                    }
+                    flag if flag.starts_with('-') => {
+                        anyhow::bail!("Unknown option '{}'\n{}", flag, USAGE)
+                    }
+                    _ if file.is_none() => file = Some(arg),
+                    _ => anyhow::bail!("Wrong number of arguments\n{}", USAGE),
+                }
+            }
+
+            let file = file.with_context(|| format!("Missing file to compile\n{}", USAGE))?;
+            let output = output.unwrap_or_else(|| {
+                std::path::Path::new(&file)
+                    .with_extension("loxc")
+                    .to_string_lossy()
+                    .into_owned()
+            });
+
+            return Ok(Command::Compile(file, output));
+        }
+        Some(command) if command == "dump" => {
+            let mut options = DumpOptions {
+                tokens: false,
+                ast: None,
+                resolutions: false,
+                bytecode: false,
+            };
+            let mut file = None;
+
+            for arg in args.by_ref() {
+                match arg.as_str() {
+                    "--tokens" => options.tokens = true,
+                    "--ast" | "--ast=tree" => options.ast = Some(AstFormat::Tree),
+                    "--ast=prefix" => options.ast = Some(AstFormat::Prefix),
+                    "--ast=json" => options.ast = Some(AstFormat::Json),
+                    "--resolutions" => options.resolutions = true,
+                    "--bytecode" => options.bytecode = true,
+                    flag if flag.starts_with("--") => {
+                        anyhow::bail!("Unknown option '{}'\n{}", flag, USAGE)
+                    }
+                    _ if file.is_none() => file = Some(arg),
+                    _ => anyhow::bail!("Wrong number of arguments\n{}", USAGE),
+                }
+            }
+
+            if !options.tokens && options.ast.is_none() && !options.resolutions && !options.bytecode
+            {
+                options = DumpOptions {
+                    tokens: true,
+                    ast: Some(AstFormat::Tree),
+                    resolutions: true,
+                    bytecode: true,
+                };
+            }
+
+            let file = file.with_context(|| format!("Missing file to dump\n{}", USAGE))?;
+
+            return Ok(Command::Dump(options, file));
+        }
+        first => {
+            for arg in first.into_iter().chain(args) {
+                match arg.as_str() {
+                    "--backend=tree" => options.backend = BackendKind::Tree,
+                    "--backend=vm" => options.backend = BackendKind::Vm,
+                    "--trace-execution" => options.trace_execution = true,
+                    "--gc-stress" => options.gc_stress = true,
+                    flag if flag.starts_with("--timeout=") => {
+                        let seconds = flag["--timeout=".len()..]
+                            .parse::<f64>()
+                            .ok()
+                            .filter(|seconds| *seconds > 0.0 && seconds.is_finite())
+                            .with_context(|| format!("Invalid timeout '{}'\n{}", flag, USAGE))?;
+
+                        options.timeout = Some(Duration::from_secs_f64(seconds));
+                    }
+                    flag if flag.starts_with("--") => {
+                        anyhow::bail!("Unknown option '{}'\n{}", flag, USAGE)
+                    }
+                    _ if file.is_none() => file = Some(arg),
+                    _ => anyhow::bail!("Wrong number of arguments\n{}", USAGE),
+                }
+            }
+        }
+    }
+
+    if options.trace_execution && matches!(options.backend, BackendKind::Tree) {
+        anyhow::bail!(
+            "Only the VM can trace execution, use --backend=vm\n{}",
+            USAGE
+        );
+    }
 
-    match (args.next(), args.next(), args.next()) {
-        (Some(_), None, _) => run_prompt(),
-        (Some(_), Some(file), None) => run_file(file),
-        (_, _, Some(_)) => anyhow::bail!("Wrong number of arguments"),
-        _ => panic!("impossible"),
+    match file {
+        Some(file) => Ok(Command::Run(options, file)),
+        None => Ok(Command::Prompt(options)),
     }
 }
 
-fn run_prompt() -> Result<()> {
+fn run_prompt(options: &RunOptions) -> Result<()> {
+    let mut sources = SourceMap::new();
+    let mut backend = Backend::new(options);
+    let mut line_number = 0;
+
+    // Ctrl-C stops the line running instead of the whole session, with nothing running it tells
+    // how to leave.
+    let running = Arc::new(AtomicBool::new(false));
+    let interrupt = backend.interrupt_handle();
+    let handler_running = running.clone();
+    ctrlc::set_handler(move || {
+        if handler_running.load(Ordering::SeqCst) {
+            interrupt.interrupt();
+        } else {
+            print!("\nNothing is running, press Ctrl-D to exit\n> ");
+            let _ = std::io::stdout().flush();
+        }
+    })
+    .context("Fatal error handling Ctrl-C")?;
+
     while let Some(line) = prompt()? {
-        match run(line) {
-            Ok(_) => (),
-            Err(e) => eprintln!("{}", e),
+        line_number += 1;
+
+        let file = sources.add(format!("<repl:{}>", line_number), line);
+        backend.interrupt_handle().reset();
+
+        running.store(true, Ordering::SeqCst);
+        let result = run(&sources, file, &mut backend, true, options.timeout);
+        running.store(false, Ordering::SeqCst);
+
+        if let Err(e) = result {
+            eprintln!("{}", e);
         }
     }
 
@@ -48,35 +336,181 @@ fn prompt() -> Result<Option<String>> {
     }
 }
 
-fn run_file(file: String) -> Result<()> {
-    let content = std::fs::read_to_string(file).context("Fatal error reading file")?;
+/// Prints the tokens of the file, and the code the backends get from the front end, for debugging
+/// it.
+fn dump(options: &DumpOptions, file: String) -> Result<()> {
+    let content = std::fs::read_to_string(&file).context("Fatal error reading file")?;
 
-    // TODO: Add some timers here just for curiosity
-    run(content)?;
+    let mut sources = SourceMap::new();
+    let file = sources.add(file, content);
+
+    if options.tokens {
+        println!("== tokens ==");
+
+        let ScanResult { tokens, errors } = Scanner::new(sources.file(file)).scan_tokens();
+
+        for token in tokens.iter() {
+            println!("{}", dump_token(token));
+        }
+
+        if errors.size() > 0 {
+            errors.print(&sources);
+
+            anyhow::bail!("Compilation failed due to {} errors", errors.size());
+        }
+    }
+
+    if options.ast.is_none() && !options.resolutions && !options.bytecode {
+        return Ok(());
+    }
+
+    let Analyzed { code, resolutions } = reported(&sources, analyze(sources.file(file)))?;
+
+    if let Some(format) = options.ast {
+        println!("== ast ==");
+
+        match format {
+            AstFormat::Prefix => println!("{}", PrefixPrinter::new().print_program(&code)),
+            AstFormat::Tree => print!("{}", TreePrinter::new().print_program(&code)),
+            AstFormat::Json => println!("{}", code.to_json()),
+        }
+    }
+
+    if options.resolutions {
+        println!("== resolutions ==");
+
+        for resolution in resolutions.iter() {
+            let start = resolution.name.section.start;
+
+            match resolution.depth {
+                Some(depth) => println!(
+                    "{}:{} {} -> local, depth {}",
+                    start.line, start.column, resolution.name.lexeme, depth
+                ),
+                None => println!(
+                    "{}:{} {} -> global",
+                    start.line, start.column, resolution.name.lexeme
+                ),
+            }
+        }
+    }
+
+    if options.bytecode {
+        println!("== bytecode ==");
+
+        match Compiler::compile(&code) {
+            Ok(script) => print!("{}", disassemble(&script)),
+            Err(e) => anyhow::bail!("Compilation failed: {}", e.located(&sources)),
+        }
+    }
 
     Ok(())
 }
 
-fn run(code: String) -> Result<()> {
-    let mut scanner = jrlox::lexer::Scanner::new(code);
-    let jrlox::lexer::ScanResult { tokens, errors } = scanner.scan_tokens();
+fn dump_token(token: &Token) -> String {
+    format!(
+        "{}:{} {:?} {}",
+        token.section.start.line, token.section.start.column, token.kind, token.lexeme
+    )
+}
 
-    if errors.size() > 0 {
-        errors.print();
+/// Prints the errors the front end found, if any.
+fn reported<T>(sources: &SourceMap, result: std::result::Result<T, ErrorList>) -> Result<T> {
+    result.or_else(|errors| {
+        errors.print(sources);
 
         anyhow::bail!("Compilation failed due to {} errors", errors.size());
+    })
+}
+
+/// Compiles the file to bytecode and saves it, so it can be run later without parsing it.
+fn compile(file: String, output: String) -> Result<()> {
+    let content = std::fs::read_to_string(&file).context("Fatal error reading file")?;
+
+    let mut sources = SourceMap::new();
+    let file = sources.add(file, content);
+
+    let Analyzed { code, .. } = reported(&sources, analyze(sources.file(file)))?;
+
+    let script = match Compiler::compile(&code) {
+        Ok(script) => script,
+        Err(e) => anyhow::bail!("Compilation failed: {}", e.located(&sources)),
+    };
+
+    std::fs::write(&output, loxc::write(&script, &sources)).context("Fatal error writing file")?;
+
+    Ok(())
+}
+
+fn run_file(options: &RunOptions, file: String) -> Result<()> {
+    let content = std::fs::read(&file).context("Fatal error reading file")?;
+
+    let mut sources = SourceMap::new();
+
+    // Compiled scripts can only run on the VM.
+    if content.starts_with(loxc::MAGIC) {
+        let script = loxc::read(&content, &mut sources)
+            .map_err(|e| anyhow::anyhow!("Failed loading '{}': {}", file, e.message))?;
+
+        let mut vm = options.vm();
+        let _watchdog = watchdog(options.timeout, vm.interrupt_handle());
+
+        if let Err(e) = vm.run_compiled(script) {
+            anyhow::bail!("Runtime error encountered: {}", e.located(&sources));
+        }
+
+        return Ok(());
     }
 
-    let parser = jrlox::parser::Parser::new(tokens);
+    let content = String::from_utf8(content).context("Fatal error reading file")?;
+    let file = sources.add(file, content);
+
+    // TODO: Add some timers here just for curiosity
+    run(
+        &sources,
+        file,
+        &mut Backend::new(options),
+        false,
+        options.timeout,
+    )?;
+
+    Ok(())
+}
 
-    let expression = parser
-        .parse()
-        .map_err(|e| anyhow::anyhow!("{}", e.to_string()))?;
+/// With `echo` a lone expression is evaluated and its value printed, like the REPL does, anything
+/// else is run as a program. Running longer than `timeout` interrupts it.
+fn run(
+    sources: &SourceMap,
+    file: FileId,
+    backend: &mut Backend,
+    echo: bool,
+    timeout: Option<Duration>,
+) -> Result<()> {
+    let _watchdog = watchdog(timeout, backend.interrupt_handle());
 
-    match jrlox::interpreter::eval(&expression) {
-        Ok(result) => println!("{}", result.to_string()),
-        Err(e) => anyhow::bail!("Runtime error encountered: {}", e),
+    let line = if echo {
+        analyze_line(sources.file(file))
+    } else {
+        analyze(sources.file(file)).map(Line::Program)
     };
 
+    match reported(sources, line)? {
+        Line::Expression(Analyzed { code, resolutions }) => {
+            backend.resolve(resolutions);
+
+            match backend.eval(&code) {
+                Ok(result) => println!("{}", result),
+                Err(e) => anyhow::bail!("Runtime error encountered: {}", e.located(sources)),
+            }
+        }
+        Line::Program(Analyzed { code, resolutions }) => {
+            backend.resolve(resolutions);
+
+            if let Err(e) = backend.execute(&code) {
+                anyhow::bail!("Runtime error encountered: {}", e.located(sources));
+            }
+        }
+    }
+
     Ok(())
 }
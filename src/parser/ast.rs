@@ -1,14 +1,53 @@
+use crate::intern::Symbol;
 use crate::lexer::token::Token;
+use crate::lexer::token::TokenKind;
+use crate::text::Spanned;
+use crate::text::TextSection;
 use ast_macros::grammar;
 
 grammar! {
+    #![pointer(Rc)]
+
+    extern Token, Symbol, f64;
+
+    Statement => ExpressionStatement
+        | Print
+        | Var
+        | Block
+        | If
+        | While
+        | Function
+        | Return;
+
+    ExpressionStatement => expression: Expression;
+
+    Print => keyword: Token, expression: Expression;
+
+    Var => name: Token, initializer: Option<Expression>;
+
+    Block => statements: Vec<Statement>;
+
+    If => condition: Expression, then_branch: Statement, else_branch: Option<Statement>;
+
+    While => condition: Expression, body: Statement;
+
+    Function => name: Token, params: Vec<Token>, body: Block;
+
+    Return => keyword: Token, value: Option<Expression>;
+
     Expression => Binary
         | Unary
         | Grouping
-        | Literal;
+        | Literal
+        | Variable
+        | Assign
+        | Logical
+        | Call
+        | Get
+        | Set;
 
     Literal => Number as f64
-        | String
+        | String as Symbol
         | @True
         | @False
         | @Nil;
@@ -18,20 +57,106 @@ grammar! {
     Unary => operator: Token, expression: Expression;
 
     Grouping => expression: Expression;
+
+    Variable => name: Token;
+
+    Assign => name: Token, value: Expression;
+
+    Logical => left: Expression, operator: Token, right: Expression;
+
+    Call => callee: Expression, paren: Token, arguments: Vec<Expression>;
+
+    Get => object: Expression, name: Token;
+
+    Set => object: Expression, name: Token, value: Expression;
 }
 
-impl ToString for Literal {
-    fn to_string(&self) -> String {
-        match self {
-            Literal::Number(value) => value.to_string(),
-            Literal::String(value) => format!("\"{}\"", value),
-            Literal::True => String::from("true"),
-            Literal::False => String::from("false"),
-            Literal::Nil => String::from("nil"),
-        }
+/// Tokens are the same when their text is, wherever they are.
+impl SyntaxEq for Token {
+    fn syntax_eq(&self, other: &Self) -> bool {
+        self.kind == other.kind && self.lexeme == other.lexeme
+    }
+}
+
+impl ToSexp for Token {
+    fn write_sexp(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(&self.lexeme)
+    }
+
+    /// Only names and operators tell something the node doesn't, `print` or `)` don't.
+    fn skip_sexp(&self) -> bool {
+        !matches!(
+            self.kind,
+            TokenKind::Identifier(_)
+                | TokenKind::Minus
+                | TokenKind::Plus
+                | TokenKind::Slash
+                | TokenKind::Star
+                | TokenKind::Bang
+                | TokenKind::BangEqual
+                | TokenKind::Equal
+                | TokenKind::EqualEqual
+                | TokenKind::Greater
+                | TokenKind::GreaterEqual
+                | TokenKind::Less
+                | TokenKind::LessEqual
+                | TokenKind::And
+                | TokenKind::Or
+        )
+    }
+}
+
+impl ToJson for Token {
+    fn write_json(&self, out: &mut String) {
+        out.push_str("{\"lexeme\":");
+        write_json_string(&self.lexeme, out);
+        out.push_str(",\"span\":");
+        self.section.write_json(out);
+        out.push('}');
+    }
+}
+
+impl SyntaxEq for Symbol {
+    fn syntax_eq(&self, other: &Self) -> bool {
+        self == other
+    }
+}
+
+impl ToSexp for Symbol {
+    fn write_sexp(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let mut quoted = String::new();
+        write_json_string(self, &mut quoted);
+
+        f.write_str(&quoted)
+    }
+}
+
+impl ToJson for Symbol {
+    fn write_json(&self, out: &mut String) {
+        write_json_string(self, out)
     }
 }
 
+impl ToJson for TextSection {
+    fn write_json(&self, out: &mut String) {
+        let position = |position: crate::text::Position| {
+            format!(
+                "{{\"line\":{},\"column\":{},\"offset\":{}}}",
+                position.line, position.column, position.offset
+            )
+        };
+
+        out.push_str(&format!(
+            "{{\"start\":{},\"end\":{}}}",
+            position(self.start),
+            position(self.end)
+        ));
+    }
+}
+
+pub mod infix_printer;
+pub mod rpn_printer;
+
 pub mod prefix_printer {
     use super::*;
 
@@ -46,16 +171,121 @@ pub mod prefix_printer {
         pub fn print(&mut self, expression: &Expression) -> String {
             expression.accept(self)
         }
+
+        /// Prints every statement in its own line.
+        pub fn print_program(&mut self, statements: &[Statement]) -> String {
+            statements
+                .iter()
+                .map(|statement| statement.accept(self))
+                .collect::<Vec<_>>()
+                .join("\n")
+        }
+
+        fn join<'a, T: Visitable<String> + 'a>(
+            &mut self,
+            nodes: impl IntoIterator<Item = &'a T>,
+        ) -> String {
+            nodes
+                .into_iter()
+                .map(|node| format!(" {}", node.accept(self)))
+                .collect()
+        }
     }
 
     impl SyntaxVisitor<String> for PrefixPrinter {
-        fn visit_expression(&mut self, arg: &Expression) -> String {
-            match arg {
-                Expression::Binary(binary) => self.visit_binary(binary),
-                Expression::Unary(unary) => self.visit_unary(unary),
-                Expression::Grouping(grouping) => self.visit_grouping(grouping),
-                Expression::Literal(literal) => self.visit_literal(literal),
-            }
+        fn visit_expression_statement(&mut self, statement: &ExpressionStatement) -> String {
+            format!("(; {})", statement.expression.accept(self))
+        }
+
+        fn visit_print(&mut self, print: &Print) -> String {
+            format!("(print {})", print.expression.accept(self))
+        }
+
+        fn visit_var(&mut self, var: &Var) -> String {
+            format!(
+                "(var {}{})",
+                var.name.lexeme,
+                self.join(var.initializer.as_ref())
+            )
+        }
+
+        fn visit_block(&mut self, block: &Block) -> String {
+            format!("(block{})", self.join(&block.statements))
+        }
+
+        fn visit_if(&mut self, statement: &If) -> String {
+            format!(
+                "(if {} {}{})",
+                statement.condition.accept(self),
+                statement.then_branch.accept(self),
+                self.join(statement.else_branch.as_deref())
+            )
+        }
+
+        fn visit_while(&mut self, statement: &While) -> String {
+            format!(
+                "(while {} {})",
+                statement.condition.accept(self),
+                statement.body.accept(self)
+            )
+        }
+
+        fn visit_function(&mut self, function: &Function) -> String {
+            let params = function
+                .params
+                .iter()
+                .map(|param| param.lexeme.as_str())
+                .collect::<Vec<_>>()
+                .join(" ");
+
+            format!(
+                "(fun {} ({}) {})",
+                function.name.lexeme,
+                params,
+                function.body.accept(self)
+            )
+        }
+
+        fn visit_return(&mut self, statement: &Return) -> String {
+            format!("(return{})", self.join(statement.value.as_ref()))
+        }
+
+        fn visit_variable(&mut self, variable: &Variable) -> String {
+            variable.name.lexeme.to_string()
+        }
+
+        fn visit_assign(&mut self, assign: &Assign) -> String {
+            format!("(= {} {})", assign.name.lexeme, assign.value.accept(self))
+        }
+
+        fn visit_logical(&mut self, logical: &Logical) -> String {
+            format!(
+                "({} {} {})",
+                logical.operator.lexeme,
+                logical.left.accept(self),
+                logical.right.accept(self),
+            )
+        }
+
+        fn visit_call(&mut self, call: &Call) -> String {
+            format!(
+                "(call {}{})",
+                call.callee.accept(self),
+                self.join(&call.arguments)
+            )
+        }
+
+        fn visit_get(&mut self, get: &Get) -> String {
+            format!("(. {} {})", get.object.accept(self), get.name.lexeme)
+        }
+
+        fn visit_set(&mut self, set: &Set) -> String {
+            format!(
+                "(= (. {} {}) {})",
+                set.object.accept(self),
+                set.name.lexeme,
+                set.value.accept(self)
+            )
         }
 
         fn visit_grouping(&mut self, grouping: &Grouping) -> String {
@@ -82,49 +312,308 @@ pub mod prefix_printer {
             )
         }
     }
+}
+
+pub mod tree_printer {
+    use super::*;
+
+    /// Prints a node per line, children indented under their parent and every node followed by
+    /// where it starts.
+    #[derive(Default)]
+    pub struct TreePrinter {
+        out: String,
+        depth: usize,
+    }
+
+    impl TreePrinter {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        pub fn print(mut self, expression: &Expression) -> String {
+            expression.accept(&mut self);
+
+            self.out
+        }
+
+        pub fn print_program(mut self, statements: &[Statement]) -> String {
+            for statement in statements {
+                statement.accept(&mut self);
+            }
+
+            self.out
+        }
+
+        fn node(&mut self, label: impl std::fmt::Display, span: TextSection) {
+            self.out.push_str(&format!(
+                "{:indent$}{} @{}:{}\n",
+                "",
+                label,
+                span.start.line,
+                span.start.column,
+                indent = self.depth * 2
+            ));
+        }
+
+        fn children<'a, T: Visitable<()> + 'a>(&mut self, nodes: impl IntoIterator<Item = &'a T>) {
+            self.depth += 1;
+
+            for node in nodes {
+                node.accept(self);
+            }
+
+            self.depth -= 1;
+        }
+    }
+
+    impl SyntaxVisitor<()> for TreePrinter {
+        fn visit_expression_statement(&mut self, statement: &ExpressionStatement) {
+            self.node("ExpressionStatement", statement.span);
+            self.children([&statement.expression]);
+        }
+
+        fn visit_print(&mut self, print: &Print) {
+            self.node("Print", print.span);
+            self.children([&print.expression]);
+        }
+
+        fn visit_var(&mut self, var: &Var) {
+            self.node(format!("Var {}", var.name.lexeme), var.span);
+            self.children(var.initializer.as_ref());
+        }
+
+        fn visit_block(&mut self, block: &Block) {
+            self.node("Block", block.span);
+            self.children(&block.statements);
+        }
+
+        fn visit_if(&mut self, statement: &If) {
+            self.node("If", statement.span);
+            self.children([&statement.condition]);
+            self.children(
+                std::iter::once(&*statement.then_branch).chain(statement.else_branch.as_deref()),
+            );
+        }
+
+        fn visit_while(&mut self, statement: &While) {
+            self.node("While", statement.span);
+            self.children([&statement.condition]);
+            self.children([&*statement.body]);
+        }
+
+        fn visit_function(&mut self, function: &Function) {
+            let params = function
+                .params
+                .iter()
+                .map(|param| param.lexeme.as_str())
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            self.node(
+                format!("Function {}({})", function.name.lexeme, params),
+                function.span,
+            );
+            self.children(&function.body.statements);
+        }
+
+        fn visit_return(&mut self, statement: &Return) {
+            self.node("Return", statement.span);
+            self.children(statement.value.as_ref());
+        }
+
+        fn visit_binary(&mut self, binary: &Binary) {
+            self.node(format!("Binary {}", binary.operator.lexeme), binary.span);
+            self.children([&*binary.left, &*binary.right]);
+        }
+
+        fn visit_unary(&mut self, unary: &Unary) {
+            self.node(format!("Unary {}", unary.operator.lexeme), unary.span);
+            self.children([&*unary.expression]);
+        }
+
+        fn visit_grouping(&mut self, grouping: &Grouping) {
+            self.node("Grouping", grouping.span);
+            self.children([&*grouping.expression]);
+        }
+
+        fn visit_literal(&mut self, literal: &Literal) {
+            self.node(format!("Literal {}", literal), literal.span());
+        }
+
+        fn visit_variable(&mut self, variable: &Variable) {
+            self.node(format!("Variable {}", variable.name.lexeme), variable.span);
+        }
+
+        fn visit_assign(&mut self, assign: &Assign) {
+            self.node(format!("Assign {}", assign.name.lexeme), assign.span);
+            self.children([&*assign.value]);
+        }
+
+        fn visit_logical(&mut self, logical: &Logical) {
+            self.node(format!("Logical {}", logical.operator.lexeme), logical.span);
+            self.children([&*logical.left, &*logical.right]);
+        }
+
+        fn visit_call(&mut self, call: &Call) {
+            self.node("Call", call.span);
+            self.children([&*call.callee]);
+            self.children(&call.arguments);
+        }
+
+        fn visit_get(&mut self, get: &Get) {
+            self.node(format!("Get {}", get.name.lexeme), get.span);
+            self.children([&*get.object]);
+        }
+
+        fn visit_set(&mut self, set: &Set) {
+            self.node(format!("Set {}", set.name.lexeme), set.span);
+            self.children([&*set.object, &*set.value]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::prefix_printer::PrefixPrinter;
+    use super::*;
+    use crate::lexer::Scanner;
+    use crate::parser::Parser;
+    use crate::text::SourceMap;
+
+    pub(super) fn parse(source: &str) -> Expression {
+        let mut sources = SourceMap::new();
+        let file = sources.add("test.lox", source);
+
+        let tokens = Scanner::new(sources.file(file)).scan_tokens().tokens;
+
+        Parser::new(tokens).parse().expect("valid expression")
+    }
+
+    #[test]
+    fn fold_only_rebuilds_overridden_nodes() {
+        struct Ungroup;
+
+        impl Fold for Ungroup {
+            fn fold_expression(&mut self, arg: Expression) -> Expression {
+                match walk_expression_fold(self, arg) {
+                    Expression::Grouping(grouping) => (*grouping.expression).clone(),
+                    other => other,
+                }
+            }
+        }
+
+        let folded = Ungroup.fold_expression(parse("-((1 + (2)) * 3)"));
+
+        assert_eq!("(- (* (+ 1 2) 3))", PrefixPrinter::new().print(&folded));
+    }
+
+    #[test]
+    fn visit_reaches_every_node() {
+        #[derive(Default)]
+        struct Numbers(Vec<f64>);
+
+        impl Visit for Numbers {
+            fn visit_literal(&mut self, arg: &Literal) {
+                if let Literal::Number(value, _) = arg {
+                    self.0.push(*value);
+                }
+            }
+        }
+
+        let mut numbers = Numbers::default();
+        numbers.visit_expression(&parse("1 + -(2 * (3 - nil))"));
+
+        assert_eq!(vec![1.0, 2.0, 3.0], numbers.0);
+    }
+
+    #[test]
+    fn visitor_mut_reaches_every_node() {
+        struct Double;
+
+        impl SyntaxVisitorMut for Double {
+            fn visit_literal_mut(&mut self, arg: &mut Literal) {
+                if let Literal::Number(value, _) = arg {
+                    *value *= 2.0;
+                }
+            }
+        }
+
+        let mut expression = parse("1 + -(2 * nil)");
+        Double.visit_expression_mut(&mut expression);
+
+        assert_eq!(
+            "(+ 2 (- (grouping (* 4 nil))))",
+            PrefixPrinter::new().print(&expression)
+        );
+    }
 
     #[test]
     fn pretty_print_renders_correct_tree() {
-        use crate::lexer::token::TokenKind;
-
-        let expression = Expression::Binary(Binary {
-            left: Box::new(Expression::Unary(Unary {
-                operator: Box::new(Token {
-                    kind: TokenKind::Minus,
-                    lexeme: "-".into(),
-                    section: Default::default(),
-                }),
-                expression: Box::new(Expression::Binary(Binary {
-                    left: Box::new(Expression::Literal(Literal::True)),
-                    operator: Box::new(Token {
-                        kind: TokenKind::Slash,
-                        lexeme: "/".into(),
-                        section: Default::default(),
-                    }),
-                    right: Box::new(Expression::Literal(Literal::Number(123.0))),
-                })),
-            })),
-            operator: Box::new(Token {
-                kind: TokenKind::Star,
-                lexeme: "*".into(),
-                section: Default::default(),
-            }),
-            right: Box::new(Expression::Grouping(Grouping {
-                expression: Box::new(Expression::Binary(Binary {
-                    left: Box::new(Expression::Literal(Literal::Nil)),
-                    operator: Box::new(Token {
-                        kind: TokenKind::Plus,
-                        lexeme: "+".into(),
-                        section: Default::default(),
-                    }),
-                    right: Box::new(Expression::Literal(Literal::Number(45.67))),
-                })),
-            })),
-        });
+        assert_eq!(
+            "(* (- (grouping (/ true 123))) (grouping (+ nil 45.67)))",
+            PrefixPrinter::new().print(&parse("-(true / 123) * (nil + 45.67)")),
+        );
+    }
+
+    #[test]
+    fn display_is_an_s_expression() {
+        assert_eq!(
+            "(binary (unary - (grouping (binary true / 123))) * (call (variable f) [\"a\" nil]))",
+            parse("-(true / 123) * f(\"a\", nil)").to_string()
+        );
+        assert_eq!(
+            "(binary \"a\\\\b\\nc)\" + (get (variable o) name))",
+            parse("\"a\\b\nc)\" + o.name").to_string()
+        );
+
+        let mut sources = SourceMap::new();
+        let file = sources.add("test.lox", "var a; { print a; }");
+        let tokens = Scanner::new(sources.file(file)).scan_tokens().tokens;
+        let program = Parser::new(tokens).parse_program().unwrap();
+
+        assert_eq!(
+            "(var a _) (block [(print (variable a))])",
+            format!("{} {}", program[0], program[1])
+        );
+    }
+
+    #[test]
+    fn equality_ignores_spans() {
+        assert_eq!(parse("1 + f(2)"), parse("  1\n+   f( 2 )"));
+        assert_ne!(parse("1 + 2"), parse("1 - 2"));
+        assert_ne!(parse("1 + 2"), parse("(1 + 2)"));
+        assert_ne!(parse("f(1)"), parse("f(1, 2)"));
+    }
+
+    #[test]
+    fn json_has_types_spans_and_fields() {
+        let span = |start, end| {
+            format!(
+                "{{\"start\":{{\"line\":1,\"column\":{},\"offset\":{}}},\"end\":{{\"line\":1,\"column\":{},\"offset\":{}}}}}",
+                start,
+                start - 1,
+                end,
+                end - 1
+            )
+        };
+
+        let expected = format!(
+            "{{\"type\":\"Unary\",\"span\":{},\"operator\":{{\"lexeme\":\"-\",\"span\":{}}},\"expression\":{{\"type\":\"String\",\"span\":{},\"value\":\"a\\tb\"}}}}",
+            span(1, 7),
+            span(1, 2),
+            span(2, 7),
+        );
+
+        assert_eq!(expected, parse("-\"a\tb\"").to_json());
+    }
+
+    #[test]
+    fn tree_printer_indents_children() {
+        use super::tree_printer::TreePrinter;
 
         assert_eq!(
-            "(* (- (/ true 123)) (grouping (+ nil 45.67)))",
-            PrefixPrinter::new().print(&expression),
+            "Binary * @1:1\n  Unary - @1:1\n    Literal 1 @1:2\n  Call @1:6\n    Variable f @1:6\n    Literal true @1:8\n",
+            TreePrinter::new().print(&parse("-1 * f(true)"))
         );
     }
 }
@@ -0,0 +1,524 @@
+use super::*;
+use crate::parser::parser::infix_operator;
+use crate::parser::parser::Associativity;
+use crate::parser::parser::Precedence;
+
+/// Prints the tree back as Lox source. Groupings are printed as the parentheses they were written
+/// with, and more are only added where precedence or associativity need them, for trees that don't
+/// come from the parser. Parsing the output of a parsed tree gives back the same tree.
+///
+/// Negative numbers, which only the optimizer makes, are printed like negations.
+#[derive(Default)]
+pub struct InfixPrinter {
+    depth: usize,
+}
+
+impl InfixPrinter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn print(&mut self, expression: &Expression) -> String {
+        expression.accept(self)
+    }
+
+    /// Prints every statement in its own line.
+    pub fn print_program(&mut self, statements: &[Statement]) -> String {
+        statements
+            .iter()
+            .map(|statement| statement.accept(self))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Prints the expression, wrapped in parentheses if it binds looser than `min`.
+    fn operand(&mut self, expression: &Expression, min: Precedence) -> String {
+        let printed = expression.accept(self);
+
+        if precedence(expression) < min {
+            format!("({})", printed)
+        } else {
+            printed
+        }
+    }
+
+    fn indent(&self) -> String {
+        "    ".repeat(self.depth)
+    }
+}
+
+fn precedence(expression: &Expression) -> Precedence {
+    match expression {
+        Expression::Binary(Binary { operator, .. })
+        | Expression::Logical(Logical { operator, .. }) => {
+            infix_operator(&operator.kind).map_or(Precedence::Primary, |(precedence, _)| precedence)
+        }
+        Expression::Assign(_) | Expression::Set(_) => Precedence::Assignment,
+        Expression::Unary(_) => Precedence::Unary,
+        Expression::Call(_) | Expression::Get(_) => Precedence::Call,
+        Expression::Literal(Literal::Number(value, _)) if value.is_sign_negative() => {
+            Precedence::Unary
+        }
+        Expression::Grouping(_) | Expression::Literal(_) | Expression::Variable(_) => {
+            Precedence::Primary
+        }
+    }
+}
+
+impl SyntaxVisitor<String> for InfixPrinter {
+    fn visit_expression_statement(&mut self, statement: &ExpressionStatement) -> String {
+        format!("{};", statement.expression.accept(self))
+    }
+
+    fn visit_print(&mut self, print: &Print) -> String {
+        format!("print {};", print.expression.accept(self))
+    }
+
+    fn visit_var(&mut self, var: &Var) -> String {
+        match &var.initializer {
+            Some(initializer) => format!("var {} = {};", var.name.lexeme, initializer.accept(self)),
+            None => format!("var {};", var.name.lexeme),
+        }
+    }
+
+    fn visit_block(&mut self, block: &Block) -> String {
+        if block.statements.is_empty() {
+            return "{}".into();
+        }
+
+        self.depth += 1;
+
+        let lines = block
+            .statements
+            .iter()
+            .map(|statement| format!("{}{}", self.indent(), statement.accept(self)))
+            .collect::<Vec<_>>();
+
+        self.depth -= 1;
+
+        format!("{{\n{}\n{}}}", lines.join("\n"), self.indent())
+    }
+
+    fn visit_if(&mut self, statement: &If) -> String {
+        let mut printed = format!(
+            "if ({}) {}",
+            statement.condition.accept(self),
+            statement.then_branch.accept(self)
+        );
+
+        if let Some(else_branch) = &statement.else_branch {
+            printed.push_str(&format!(" else {}", else_branch.accept(self)));
+        }
+
+        printed
+    }
+
+    fn visit_while(&mut self, statement: &While) -> String {
+        format!(
+            "while ({}) {}",
+            statement.condition.accept(self),
+            statement.body.accept(self)
+        )
+    }
+
+    fn visit_function(&mut self, function: &Function) -> String {
+        let params = function
+            .params
+            .iter()
+            .map(|param| param.lexeme.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        format!(
+            "fun {}({}) {}",
+            function.name.lexeme,
+            params,
+            function.body.accept(self)
+        )
+    }
+
+    fn visit_return(&mut self, statement: &Return) -> String {
+        match &statement.value {
+            Some(value) => format!("return {};", value.accept(self)),
+            None => "return;".into(),
+        }
+    }
+
+    fn visit_binary(&mut self, binary: &Binary) -> String {
+        let (precedence, associativity) = infix_operator(&binary.operator.kind)
+            .unwrap_or((Precedence::Primary, Associativity::Left));
+
+        let (left, right) = match associativity {
+            Associativity::Left => (precedence, precedence.tighter()),
+            Associativity::Right => (precedence.tighter(), precedence),
+        };
+
+        format!(
+            "{} {} {}",
+            self.operand(&binary.left, left),
+            binary.operator.lexeme,
+            self.operand(&binary.right, right)
+        )
+    }
+
+    fn visit_unary(&mut self, unary: &Unary) -> String {
+        format!(
+            "{}{}",
+            unary.operator.lexeme,
+            self.operand(&unary.expression, Precedence::Unary)
+        )
+    }
+
+    fn visit_grouping(&mut self, grouping: &Grouping) -> String {
+        format!("({})", grouping.expression.accept(self))
+    }
+
+    fn visit_literal(&mut self, literal: &Literal) -> String {
+        literal.to_string()
+    }
+
+    fn visit_variable(&mut self, variable: &Variable) -> String {
+        variable.name.lexeme.to_string()
+    }
+
+    fn visit_assign(&mut self, assign: &Assign) -> String {
+        format!(
+            "{} = {}",
+            assign.name.lexeme,
+            self.operand(&assign.value, Precedence::Assignment)
+        )
+    }
+
+    fn visit_logical(&mut self, logical: &Logical) -> String {
+        let (precedence, _) = infix_operator(&logical.operator.kind)
+            .unwrap_or((Precedence::Primary, Associativity::Left));
+
+        format!(
+            "{} {} {}",
+            self.operand(&logical.left, precedence),
+            logical.operator.lexeme,
+            self.operand(&logical.right, precedence.tighter())
+        )
+    }
+
+    fn visit_call(&mut self, call: &Call) -> String {
+        let arguments = call
+            .arguments
+            .iter()
+            .map(|argument| self.operand(argument, Precedence::Assignment))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        format!(
+            "{}({})",
+            self.operand(&call.callee, Precedence::Call),
+            arguments
+        )
+    }
+
+    fn visit_get(&mut self, get: &Get) -> String {
+        format!(
+            "{}.{}",
+            self.operand(&get.object, Precedence::Call),
+            get.name.lexeme
+        )
+    }
+
+    fn visit_set(&mut self, set: &Set) -> String {
+        format!(
+            "{}.{} = {}",
+            self.operand(&set.object, Precedence::Call),
+            set.name.lexeme,
+            self.operand(&set.value, Precedence::Assignment)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::token::TokenKind;
+    use crate::lexer::Scanner;
+    use crate::parser::ast::tests::parse;
+    use crate::parser::Parser;
+    use crate::text::SourceMap;
+    use proptest::prelude::*;
+
+    fn print(source: &str) -> String {
+        InfixPrinter::new().print(&parse(source))
+    }
+
+    fn parse_program(source: &str) -> Vec<Statement> {
+        let mut sources = SourceMap::new();
+        let file = sources.add("test.lox", source);
+
+        let tokens = Scanner::new(sources.file(file)).scan_tokens().tokens;
+
+        Parser::new(tokens).parse_program().expect("valid program")
+    }
+
+    #[test]
+    fn groupings_are_printed_as_written() {
+        assert_eq!("(1 + (2 * 3))", print("(1 + (2 * 3))"));
+        assert_eq!("((1 + 2)) * 3", print("((1 + 2)) * 3"));
+        assert_eq!("a = (b = (c or d))", print("a = (b = (c or d))"));
+        assert_eq!("-(-c)(1)", print("-(-c)(1)"));
+    }
+
+    #[test]
+    fn only_needed_parentheses_are_added() {
+        let number = |value| Expression::Literal(Literal::Number(value, TextSection::default()));
+        let binary = |left, kind, lexeme, right| {
+            Expression::Binary(Binary::new(left, token(kind, lexeme), right))
+        };
+
+        let sum = binary(number(1.0), TokenKind::Plus, "+", number(2.0));
+        let difference = binary(number(2.0), TokenKind::Minus, "-", number(3.0));
+
+        let cases = [
+            (
+                binary(sum.clone(), TokenKind::Star, "*", number(3.0)),
+                "(1 + 2) * 3",
+            ),
+            (
+                binary(number(3.0), TokenKind::Plus, "+", sum.clone()),
+                "3 + (1 + 2)",
+            ),
+            (
+                binary(sum.clone(), TokenKind::Minus, "-", number(3.0)),
+                "1 + 2 - 3",
+            ),
+            (
+                binary(number(1.0), TokenKind::Minus, "-", difference),
+                "1 - (2 - 3)",
+            ),
+        ];
+
+        for (expression, printed) in cases {
+            assert_eq!(printed, InfixPrinter::new().print(&expression));
+        }
+    }
+
+    #[test]
+    fn negative_numbers_bind_like_negations() {
+        let negative = || Expression::Literal(Literal::Number(-1.5, TextSection::default()));
+        let paren = token(TokenKind::RightParen, ")");
+
+        let call = Expression::Call(Call::new(negative(), paren, vec![negative()]));
+        let get = Expression::Get(Get::new(negative(), name_token("a")));
+
+        assert_eq!("(-1.5)(-1.5)", InfixPrinter::new().print(&call));
+        assert_eq!("(-1.5).a", InfixPrinter::new().print(&get));
+    }
+
+    #[test]
+    fn statements_are_printed_as_source() {
+        let source =
+            "fun f(a, b) {\n    if (a) print a; else {\n        return b;\n    }\n}\nvar c;";
+
+        assert_eq!(
+            source,
+            InfixPrinter::new().print_program(&parse_program(source))
+        );
+    }
+
+    fn token(kind: TokenKind, lexeme: &str) -> Token {
+        Token {
+            kind,
+            lexeme: lexeme.into(),
+            section: Default::default(),
+        }
+    }
+
+    fn name_token(name: &str) -> Token {
+        token(TokenKind::Identifier(name.into()), name)
+    }
+
+    fn name() -> impl Strategy<Value = Token> {
+        prop::sample::select(vec!["a", "b", "foo", "bar_1"]).prop_map(name_token)
+    }
+
+    fn literal() -> impl Strategy<Value = Expression> {
+        let span = TextSection::default;
+
+        prop_oneof![
+            // Eighths print exactly, negative numbers are parsed as negations.
+            (0..10_000).prop_map(move |n| Literal::Number(n as f64 / 8.0, span())),
+            "[a-z ]{0,5}".prop_map(move |text| Literal::String(text.into(), span())),
+            Just(Literal::True(span())),
+            Just(Literal::False(span())),
+            Just(Literal::Nil(span())),
+        ]
+        .prop_map(Expression::Literal)
+    }
+
+    /// Wraps the expression in a grouping if it binds looser than `min`, as the parser would only
+    /// give it back with parentheses around.
+    fn grouped(expression: Expression, min: Precedence) -> Expression {
+        if precedence(&expression) < min {
+            Expression::Grouping(Grouping::new(expression))
+        } else {
+            expression
+        }
+    }
+
+    /// Trees the parser can give back, with groupings wherever precedence needs parentheses.
+    fn expression() -> impl Strategy<Value = Expression> {
+        let leaf = prop_oneof![
+            literal(),
+            name().prop_map(|name| Expression::Variable(Variable::new(name)))
+        ];
+
+        leaf.prop_recursive(5, 48, 3, |inner| {
+            let binary_operator = prop::sample::select(vec![
+                (TokenKind::BangEqual, "!="),
+                (TokenKind::EqualEqual, "=="),
+                (TokenKind::Greater, ">"),
+                (TokenKind::GreaterEqual, ">="),
+                (TokenKind::Less, "<"),
+                (TokenKind::LessEqual, "<="),
+                (TokenKind::Minus, "-"),
+                (TokenKind::Plus, "+"),
+                (TokenKind::Slash, "/"),
+                (TokenKind::Star, "*"),
+            ]);
+
+            let logical_operator =
+                prop::sample::select(vec![(TokenKind::Or, "or"), (TokenKind::And, "and")]);
+
+            let unary_operator =
+                prop::sample::select(vec![(TokenKind::Minus, "-"), (TokenKind::Bang, "!")]);
+
+            let infix = |left, (kind, lexeme): (TokenKind, &str), right| {
+                let (precedence, associativity) =
+                    infix_operator(&kind).expect("infix operators only");
+
+                let (left_min, right_min) = match associativity {
+                    Associativity::Left => (precedence, precedence.tighter()),
+                    Associativity::Right => (precedence.tighter(), precedence),
+                };
+
+                (
+                    grouped(left, left_min),
+                    token(kind, lexeme),
+                    grouped(right, right_min),
+                )
+            };
+
+            prop_oneof![
+                inner
+                    .clone()
+                    .prop_map(|expression| Expression::Grouping(Grouping::new(expression))),
+                (inner.clone(), binary_operator, inner.clone()).prop_map(
+                    move |(left, operator, right)| {
+                        let (left, operator, right) = infix(left, operator, right);
+
+                        Expression::Binary(Binary::new(left, operator, right))
+                    }
+                ),
+                (inner.clone(), logical_operator, inner.clone()).prop_map(
+                    move |(left, operator, right)| {
+                        let (left, operator, right) = infix(left, operator, right);
+
+                        Expression::Logical(Logical::new(left, operator, right))
+                    }
+                ),
+                (unary_operator, inner.clone()).prop_map(|((kind, lexeme), expression)| {
+                    Expression::Unary(Unary::new(
+                        token(kind, lexeme),
+                        grouped(expression, Precedence::Unary),
+                    ))
+                }),
+                (name(), inner.clone())
+                    .prop_map(|(name, value)| Expression::Assign(Assign::new(name, value))),
+                (inner.clone(), name()).prop_map(|(object, name)| {
+                    Expression::Get(Get::new(grouped(object, Precedence::Call), name))
+                }),
+                (inner.clone(), name(), inner.clone()).prop_map(|(object, name, value)| {
+                    Expression::Set(Set::new(grouped(object, Precedence::Call), name, value))
+                }),
+                (inner.clone(), prop::collection::vec(inner, 0..3)).prop_map(
+                    |(callee, arguments)| {
+                        let paren = token(TokenKind::RightParen, ")");
+
+                        Expression::Call(Call::new(
+                            grouped(callee, Precedence::Call),
+                            paren,
+                            arguments,
+                        ))
+                    }
+                ),
+            ]
+        })
+    }
+
+    /// Whether an `else` right after the statement would go to an `if` inside of it.
+    fn dangles(statement: &Statement) -> bool {
+        match statement {
+            Statement::If(statement) => statement.else_branch.as_deref().is_none_or(dangles),
+            Statement::While(statement) => dangles(&statement.body),
+            _ => false,
+        }
+    }
+
+    fn statement() -> impl Strategy<Value = Statement> {
+        let simple = prop_oneof![
+            expression().prop_map(|expression| {
+                Statement::ExpressionStatement(ExpressionStatement::new(expression))
+            }),
+            expression().prop_map(|expression| {
+                Statement::Print(Print::new(token(TokenKind::Print, "print"), expression))
+            }),
+            (name(), prop::option::of(expression()))
+                .prop_map(|(name, initializer)| Statement::Var(Var::new(name, initializer))),
+            prop::option::of(expression()).prop_map(|value| {
+                Statement::Return(Return::new(token(TokenKind::Return, "return"), value))
+            }),
+        ];
+
+        simple.prop_recursive(3, 24, 4, |inner| {
+            // Declarations can only be in blocks.
+            let body = inner.clone().prop_filter("declaration", |statement| {
+                !matches!(statement, Statement::Var(_) | Statement::Function(_))
+            });
+
+            prop_oneof![
+                prop::collection::vec(inner.clone(), 0..4)
+                    .prop_map(|statements| Statement::Block(Block::new(statements))),
+                (expression(), body.clone(), prop::option::of(body.clone()))
+                    .prop_filter("dangling else", |(_, then_branch, else_branch)| {
+                        else_branch.is_none() || !dangles(then_branch)
+                    })
+                    .prop_map(|(condition, then_branch, else_branch)| {
+                        Statement::If(If::new(condition, then_branch, else_branch))
+                    }),
+                (expression(), body)
+                    .prop_map(|(condition, body)| Statement::While(While::new(condition, body))),
+                (
+                    name(),
+                    prop::collection::vec(name(), 0..3),
+                    prop::collection::vec(inner, 0..3)
+                )
+                    .prop_map(|(name, params, statements)| {
+                        Statement::Function(Function::new(name, params, Block::new(statements)))
+                    }),
+            ]
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn expressions_round_trip(expression in expression()) {
+            let printed = InfixPrinter::new().print(&expression);
+
+            prop_assert_eq!(expression, parse(&printed), "{}", printed);
+        }
+
+        #[test]
+        fn programs_round_trip(statements in prop::collection::vec(statement(), 0..4)) {
+            let printed = InfixPrinter::new().print_program(&statements);
+
+            prop_assert_eq!(statements, parse_program(&printed), "{}", printed);
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use super::*;
+use crate::lexer::token::TokenKind;
+
+/// Prints the tree in reverse Polish notation, operands first and then what is done with them.
+/// There are no parentheses, groupings disappear, and unary minus is written `~` to tell it apart
+/// from subtraction.
+///
+/// Nodes taking a variable number of children say how many, `f 1 2 call/2`.
+#[derive(Default)]
+pub struct RpnPrinter {}
+
+impl RpnPrinter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn print(&mut self, expression: &Expression) -> String {
+        expression.accept(self)
+    }
+
+    /// Prints every statement in its own line.
+    pub fn print_program(&mut self, statements: &[Statement]) -> String {
+        statements
+            .iter()
+            .map(|statement| statement.accept(self))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn postfix<'a, T: Visitable<String> + 'a>(
+        &mut self,
+        operands: impl IntoIterator<Item = &'a T>,
+        operator: impl std::fmt::Display,
+    ) -> String {
+        operands
+            .into_iter()
+            .map(|operand| operand.accept(self))
+            .chain([operator.to_string()])
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+impl SyntaxVisitor<String> for RpnPrinter {
+    fn visit_expression_statement(&mut self, statement: &ExpressionStatement) -> String {
+        self.postfix([&statement.expression], ";")
+    }
+
+    fn visit_print(&mut self, print: &Print) -> String {
+        self.postfix([&print.expression], "print")
+    }
+
+    fn visit_var(&mut self, var: &Var) -> String {
+        self.postfix(var.initializer.as_ref(), format!("{} var", var.name.lexeme))
+    }
+
+    fn visit_block(&mut self, block: &Block) -> String {
+        self.postfix(
+            &block.statements,
+            format!("block/{}", block.statements.len()),
+        )
+    }
+
+    fn visit_if(&mut self, statement: &If) -> String {
+        let condition = statement.condition.accept(self);
+
+        let branches = self.postfix(
+            std::iter::once(&*statement.then_branch).chain(statement.else_branch.as_deref()),
+            if statement.else_branch.is_some() {
+                "if-else"
+            } else {
+                "if"
+            },
+        );
+
+        format!("{} {}", condition, branches)
+    }
+
+    fn visit_while(&mut self, statement: &While) -> String {
+        format!(
+            "{} {}",
+            statement.condition.accept(self),
+            self.postfix([&*statement.body], "while")
+        )
+    }
+
+    fn visit_function(&mut self, function: &Function) -> String {
+        let params = function
+            .params
+            .iter()
+            .map(|param| format!("{} ", param.lexeme))
+            .collect::<String>();
+
+        format!(
+            "{}{} {} fun/{}",
+            params,
+            function.body.accept(self),
+            function.name.lexeme,
+            function.params.len()
+        )
+    }
+
+    fn visit_return(&mut self, statement: &Return) -> String {
+        self.postfix(statement.value.as_ref(), "return")
+    }
+
+    fn visit_binary(&mut self, binary: &Binary) -> String {
+        self.postfix([&*binary.left, &*binary.right], &binary.operator.lexeme)
+    }
+
+    fn visit_unary(&mut self, unary: &Unary) -> String {
+        let operator = match unary.operator.kind {
+            TokenKind::Minus => "~",
+            _ => &unary.operator.lexeme,
+        };
+
+        self.postfix([&*unary.expression], operator)
+    }
+
+    fn visit_grouping(&mut self, grouping: &Grouping) -> String {
+        grouping.expression.accept(self)
+    }
+
+    fn visit_literal(&mut self, literal: &Literal) -> String {
+        literal.to_string()
+    }
+
+    fn visit_variable(&mut self, variable: &Variable) -> String {
+        variable.name.lexeme.to_string()
+    }
+
+    fn visit_assign(&mut self, assign: &Assign) -> String {
+        self.postfix([&*assign.value], format!("{} =", assign.name.lexeme))
+    }
+
+    fn visit_logical(&mut self, logical: &Logical) -> String {
+        self.postfix([&*logical.left, &*logical.right], &logical.operator.lexeme)
+    }
+
+    fn visit_call(&mut self, call: &Call) -> String {
+        let callee = call.callee.accept(self);
+
+        format!(
+            "{} {}",
+            callee,
+            self.postfix(&call.arguments, format!("call/{}", call.arguments.len()))
+        )
+    }
+
+    fn visit_get(&mut self, get: &Get) -> String {
+        self.postfix([&*get.object], format!(".{}", get.name.lexeme))
+    }
+
+    fn visit_set(&mut self, set: &Set) -> String {
+        self.postfix(
+            [&*set.object, &*set.value],
+            format!(".{} =", set.name.lexeme),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::ast::tests::parse;
+
+    #[test]
+    fn operands_come_before_operators() {
+        let print = |source| RpnPrinter::new().print(&parse(source));
+
+        assert_eq!("1 2 + 4 3 - *", print("(1 + 2) * (4 - 3)"));
+        assert_eq!("1 ~ ~ ! a b or and c or", print("!--1 and (a or b) or c"));
+        assert_eq!("f 1 2 x = call/2 call/0", print("f(1, x = 2)()"));
+    }
+}
@@ -1,30 +1,214 @@
+use std::rc::Rc;
+
 use crate::error::Error;
 use crate::error::ErrorBuilder;
+use crate::error::ErrorList;
 use crate::lexer::token::Token;
 use crate::lexer::token::TokenKind;
+use crate::parser::ast::Assign;
 use crate::parser::ast::Binary;
+use crate::parser::ast::Block;
+use crate::parser::ast::Call;
 use crate::parser::ast::Expression;
+use crate::parser::ast::ExpressionStatement;
+use crate::parser::ast::Function;
+use crate::parser::ast::Get;
 use crate::parser::ast::Grouping;
+use crate::parser::ast::If;
 use crate::parser::ast::Literal;
+use crate::parser::ast::Logical;
+use crate::parser::ast::Print;
+use crate::parser::ast::Return;
+use crate::parser::ast::Set;
+use crate::parser::ast::Statement;
 use crate::parser::ast::Unary;
+use crate::parser::ast::Var;
+use crate::parser::ast::Variable;
+use crate::parser::ast::While;
+use crate::text::Spanned;
 
 type Result<T> = std::result::Result<T, Error>;
 
-/// Parses the following unambigous grammar:
-/// ```
-/// expression -> equality ;
-/// equality   -> comparison ( ( "!=" | "==" ) comparison )* ;
-/// comparison -> term ( ( ">" | ">=" | "<" | "<=" ) term )* ;
-/// term       -> factor ( ( "-" | "+" ) factor )* ;
-/// factor     -> unary ( ( "/" | "*" ) unary )* ;
-/// unary      -> ( "!" | "-" ) unary | primary ;
-/// primary    -> NUMBER | STRING | "true" | "false" | "nil" | "(" expression ")" ;
+/// Maximum number of arguments of a call, and so parameters of a function.
+pub const MAX_ARGUMENTS: usize = 255;
+
+/// Parses the following grammar:
+/// ```text
+/// program     -> declaration* EOF ;
+/// declaration -> funDecl | varDecl | statement ;
+/// funDecl     -> "fun" IDENTIFIER "(" parameters? ")" block ;
+/// parameters  -> IDENTIFIER ( "," IDENTIFIER )* ;
+/// varDecl     -> "var" IDENTIFIER ( "=" expression )? ";" ;
+/// statement   -> exprStmt | forStmt | ifStmt | printStmt | returnStmt | whileStmt | block ;
+/// exprStmt    -> expression ";" ;
+/// forStmt     -> "for" "(" ( varDecl | exprStmt | ";" ) expression? ";" expression? ")" statement ;
+/// ifStmt      -> "if" "(" expression ")" statement ( "else" statement )? ;
+/// printStmt   -> "print" expression ";" ;
+/// returnStmt  -> "return" expression? ";" ;
+/// whileStmt   -> "while" "(" expression ")" statement ;
+/// block       -> "{" declaration* "}" ;
+///
+/// expression  -> prefix ( INFIX_OPERATOR expression )* ;
+/// prefix      -> PREFIX_OPERATOR prefix | call ;
+/// call        -> primary ( "(" arguments? ")" | "." IDENTIFIER )* ;
+/// arguments   -> expression ( "," expression )* ;
+/// primary     -> NUMBER | STRING | IDENTIFIER | "true" | "false" | "nil" | "(" expression ")" ;
 /// ```
+///
+/// The ambiguity in `expression` is resolved by precedence climbing, using the binding power and
+/// associativity of each operator as defined in `INFIX_OPERATORS` and `PREFIX_OPERATORS`.
 pub struct Parser {
     tokens: Vec<Token>,
     scan_position: usize,
 }
 
+/// How tight an operator binds its operands, from loosest to tightest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Precedence {
+    Lowest,
+    Assignment,
+    Or,
+    And,
+    Equality,
+    Comparison,
+    Term,
+    Factor,
+    Unary,
+    Call,
+    Primary,
+}
+
+impl Precedence {
+    /// The next tighter level.
+    pub(crate) fn tighter(self) -> Self {
+        match self {
+            Precedence::Lowest => Precedence::Assignment,
+            Precedence::Assignment => Precedence::Or,
+            Precedence::Or => Precedence::And,
+            Precedence::And => Precedence::Equality,
+            Precedence::Equality => Precedence::Comparison,
+            Precedence::Comparison => Precedence::Term,
+            Precedence::Term => Precedence::Factor,
+            Precedence::Factor => Precedence::Unary,
+            Precedence::Unary => Precedence::Call,
+            Precedence::Call | Precedence::Primary => Precedence::Primary,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Associativity {
+    Left,
+    Right,
+}
+
+/// Builds the node for an infix operator out of its operands.
+type Build = fn(Expression, Token, Expression) -> Result<Expression>;
+
+struct Operator {
+    kind: TokenKind,
+    precedence: Precedence,
+    associativity: Associativity,
+    build: Build,
+}
+
+impl Operator {
+    const fn left(kind: TokenKind, precedence: Precedence) -> Self {
+        Self::building(kind, precedence, Associativity::Left, binary)
+    }
+
+    const fn right(kind: TokenKind, precedence: Precedence) -> Self {
+        Self::building(kind, precedence, Associativity::Right, binary)
+    }
+
+    const fn building(
+        kind: TokenKind,
+        precedence: Precedence,
+        associativity: Associativity,
+        build: Build,
+    ) -> Self {
+        Self {
+            kind,
+            precedence,
+            associativity,
+            build,
+        }
+    }
+
+    /// Binding power of the operator on its left and right operands. The side with the higher power
+    /// wins ties between operators of the same precedence, which is what makes it associative.
+    fn binding_power(&self) -> (u8, u8) {
+        let base = self.precedence as u8 * 2;
+
+        match self.associativity {
+            Associativity::Left => (base, base + 1),
+            Associativity::Right => (base + 1, base),
+        }
+    }
+}
+
+const INFIX_OPERATORS: &[Operator] = &[
+    Operator::building(
+        TokenKind::Equal,
+        Precedence::Assignment,
+        Associativity::Right,
+        assign,
+    ),
+    Operator::building(TokenKind::Or, Precedence::Or, Associativity::Left, logical),
+    Operator::building(
+        TokenKind::And,
+        Precedence::And,
+        Associativity::Left,
+        logical,
+    ),
+    Operator::left(TokenKind::BangEqual, Precedence::Equality),
+    Operator::left(TokenKind::EqualEqual, Precedence::Equality),
+    Operator::left(TokenKind::Greater, Precedence::Comparison),
+    Operator::left(TokenKind::GreaterEqual, Precedence::Comparison),
+    Operator::left(TokenKind::Less, Precedence::Comparison),
+    Operator::left(TokenKind::LessEqual, Precedence::Comparison),
+    Operator::left(TokenKind::Minus, Precedence::Term),
+    Operator::left(TokenKind::Plus, Precedence::Term),
+    Operator::left(TokenKind::Slash, Precedence::Factor),
+    Operator::left(TokenKind::Star, Precedence::Factor),
+];
+
+const PREFIX_OPERATORS: &[Operator] = &[
+    Operator::right(TokenKind::Bang, Precedence::Unary),
+    Operator::right(TokenKind::Minus, Precedence::Unary),
+];
+
+fn binary(left: Expression, operator: Token, right: Expression) -> Result<Expression> {
+    Ok(Expression::Binary(Binary::new(left, operator, right)))
+}
+
+fn logical(left: Expression, operator: Token, right: Expression) -> Result<Expression> {
+    Ok(Expression::Logical(Logical::new(left, operator, right)))
+}
+
+fn assign(target: Expression, equals: Token, value: Expression) -> Result<Expression> {
+    match target {
+        Expression::Variable(variable) => Ok(Expression::Assign(Assign::new(variable.name, value))),
+        Expression::Get(get) => Ok(Expression::Set(Set::new(
+            Rc::unwrap_or_clone(get.object),
+            get.name,
+            value,
+        ))),
+        _ => Err(error_at("Invalid assignment target", &equals)),
+    }
+}
+
+/// Precedence and associativity of an infix operator, for printers that need to put back the
+/// parentheses.
+pub(crate) fn infix_operator(kind: &TokenKind) -> Option<(Precedence, Associativity)> {
+    find_operator(INFIX_OPERATORS, kind)
+        .map(|operator| (operator.precedence, operator.associativity))
+}
+
+fn find_operator<'a>(table: &'a [Operator], kind: &TokenKind) -> Option<&'a Operator> {
+    table.iter().find(|operator| operator.kind == *kind)
+}
+
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Self {
         Self {
@@ -33,170 +217,391 @@ impl Parser {
         }
     }
 
-    // maybe consume self instead? yes
+    /// Parses a whole program, recovering from errors at the start of the next statement so all of
+    /// them are reported at once.
+    pub fn parse_program(mut self) -> std::result::Result<Vec<Statement>, ErrorList> {
+        let mut statements = Vec::new();
+        let mut errors = ErrorList::default();
+
+        while !self.is_done() {
+            match self.declaration() {
+                Ok(statement) => statements.push(statement),
+                Err(error) => {
+                    errors.add(error);
+                    self.synchronize();
+                }
+            }
+        }
+
+        if errors.size() > 0 {
+            Err(errors)
+        } else {
+            Ok(statements)
+        }
+    }
+
+    /// Parses a single expression, failing if any token other than `Eof` is left after it.
     pub fn parse(mut self) -> Result<Expression> {
-        // Do error handling/recovery here
-        // TODO: After this, the token stream should have been completely consumed...
-        self.expression()
+        let expression = self.expression()?;
+
+        if !self.is_done() {
+            return Err(self.unexpected_after("expression"));
+        }
+
+        Ok(expression)
     }
 
-    /// expression -> equality ;
-    fn expression(&mut self) -> Result<Expression> {
-        self.equality()
+    /// declaration -> funDecl | varDecl | statement ;
+    fn declaration(&mut self) -> Result<Statement> {
+        if self.matches(TokenKind::Fun) {
+            self.function().map(Statement::Function)
+        } else if self.matches(TokenKind::Var) {
+            self.var_declaration()
+        } else {
+            self.statement()
+        }
     }
 
-    /// equality   -> comparison ( ( "!=" | "==" ) comparison )* ;
-    fn equality(&mut self) -> Result<Expression> {
-        let mut left = self.comparison()?;
+    /// funDecl     -> "fun" IDENTIFIER "(" parameters? ")" block ;
+    fn function(&mut self) -> Result<Function> {
+        let start = self.previous().section;
+        let name = self.consume_identifier()?;
+
+        self.consume(TokenKind::LeftParen)?;
 
-        while self.matches(TokenKind::BangEqual) || self.matches(TokenKind::EqualEqual) {
-            let binary = Binary {
-                left: std::boxed::Box::new(left),
-                operator: std::boxed::Box::new(self.previous().clone()),
-                right: std::boxed::Box::new(self.comparison()?),
-            };
+        let mut params = Vec::new();
 
-            left = Expression::Binary(binary);
+        if !self.check(TokenKind::RightParen) {
+            loop {
+                if params.len() >= MAX_ARGUMENTS {
+                    return Err(error_at(
+                        format!("Can't have more than {} parameters", MAX_ARGUMENTS),
+                        self.peek(),
+                    ));
+                }
+
+                params.push(self.consume_identifier()?);
+
+                if !self.matches(TokenKind::Comma) {
+                    break;
+                }
+            }
         }
 
-        Ok(left)
+        self.consume(TokenKind::RightParen)?;
+        self.consume(TokenKind::LeftBrace)?;
+
+        let mut function = Function::new(name, params, self.block()?);
+        function.span = start.to(function.span);
+
+        Ok(function)
     }
 
-    /// comparison -> term ( ( ">" | ">=" | "<" | "<=" ) term )* ;
-    fn comparison(&mut self) -> Result<Expression> {
-        let mut left = self.term()?;
+    /// varDecl     -> "var" IDENTIFIER ( "=" expression )? ";" ;
+    fn var_declaration(&mut self) -> Result<Statement> {
+        let start = self.previous().section;
+        let name = self.consume_identifier()?;
 
-        while self.matches(TokenKind::Greater)
-            || self.matches(TokenKind::GreaterEqual)
-            || self.matches(TokenKind::Less)
-            || self.matches(TokenKind::LessEqual)
-        {
-            let binary = Binary {
-                left: std::boxed::Box::new(left),
-                operator: std::boxed::Box::new(self.previous().clone()),
-                right: std::boxed::Box::new(self.term()?),
-            };
+        let initializer = if self.matches(TokenKind::Equal) {
+            Some(self.expression()?)
+        } else {
+            None
+        };
 
-            left = Expression::Binary(binary);
-        }
+        let mut var = Var::new(name, initializer);
+        var.span = start.to(self.consume(TokenKind::Semicolon)?.section);
 
-        Ok(left)
+        Ok(Statement::Var(var))
+    }
+
+    fn statement(&mut self) -> Result<Statement> {
+        if self.matches(TokenKind::For) {
+            self.for_statement()
+        } else if self.matches(TokenKind::If) {
+            self.if_statement()
+        } else if self.matches(TokenKind::Print) {
+            self.print_statement()
+        } else if self.matches(TokenKind::Return) {
+            self.return_statement()
+        } else if self.matches(TokenKind::While) {
+            self.while_statement()
+        } else if self.matches(TokenKind::LeftBrace) {
+            self.block().map(Statement::Block)
+        } else {
+            self.expression_statement()
+        }
     }
 
-    /// term       -> factor ( ( "-" | "+" ) factor )* ;
-    fn term(&mut self) -> Result<Expression> {
-        let mut left = self.factor()?;
+    /// forStmt     -> "for" "(" ( varDecl | exprStmt | ";" ) expression? ";" expression? ")" statement ;
+    ///
+    /// There's no node for it, it's desugared into a `while` loop inside its own block:
+    /// ```text
+    /// { initializer; while (condition) { body; increment; } }
+    /// ```
+    fn for_statement(&mut self) -> Result<Statement> {
+        let start = self.previous().section;
+
+        self.consume(TokenKind::LeftParen)?;
+
+        let initializer = if self.matches(TokenKind::Semicolon) {
+            None
+        } else if self.matches(TokenKind::Var) {
+            Some(self.var_declaration()?)
+        } else {
+            Some(self.expression_statement()?)
+        };
+
+        let condition = if self.check(TokenKind::Semicolon) {
+            Expression::Literal(Literal::True(self.peek().section))
+        } else {
+            self.expression()?
+        };
+
+        self.consume(TokenKind::Semicolon)?;
+
+        let increment = if self.check(TokenKind::RightParen) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+
+        self.consume(TokenKind::RightParen)?;
 
-        while self.matches(TokenKind::Minus) || self.matches(TokenKind::Plus) {
-            let binary = Binary {
-                left: std::boxed::Box::new(left),
-                operator: std::boxed::Box::new(self.previous().clone()),
-                right: std::boxed::Box::new(self.factor()?),
-            };
+        let mut body = self.statement()?;
+        let span = start.to(body.span());
 
-            left = Expression::Binary(binary);
+        if let Some(increment) = increment {
+            let increment = Statement::ExpressionStatement(ExpressionStatement::new(increment));
+
+            let mut block = Block::new(vec![body, increment]);
+            block.span = span;
+
+            body = Statement::Block(block);
         }
 
-        Ok(left)
+        let mut looping = While::new(condition, body);
+        looping.span = span;
+
+        let mut block = Block::new(
+            initializer
+                .into_iter()
+                .chain([Statement::While(looping)])
+                .collect(),
+        );
+        block.span = span;
+
+        Ok(Statement::Block(block))
     }
 
-    /// factor     -> unary ( ( "/" | "*" ) unary )* ;
-    fn factor(&mut self) -> Result<Expression> {
-        let mut left = self.unary()?;
+    /// ifStmt      -> "if" "(" expression ")" statement ( "else" statement )? ;
+    fn if_statement(&mut self) -> Result<Statement> {
+        let start = self.previous().section;
 
-        while self.matches(TokenKind::Slash) || self.matches(TokenKind::Star) {
-            let binary = Binary {
-                left: std::boxed::Box::new(left),
-                operator: std::boxed::Box::new(self.previous().clone()),
-                right: std::boxed::Box::new(self.unary()?),
-            };
+        self.consume(TokenKind::LeftParen)?;
+        let condition = self.expression()?;
+        self.consume(TokenKind::RightParen)?;
 
-            left = Expression::Binary(binary);
-        }
+        let then_branch = self.statement()?;
 
-        Ok(left)
+        let else_branch = if self.matches(TokenKind::Else) {
+            Some(self.statement()?)
+        } else {
+            None
+        };
+
+        let mut statement = If::new(condition, then_branch, else_branch);
+        statement.span = start.to(statement.span);
+
+        Ok(Statement::If(statement))
     }
 
-    /// unary      -> ( "!" | "-" ) unary | primary ;
-    fn unary(&mut self) -> Result<Expression> {
-        if self.matches(TokenKind::Bang) || self.matches(TokenKind::Minus) {
-            let unary = Unary {
-                operator: std::boxed::Box::new(self.previous().clone()),
-                expression: std::boxed::Box::new(self.unary()?),
-            };
+    /// printStmt   -> "print" expression ";" ;
+    fn print_statement(&mut self) -> Result<Statement> {
+        let keyword = self.previous().clone();
+
+        let mut print = Print::new(keyword, self.expression()?);
+        print.span = print.span.to(self.consume(TokenKind::Semicolon)?.section);
+
+        Ok(Statement::Print(print))
+    }
 
-            Ok(Expression::Unary(unary))
+    /// returnStmt  -> "return" expression? ";" ;
+    fn return_statement(&mut self) -> Result<Statement> {
+        let keyword = self.previous().clone();
+
+        let value = if self.check(TokenKind::Semicolon) {
+            None
         } else {
-            self.primary()
-        }
+            Some(self.expression()?)
+        };
+
+        let mut statement = Return::new(keyword, value);
+        statement.span = statement
+            .span
+            .to(self.consume(TokenKind::Semicolon)?.section);
+
+        Ok(Statement::Return(statement))
     }
 
-    /// primary    -> NUMBER | STRING | "true" | "false" | "nil" | "(" expression ")" ;
-    fn primary(&mut self) -> Result<Expression> {
-        // TODO: rewrite this into a proper match, same for all the other matches..
-        if self.matches(TokenKind::False) {
-            return Ok(Expression::Literal(Literal::False));
-        }
+    /// whileStmt   -> "while" "(" expression ")" statement ;
+    fn while_statement(&mut self) -> Result<Statement> {
+        let start = self.previous().section;
 
-        if self.matches(TokenKind::True) {
-            return Ok(Expression::Literal(Literal::True));
-        }
+        self.consume(TokenKind::LeftParen)?;
+        let condition = self.expression()?;
+        self.consume(TokenKind::RightParen)?;
+
+        let mut statement = While::new(condition, self.statement()?);
+        statement.span = start.to(statement.span);
+
+        Ok(Statement::While(statement))
+    }
+
+    /// block       -> "{" declaration* "}" ;
+    fn block(&mut self) -> Result<Block> {
+        let start = self.previous().section;
+        let mut statements = Vec::new();
 
-        if self.matches(TokenKind::Nil) {
-            return Ok(Expression::Literal(Literal::Nil));
+        while !self.check(TokenKind::RightBrace) && !self.is_done() {
+            statements.push(self.declaration()?);
         }
 
-        if let Some(number) = self.number() {
-            let literal = Literal::Number(number);
+        let mut block = Block::new(statements);
+        block.span = start.to(self.consume(TokenKind::RightBrace)?.section);
 
-            // TODO: fix this should advance on number
-            self.advance();
+        Ok(block)
+    }
+
+    /// exprStmt    -> expression ";" ;
+    fn expression_statement(&mut self) -> Result<Statement> {
+        let mut statement = ExpressionStatement::new(self.expression()?);
+        statement.span = statement
+            .span
+            .to(self.consume(TokenKind::Semicolon)?.section);
+
+        Ok(Statement::ExpressionStatement(statement))
+    }
+
+    fn expression(&mut self) -> Result<Expression> {
+        self.expression_binding(Precedence::Lowest as u8)
+    }
+
+    /// Parses an expression whose operators bind their left operand tighter than `min_power`.
+    fn expression_binding(&mut self, min_power: u8) -> Result<Expression> {
+        let mut left = self.prefix()?;
+
+        while let Some(operator) = find_operator(INFIX_OPERATORS, &self.peek().kind) {
+            let (left_power, right_power) = operator.binding_power();
+
+            if left_power < min_power {
+                break;
+            }
 
-            return Ok(Expression::Literal(literal));
+            let build = operator.build;
+            let operator = self.advance().clone();
+            let right = self.expression_binding(right_power)?;
+
+            left = build(left, operator, right)?;
         }
 
-        if let Some(string) = self.string() {
-            let literal = Literal::String(string);
+        Ok(left)
+    }
 
-            // TODO: fix this should advance on string
-            self.advance();
+    fn prefix(&mut self) -> Result<Expression> {
+        match find_operator(PREFIX_OPERATORS, &self.peek().kind) {
+            Some(operator) => {
+                let (_, right_power) = operator.binding_power();
+
+                let operator = self.advance().clone();
+                let expression = self.expression_binding(right_power)?;
 
-            return Ok(Expression::Literal(literal));
+                Ok(Expression::Unary(Unary::new(operator, expression)))
+            }
+            None => self.call(),
         }
+    }
 
-        if self.matches(TokenKind::LeftParen) {
-            let grouped = Grouping {
-                expression: std::boxed::Box::new(self.expression()?),
-            };
+    /// call        -> primary ( "(" arguments? ")" | "." IDENTIFIER )* ;
+    fn call(&mut self) -> Result<Expression> {
+        let mut callee = self.primary()?;
 
-            self.consume(TokenKind::RightParen)?;
+        loop {
+            if self.matches(TokenKind::LeftParen) {
+                callee = self.finish_call(callee)?;
+            } else if self.matches(TokenKind::Dot) {
+                let name = self.consume_identifier()?;
 
-            return Ok(Expression::Grouping(grouped));
+                callee = Expression::Get(Get::new(callee, name));
+            } else {
+                break;
+            }
         }
 
-        Err(self.unexpected())
+        Ok(callee)
     }
 
-    //
-    //
-    // Utility functions, may abstract into token walker/cursor or something
-    //
-    //
-    fn number(&mut self) -> Option<f64> {
-        // Had a single "literal" exctractor
-        match self.peek().kind {
-            TokenKind::Number(number) => Some(number),
-            _ => None,
+    /// arguments   -> expression ( "," expression )* ;
+    fn finish_call(&mut self, callee: Expression) -> Result<Expression> {
+        let mut arguments = Vec::new();
+
+        if !self.check(TokenKind::RightParen) {
+            loop {
+                if arguments.len() >= MAX_ARGUMENTS {
+                    return Err(error_at(
+                        format!("Can't have more than {} arguments", MAX_ARGUMENTS),
+                        self.peek(),
+                    ));
+                }
+
+                arguments.push(self.expression()?);
+
+                if !self.matches(TokenKind::Comma) {
+                    break;
+                }
+            }
         }
+
+        let paren = self.consume(TokenKind::RightParen)?.clone();
+
+        Ok(Expression::Call(Call::new(callee, paren, arguments)))
     }
 
-    fn string(&mut self) -> Option<String> {
-        match &self.peek().kind {
-            TokenKind::String(string) => Some(string.clone()),
-            _ => None,
-        }
+    /// primary     -> NUMBER | STRING | IDENTIFIER | "true" | "false" | "nil" | "(" expression ")" ;
+    fn primary(&mut self) -> Result<Expression> {
+        let section = self.peek().section;
+
+        let literal = match &self.peek().kind {
+            TokenKind::False => Literal::False(section),
+            TokenKind::True => Literal::True(section),
+            TokenKind::Nil => Literal::Nil(section),
+            TokenKind::Number(number) => Literal::Number(*number, section),
+            TokenKind::String(string) => Literal::String(string.clone(), section),
+            TokenKind::Identifier(_) => {
+                let name = self.advance().clone();
+
+                return Ok(Expression::Variable(Variable::new(name)));
+            }
+            TokenKind::LeftParen => {
+                self.advance();
+
+                let mut grouped = Grouping::new(self.expression()?);
+                grouped.span = section.to(self.consume(TokenKind::RightParen)?.section);
+
+                return Ok(Expression::Grouping(grouped));
+            }
+            _ => return Err(self.unexpected()),
+        };
+
+        self.advance();
+
+        Ok(Expression::Literal(literal))
     }
 
+    //
+    //
+    // Utility functions, may abstract into token walker/cursor or something
+    //
+    //
     fn matches(&mut self, kind: TokenKind) -> bool {
         if self.check(kind) {
             self.advance();
@@ -208,11 +613,7 @@ impl Parser {
     }
 
     fn check(&self, kind: TokenKind) -> bool {
-        if self.is_done() {
-            false
-        } else {
-            self.peek().kind == kind
-        }
+        self.peek().kind == kind
     }
 
     fn advance(&mut self) -> &Token {
@@ -249,16 +650,22 @@ impl Parser {
         error_at(msg, current)
     }
 
-    fn consume(&mut self, expected: TokenKind) -> Result<()> {
+    fn unexpected_after(&self, what: &str) -> Error {
         let current = self.peek();
 
-        if current.kind == expected {
-            self.advance();
+        let msg = format!("Unexpected token '{}' after {}", current.lexeme, what);
+
+        error_at(msg, current)
+    }
 
-            Ok(())
+    fn consume(&mut self, expected: TokenKind) -> Result<&Token> {
+        let current = self.peek();
+
+        if current.kind == expected {
+            Ok(self.advance())
         } else {
             let msg = format!(
-                "Expecting to find '{:?}' fount '{}' instead",
+                "Expecting to find '{:?}' found '{}' instead",
                 expected, current.lexeme
             );
 
@@ -266,8 +673,29 @@ impl Parser {
         }
     }
 
+    fn consume_identifier(&mut self) -> Result<Token> {
+        let current = self.peek();
+
+        if let TokenKind::Identifier(_) = current.kind {
+            Ok(self.advance().clone())
+        } else {
+            let msg = format!(
+                "Expecting to find 'Identifier' found '{}' instead",
+                current.lexeme
+            );
+
+            Err(error_at(msg, current))
+        }
+    }
+
+    /// Skips tokens until what is likely the start of the next statement.
     fn synchronize(&mut self) {
-        while !self.is_done() && !self.at_synchronization_point() {
+        self.advance();
+
+        while !self.is_done()
+            && self.previous().kind != TokenKind::Semicolon
+            && !self.at_synchronization_point()
+        {
             self.advance();
         }
     }
@@ -286,20 +714,209 @@ impl Parser {
     }
 }
 
-macro_rules! matches_any {
-    ($e:expr) => {{
-        self.matches($e)
-    }};
-
-    // Decompose multiple `eval`s recursively
-    ($e:expr, $($es:expr),+) => {{
-        self.matches($e) (|| self.matches($es))+
-    }};
-}
-
 fn error_at(msg: impl Into<std::borrow::Cow<'static, str>>, token: &Token) -> Error {
     ErrorBuilder::new()
         .message(msg)
         .section(token.section)
         .build()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Scanner;
+    use crate::parser::ast::prefix_printer::PrefixPrinter;
+    use crate::text::SourceMap;
+
+    fn parse(source: &str) -> Result<Expression> {
+        let mut sources = SourceMap::new();
+        let file = sources.add("test.lox", source);
+
+        let tokens = Scanner::new(sources.file(file)).scan_tokens().tokens;
+
+        Parser::new(tokens).parse()
+    }
+
+    fn print(source: &str) -> String {
+        PrefixPrinter::new().print(&parse(source).expect("valid expression"))
+    }
+
+    fn parse_program(source: &str) -> std::result::Result<Vec<Statement>, ErrorList> {
+        let mut sources = SourceMap::new();
+        let file = sources.add("test.lox", source);
+
+        let tokens = Scanner::new(sources.file(file)).scan_tokens().tokens;
+
+        Parser::new(tokens).parse_program()
+    }
+
+    fn print_program(source: &str) -> String {
+        PrefixPrinter::new().print_program(&parse_program(source).expect("valid program"))
+    }
+
+    #[test]
+    fn operators_bind_by_precedence() {
+        assert_eq!("(+ 1 (* 2 3))", print("1 + 2 * 3"));
+        assert_eq!("(== (< 1 2) (>= 3 (- 4 5)))", print("1 < 2 == 3 >= 4 - 5"));
+        assert_eq!("(* (- 1) (grouping (+ 2 3)))", print("-1 * (2 + 3)"));
+    }
+
+    #[test]
+    fn binary_operators_are_left_associative() {
+        assert_eq!("(- (- 1 2) 3)", print("1 - 2 - 3"));
+        assert_eq!("(/ (* 1 2) 3)", print("1 * 2 / 3"));
+    }
+
+    #[test]
+    fn prefix_operators_nest() {
+        assert_eq!("(! (! (- true)))", print("!!-true"));
+    }
+
+    #[test]
+    fn nodes_span_their_source() {
+        use crate::text::Spanned;
+
+        let columns = |span: crate::text::TextSection| (span.start.column, span.end.column);
+
+        let expression = parse("-(1 + 2) * 3").unwrap();
+        assert_eq!((1, 13), columns(expression.span()));
+
+        let Expression::Binary(binary) = expression else {
+            panic!("expected a binary expression");
+        };
+        assert_eq!((1, 9), columns(binary.left.span()));
+        assert_eq!((12, 13), columns(binary.right.span()));
+
+        let Expression::Unary(unary) = &*binary.left else {
+            panic!("expected an unary expression");
+        };
+        assert_eq!((2, 9), columns(unary.expression.span()));
+    }
+
+    #[test]
+    fn malformed_inputs_report_the_offending_token() {
+        let cases = [
+            ("", "Unexpected token 'EOF'", 1, 1),
+            ("1 2", "Unexpected token '2' after expression", 1, 3),
+            ("nil nil", "Unexpected token 'nil' after expression", 1, 5),
+            ("(1 + 2) 3", "Unexpected token '3' after expression", 1, 9),
+            ("1 )", "Unexpected token ')' after expression", 1, 3),
+            (
+                "1 +\n  -2 \"a\"",
+                "Unexpected token '\"a\"' after expression",
+                2,
+                6,
+            ),
+            ("1 +", "Unexpected token 'EOF'", 1, 4),
+            ("1 + * 2", "Unexpected token '*'", 1, 5),
+            (")", "Unexpected token ')'", 1, 1),
+            ("!", "Unexpected token 'EOF'", 1, 2),
+            (
+                "(1",
+                "Expecting to find 'RightParen' found 'EOF' instead",
+                1,
+                3,
+            ),
+            (
+                "(1 2)",
+                "Expecting to find 'RightParen' found '2' instead",
+                1,
+                4,
+            ),
+            ("()", "Unexpected token ')'", 1, 2),
+        ];
+
+        for (source, message, line, column) in cases {
+            let error = parse(source).expect_err(source);
+
+            assert_eq!(message, error.message, "{:?}", source);
+            assert_eq!(
+                (line, column),
+                (error.section.start.line, error.section.start.column),
+                "{:?}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn assignment_is_right_associative_and_binds_loosest() {
+        assert_eq!(
+            "(= a (= b (or c (and d e))))",
+            print("a = b = c or d and e")
+        );
+        assert_eq!("(call (call f 1 (+ 2 3)))", print("f(1, 2 + 3)()"));
+    }
+
+    #[test]
+    fn properties_chain_with_calls_and_can_be_assigned() {
+        assert_eq!("(call (. (. a b) c) 1)", print("a.b.c(1)"));
+        assert_eq!("(= (. (call f) x) (= y 2))", print("f().x = y = 2"));
+        assert_eq!("(. 1 x)", print("1.x"));
+    }
+
+    #[test]
+    fn statements_are_parsed() {
+        assert_eq!(
+            "(var a 1)\n(print a)\n(block (; (= a 2)))",
+            print_program("var a = 1; print a; { a = 2; }")
+        );
+        assert_eq!(
+            "(if a (print 1) (print 2))\n(while true (block))",
+            print_program("if (a) print 1; else print 2; while (true) {}")
+        );
+        assert_eq!(
+            "(fun add (a b) (block (return (+ a b))))",
+            print_program("fun add(a, b) { return a + b; }")
+        );
+    }
+
+    #[test]
+    fn for_loops_are_desugared_into_while() {
+        assert_eq!(
+            "(block (var i 0) (while (< i 3) (block (print i) (; (= i (+ i 1))))))",
+            print_program("for (var i = 0; i < 3; i = i + 1) print i;")
+        );
+        assert_eq!(
+            "(block (while true (print 1)))",
+            print_program("for (;;) print 1;")
+        );
+    }
+
+    #[test]
+    fn statements_span_from_keyword_to_terminator() {
+        use crate::text::Spanned;
+
+        let statements = parse_program("var a = 1;\nif (a) { print a; }").unwrap();
+
+        let spans = statements
+            .iter()
+            .map(|statement| {
+                let span = statement.span();
+
+                (span.start.line, span.start.column, span.end.column)
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(vec![(1, 1, 11), (2, 1, 20)], spans);
+    }
+
+    #[test]
+    fn every_malformed_statement_is_reported() {
+        let errors = parse_program("var = 1;\nprint 1\nvar b = 2;\n1 = 2;").unwrap_err();
+
+        let reported = errors
+            .iter()
+            .map(|error| (error.message.as_ref(), error.section.start.line))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            vec![
+                ("Expecting to find 'Identifier' found '=' instead", 1),
+                ("Expecting to find 'Semicolon' found 'var' instead", 3),
+                ("Invalid assignment target", 4),
+            ],
+            reported
+        );
+    }
+}
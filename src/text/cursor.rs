@@ -1,22 +1,50 @@
+use crate::text::FileId;
 use crate::text::Position;
 
 #[derive(Clone, Copy, Default, Debug)]
 pub struct TextSection {
+    pub file: FileId,
     pub start: Position,
     pub end: Position,
 }
 
+impl TextSection {
+    /// Section going from the start of the first section to the end of the last one.
+    pub fn covering(sections: impl IntoIterator<Item = TextSection>) -> TextSection {
+        let mut sections = sections.into_iter();
+
+        match sections.next() {
+            Some(first) => TextSection {
+                end: sections.last().unwrap_or(first).end,
+                ..first
+            },
+            None => TextSection::default(),
+        }
+    }
+
+    pub fn to(self, other: TextSection) -> TextSection {
+        Self::covering([self, other])
+    }
+}
+
+/// Anything that can be traced back to a section of the source.
+pub trait Spanned {
+    fn span(&self) -> TextSection;
+}
+
 pub struct TextCursor {
-    text: Vec<char>,
+    file: FileId,
+    text: String,
 
     section_start: Position,
     current_position: Position,
 }
 
 impl TextCursor {
-    pub fn new<T: AsRef<str>>(text: T) -> Self {
+    pub fn new<T: AsRef<str>>(file: FileId, text: T) -> Self {
         Self {
-            text: text.as_ref().chars().collect(),
+            file,
+            text: text.as_ref().to_string(),
             section_start: Position::new(),
             current_position: Position::new(),
         }
@@ -27,7 +55,16 @@ impl TextCursor {
     }
 
     pub fn current(&self) -> Option<char> {
-        self.text.get(self.current_position.offset).copied()
+        self.rest().next()
+    }
+
+    /// The character after the current one.
+    pub fn lookahead(&self) -> Option<char> {
+        self.rest().nth(1)
+    }
+
+    fn rest(&self) -> std::str::Chars<'_> {
+        self.text[self.current_position.offset..].chars()
     }
 
     pub fn next(&mut self) -> Option<char> {
@@ -41,7 +78,7 @@ impl TextCursor {
     pub fn consume(&mut self) {
         match self.current() {
             Some('\n') => self.current_position.new_line(),
-            Some(_) => self.current_position.advance(),
+            Some(c) => self.current_position.advance(c),
             None => (),
         }
     }
@@ -50,16 +87,17 @@ impl TextCursor {
         self.current().is_none()
     }
 
-    pub fn section_slice(&self) -> &[char] {
+    pub fn section_slice(&self) -> &str {
         &self.text[self.section_start.offset..self.current_position.offset]
     }
 
     pub fn section_string(&self) -> String {
-        self.section_slice().iter().collect()
+        self.section_slice().to_string()
     }
 
     pub fn section(&self) -> TextSection {
         TextSection {
+            file: self.file,
             start: self.section_start,
             end: self.current_position,
         }
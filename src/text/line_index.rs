@@ -0,0 +1,158 @@
+/// A line and column pair, both starting at 1 like [`Position`](crate::text::Position). Whether the
+/// column counts chars or UTF-16 code units depends on the method that produced it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LineCol {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Byte offsets at which every line of a text starts, so any offset can be mapped back to its line
+/// with a binary search instead of rescanning the text from the beginning.
+///
+/// The index doesn't keep the text around, the conversions that need to count chars within a line
+/// take it as an argument and expect the same text the index was built from.
+#[derive(Clone, Debug)]
+pub struct LineIndex {
+    line_starts: Vec<usize>,
+    len: usize,
+}
+
+impl LineIndex {
+    pub fn new(text: &str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(offset, _)| offset + 1))
+            .collect();
+
+        Self {
+            line_starts,
+            len: text.len(),
+        }
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    /// Byte offset where the given line starts.
+    pub fn line_start(&self, line: usize) -> Option<usize> {
+        line.checked_sub(1)
+            .and_then(|index| self.line_starts.get(index))
+            .copied()
+    }
+
+    /// Line containing the given byte offset, offsets past the end belong to the last line.
+    pub fn line(&self, offset: usize) -> usize {
+        self.line_starts.partition_point(|&start| start <= offset)
+    }
+
+    /// Converts a byte offset into a line and char column.
+    pub fn line_col(&self, text: &str, offset: usize) -> LineCol {
+        self.line_col_with(text, offset, |line| line.chars().count())
+    }
+
+    /// Converts a byte offset into a line and UTF-16 column, as used by most editors.
+    pub fn line_col_utf16(&self, text: &str, offset: usize) -> LineCol {
+        self.line_col_with(text, offset, |line| line.encode_utf16().count())
+    }
+
+    /// Converts a line and char column into a byte offset.
+    pub fn offset(&self, text: &str, LineCol { line, column }: LineCol) -> Option<usize> {
+        self.offset_with(text, line, column, |_| 1)
+    }
+
+    /// Converts a line and UTF-16 column into a byte offset.
+    pub fn offset_utf16(&self, text: &str, LineCol { line, column }: LineCol) -> Option<usize> {
+        self.offset_with(text, line, column, char::len_utf16)
+    }
+
+    fn line_col_with<F>(&self, text: &str, offset: usize, measure: F) -> LineCol
+    where
+        F: Fn(&str) -> usize,
+    {
+        let offset = floor_char_boundary(text, offset.min(self.len));
+        let line = self.line(offset);
+        let start = self.line_starts[line - 1];
+
+        LineCol {
+            line,
+            column: measure(&text[start..offset]) + 1,
+        }
+    }
+
+    fn offset_with<F>(&self, text: &str, line: usize, column: usize, width: F) -> Option<usize>
+    where
+        F: Fn(char) -> usize,
+    {
+        let start = self.line_start(line)?;
+        // Line breaks are not part of the line they end.
+        let end = self.line_start(line + 1).map_or(self.len, |next| next - 1);
+
+        let mut remaining = column.checked_sub(1)?;
+        let mut offset = start;
+
+        for c in text[start..end].chars() {
+            if remaining == 0 {
+                return Some(offset);
+            }
+
+            // A column in the middle of a char (e.g. half a surrogate pair) is not addressable.
+            remaining = remaining.checked_sub(width(c))?;
+            offset += c.len_utf8();
+        }
+
+        (remaining == 0).then_some(offset)
+    }
+}
+
+fn floor_char_boundary(text: &str, mut offset: usize) -> usize {
+    while !text.is_char_boundary(offset) {
+        offset -= 1;
+    }
+
+    offset
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "var a = 1;\nprint \"é😀!\";\n\nnil";
+
+    #[test]
+    fn offsets_map_to_their_line() {
+        let index = LineIndex::new(TEXT);
+
+        assert_eq!(4, index.line_count());
+        assert_eq!(Some(11), index.line_start(2));
+        assert_eq!(None, index.line_start(0));
+        assert_eq!(1, index.line(10));
+        assert_eq!(2, index.line(11));
+        assert_eq!(4, index.line(TEXT.len() + 10));
+    }
+
+    #[test]
+    fn columns_count_chars_or_utf16_units() {
+        let index = LineIndex::new(TEXT);
+        let bang = TEXT.find('!').unwrap();
+
+        assert_eq!(at(2, 10), index.line_col(TEXT, bang));
+        assert_eq!(at(2, 11), index.line_col_utf16(TEXT, bang));
+    }
+
+    #[test]
+    fn columns_map_back_to_offsets() {
+        let index = LineIndex::new(TEXT);
+        let bang = TEXT.find('!').unwrap();
+
+        assert_eq!(Some(bang), index.offset(TEXT, at(2, 10)));
+        assert_eq!(Some(bang), index.offset_utf16(TEXT, at(2, 11)));
+        assert_eq!(None, index.offset_utf16(TEXT, at(2, 10)));
+        assert_eq!(None, index.offset(TEXT, at(1, 12)));
+        assert_eq!(Some(TEXT.len()), index.offset(TEXT, at(4, 4)));
+        assert_eq!(None, index.offset(TEXT, at(4, 5)));
+    }
+
+    fn at(line: usize, column: usize) -> LineCol {
+        LineCol { line, column }
+    }
+}
@@ -1,6 +1,15 @@
 mod cursor;
+mod line_index;
 mod position;
+mod source_map;
 
-pub use cursor::TextCursor;
+pub use cursor::Spanned;
+pub(crate) use cursor::TextCursor;
 pub use cursor::TextSection;
+pub use line_index::LineCol;
+pub use line_index::LineIndex;
 pub use position::Position;
+pub use source_map::FileId;
+pub use source_map::Location;
+pub use source_map::SourceFile;
+pub use source_map::SourceMap;
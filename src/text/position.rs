@@ -1,3 +1,5 @@
+/// Where a char is in a source, `offset` counting bytes so it can index the source text and be
+/// given to a [`LineIndex`](crate::text::LineIndex).
 #[derive(Clone, Copy, Debug, Default)]
 pub struct Position {
     pub line: usize,
@@ -20,9 +22,10 @@ impl Position {
         self.offset += 1;
     }
 
-    pub fn advance(&mut self) {
+    /// Moves past the given char, which must not be a line break.
+    pub fn advance(&mut self, c: char) {
         self.column += 1;
-        self.offset += 1;
+        self.offset += c.len_utf8();
     }
 }
 
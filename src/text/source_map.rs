@@ -0,0 +1,180 @@
+use crate::text::LineCol;
+use crate::text::LineIndex;
+use crate::text::TextSection;
+
+/// Identifies one of the sources loaded into a [`SourceMap`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct FileId(usize);
+
+impl FileId {
+    /// No file, what sections built without one point into. Rendered as `<unknown>`.
+    pub const UNKNOWN: FileId = FileId(usize::MAX);
+
+    pub fn index(&self) -> usize {
+        self.0
+    }
+}
+
+impl Default for FileId {
+    fn default() -> Self {
+        Self::UNKNOWN
+    }
+}
+
+#[derive(Debug)]
+pub struct SourceFile {
+    id: FileId,
+    name: String,
+    text: String,
+    lines: LineIndex,
+}
+
+impl SourceFile {
+    pub fn id(&self) -> FileId {
+        self.id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn lines(&self) -> &LineIndex {
+        &self.lines
+    }
+
+    /// Line and char column of the given byte offset.
+    pub fn line_col(&self, offset: usize) -> LineCol {
+        self.lines.line_col(&self.text, offset)
+    }
+
+    /// Line and UTF-16 column of the given byte offset.
+    pub fn line_col_utf16(&self, offset: usize) -> LineCol {
+        self.lines.line_col_utf16(&self.text, offset)
+    }
+}
+
+/// Owns every source given to the interpreter, be it a script, an imported module or a line typed
+/// in the REPL, so spans can be traced back to the place they came from.
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, name: impl Into<String>, text: impl Into<String>) -> FileId {
+        let id = FileId(self.files.len());
+        let text = text.into();
+
+        self.files.push(SourceFile {
+            id,
+            name: name.into(),
+            lines: LineIndex::new(&text),
+            text,
+        });
+
+        id
+    }
+
+    pub fn get(&self, id: FileId) -> Option<&SourceFile> {
+        self.files.get(id.0)
+    }
+
+    pub fn file(&self, id: FileId) -> &SourceFile {
+        self.get(id).expect("FileId from a different SourceMap")
+    }
+
+    pub fn files(&self) -> impl Iterator<Item = &SourceFile> {
+        self.files.iter()
+    }
+
+    /// Resolves where the given section starts.
+    pub fn location(&self, section: &TextSection) -> Location<'_> {
+        Location {
+            path: self.get(section.file).map_or("<unknown>", SourceFile::name),
+            line: section.start.line,
+            column: section.start.column,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Location<'a> {
+    pub path: &'a str,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl std::fmt::Display for Location<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}:{}:{}", self.path, self.line, self.column)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Scanner;
+    use crate::text::Position;
+
+    #[test]
+    fn sections_resolve_to_their_own_file() {
+        let mut sources = SourceMap::new();
+
+        let main = sources.add("main.lox", "1 + 2");
+        let repl = sources.add("<repl:1>", "\n  nil");
+
+        let section = TextSection {
+            file: repl,
+            start: Position {
+                line: 2,
+                column: 3,
+                offset: 3,
+            },
+            end: Default::default(),
+        };
+
+        assert_ne!(main, repl);
+        assert_eq!("main.lox", sources.file(main).name());
+        assert_eq!("<repl:1>:2:3", sources.location(&section).to_string());
+    }
+
+    #[test]
+    fn default_sections_point_nowhere() {
+        let mut sources = SourceMap::new();
+        sources.add("main.lox", "1 + 2");
+
+        let section = TextSection::default();
+
+        assert_eq!(FileId::UNKNOWN, section.file);
+        assert_eq!("<unknown>", sources.location(&section).path);
+    }
+
+    #[test]
+    fn positions_are_byte_offsets_into_the_text() {
+        let mut sources = SourceMap::new();
+        let id = sources.add("main.lox", "print \"é😀\";\n  \"ü\" + nope;");
+        let file = sources.file(id);
+
+        let tokens = Scanner::new(file).scan_tokens().tokens;
+        let nope = tokens
+            .iter()
+            .find(|token| &*token.lexeme == "nope")
+            .unwrap();
+        let start = nope.section.start;
+
+        assert_eq!(
+            Some("nope"),
+            file.text().get(start.offset..nope.section.end.offset)
+        );
+        assert_eq!((2, 9), (start.line, start.column));
+        assert_eq!(LineCol { line: 2, column: 9 }, file.line_col(start.offset));
+    }
+}
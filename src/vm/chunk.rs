@@ -0,0 +1,260 @@
+use std::rc::Rc;
+
+use crate::intern::Symbol;
+use crate::text::TextSection;
+
+/// Instructions of the VM, each one a byte followed by its operands.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum OpCode {
+    /// `index: u16`, pushes the constant.
+    Constant,
+    Nil,
+    True,
+    False,
+    Pop,
+    /// `slot: u8`, relative to the frame.
+    GetLocal,
+    /// `slot: u8`, leaves the value on the stack.
+    SetLocal,
+    /// `name: u16`, a string constant.
+    GetGlobal,
+    /// `name: u16`, pops the value.
+    DefineGlobal,
+    /// `name: u16`, leaves the value on the stack.
+    SetGlobal,
+    /// `index: u8`, into the upvalues of the running closure.
+    GetUpvalue,
+    /// `index: u8`, leaves the value on the stack.
+    SetUpvalue,
+    Equal,
+    NotEqual,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Not,
+    Negate,
+    Print,
+    /// `offset: u16`, forward from the end of the instruction.
+    Jump,
+    /// `offset: u16`, jumps when the top of the stack is falsey, without popping it.
+    JumpIfFalse,
+    /// `offset: u16`, backwards from the end of the instruction.
+    Loop,
+    /// `arguments: u8`, the callee is under its arguments.
+    Call,
+    /// `function: u16`, then `is_local: u8, index: u8` for each of its upvalues.
+    Closure,
+    /// Moves the local at the top of the stack to the heap before popping it.
+    CloseUpvalue,
+    Return,
+    /// `name: u16`, pops the object and pushes the property. Fails for now, as the VM has no
+    /// objects.
+    GetProperty,
+    /// `name: u16`, pops the value and the object under it and pushes the value back. Fails for
+    /// now, as the VM has no objects.
+    SetProperty,
+}
+
+impl OpCode {
+    const ALL: [OpCode; 34] = [
+        OpCode::Constant,
+        OpCode::Nil,
+        OpCode::True,
+        OpCode::False,
+        OpCode::Pop,
+        OpCode::GetLocal,
+        OpCode::SetLocal,
+        OpCode::GetGlobal,
+        OpCode::DefineGlobal,
+        OpCode::SetGlobal,
+        OpCode::GetUpvalue,
+        OpCode::SetUpvalue,
+        OpCode::Equal,
+        OpCode::NotEqual,
+        OpCode::Greater,
+        OpCode::GreaterEqual,
+        OpCode::Less,
+        OpCode::LessEqual,
+        OpCode::Add,
+        OpCode::Subtract,
+        OpCode::Multiply,
+        OpCode::Divide,
+        OpCode::Not,
+        OpCode::Negate,
+        OpCode::Print,
+        OpCode::Jump,
+        OpCode::JumpIfFalse,
+        OpCode::Loop,
+        OpCode::Call,
+        OpCode::Closure,
+        OpCode::CloseUpvalue,
+        OpCode::Return,
+        OpCode::GetProperty,
+        OpCode::SetProperty,
+    ];
+}
+
+impl TryFrom<u8> for OpCode {
+    type Error = u8;
+
+    fn try_from(byte: u8) -> Result<Self, u8> {
+        OpCode::ALL.get(byte as usize).copied().ok_or(byte)
+    }
+}
+
+/// Values known at compile time.
+#[derive(Clone, Debug)]
+pub enum Constant {
+    Number(f64),
+    String(Symbol),
+    Function(Rc<FunctionProto>),
+}
+
+/// Printed the way the values they turn into are.
+impl std::fmt::Display for Constant {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Constant::Number(value) => write!(f, "{}", value),
+            Constant::String(value) => write!(f, "{}", value),
+            Constant::Function(function) => write!(f, "<fn {}>", function.name),
+        }
+    }
+}
+
+/// A compiled function, closures are created out of it at runtime.
+#[derive(Clone, Debug)]
+pub struct FunctionProto {
+    /// Empty for the top level script.
+    pub name: String,
+    pub arity: u8,
+    pub upvalue_count: usize,
+    pub chunk: Chunk,
+}
+
+/// Bytecode along with its constants and where in the source each instruction comes from.
+#[derive(Clone, Debug, Default)]
+pub struct Chunk {
+    pub code: Vec<u8>,
+    pub constants: Vec<Constant>,
+    /// Run length encoded, how many bytes in a row come from the same section.
+    spans: Vec<(TextSection, usize)>,
+}
+
+impl Chunk {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Puts back together a chunk taken apart with [`Chunk::spans`], as in loading compiled code.
+    pub fn from_parts(
+        code: Vec<u8>,
+        constants: Vec<Constant>,
+        spans: Vec<(TextSection, usize)>,
+    ) -> Self {
+        Self {
+            code,
+            constants,
+            spans,
+        }
+    }
+
+    pub fn write(&mut self, byte: u8, span: TextSection) {
+        self.code.push(byte);
+
+        match self.spans.last_mut() {
+            Some((last, run)) if same_section(last, &span) => *run += 1,
+            _ => self.spans.push((span, 1)),
+        }
+    }
+
+    pub fn add_constant(&mut self, constant: Constant) -> usize {
+        self.constants.push(constant);
+
+        self.constants.len() - 1
+    }
+
+    /// Section of the source the byte at `offset` was compiled from.
+    pub fn span(&self, offset: usize) -> TextSection {
+        let mut end = 0;
+
+        for (span, run) in self.spans.iter() {
+            end += run;
+
+            if offset < end {
+                return *span;
+            }
+        }
+
+        TextSection::default()
+    }
+
+    pub fn line(&self, offset: usize) -> usize {
+        self.span(offset).start.line
+    }
+
+    pub fn spans(&self) -> impl Iterator<Item = &(TextSection, usize)> {
+        self.spans.iter()
+    }
+
+    pub fn read_u16(&self, offset: usize) -> u16 {
+        u16::from_be_bytes([self.code[offset], self.code[offset + 1]])
+    }
+}
+
+fn same_section(left: &TextSection, right: &TextSection) -> bool {
+    left.file == right.file
+        && left.start.offset == right.start.offset
+        && left.end.offset == right.end.offset
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::text::Position;
+
+    fn section(line: usize, offset: usize) -> TextSection {
+        let position = Position {
+            line,
+            column: 1,
+            offset,
+        };
+
+        TextSection {
+            file: Default::default(),
+            start: position,
+            end: position,
+        }
+    }
+
+    #[test]
+    fn spans_are_run_length_encoded() {
+        let mut chunk = Chunk::new();
+
+        chunk.write(OpCode::Constant as u8, section(1, 0));
+        chunk.write(0, section(1, 0));
+        chunk.write(0, section(1, 0));
+        chunk.write(OpCode::Print as u8, section(2, 5));
+        chunk.write(OpCode::Return as u8, section(3, 9));
+
+        assert_eq!(3, chunk.spans().count());
+        assert_eq!(
+            vec![1, 1, 1, 2, 3, 0],
+            (0..6).map(|offset| chunk.line(offset)).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn opcodes_round_trip_through_bytes() {
+        for op in OpCode::ALL {
+            assert_eq!(Ok(op), OpCode::try_from(op as u8));
+        }
+
+        assert_eq!(Err(200), OpCode::try_from(200));
+    }
+}
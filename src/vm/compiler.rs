@@ -0,0 +1,674 @@
+use std::rc::Rc;
+
+use crate::error::Error;
+use crate::error::ErrorBuilder;
+use crate::intern::Symbol;
+use crate::lexer::token::Token;
+use crate::lexer::token::TokenKind;
+use crate::parser::ast::Assign;
+use crate::parser::ast::Binary;
+use crate::parser::ast::Block;
+use crate::parser::ast::Call;
+use crate::parser::ast::Expression;
+use crate::parser::ast::ExpressionStatement;
+use crate::parser::ast::Function;
+use crate::parser::ast::Get;
+use crate::parser::ast::Grouping;
+use crate::parser::ast::If;
+use crate::parser::ast::Literal;
+use crate::parser::ast::Logical;
+use crate::parser::ast::Print;
+use crate::parser::ast::Return;
+use crate::parser::ast::Set;
+use crate::parser::ast::Statement;
+use crate::parser::ast::SyntaxVisitor;
+use crate::parser::ast::Unary;
+use crate::parser::ast::Var;
+use crate::parser::ast::Variable;
+use crate::parser::ast::Visitable;
+use crate::parser::ast::While;
+use crate::text::Spanned;
+use crate::text::TextSection;
+use crate::vm::chunk::Chunk;
+use crate::vm::chunk::Constant;
+use crate::vm::chunk::FunctionProto;
+use crate::vm::chunk::OpCode;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Locals and upvalues are addressed with a byte.
+const MAX_LOCALS: usize = u8::MAX as usize + 1;
+const MAX_UPVALUES: usize = u8::MAX as usize + 1;
+
+struct Local {
+    name: Symbol,
+    /// Unset while its initializer is being compiled.
+    depth: Option<usize>,
+    captured: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct UpvalueRef {
+    index: u8,
+    /// Whether it captures a local of the enclosing function or one of its upvalues.
+    is_local: bool,
+}
+
+/// The function being compiled, nested declarations push a new one.
+struct FunctionState {
+    proto: FunctionProto,
+    locals: Vec<Local>,
+    upvalues: Vec<UpvalueRef>,
+    scope_depth: usize,
+}
+
+impl FunctionState {
+    fn new(name: String, arity: u8) -> Self {
+        Self {
+            proto: FunctionProto {
+                name,
+                arity,
+                upvalue_count: 0,
+                chunk: Chunk::new(),
+            },
+            // The first slot holds the function being called.
+            locals: vec![Local {
+                name: Symbol::intern(""),
+                depth: Some(0),
+                captured: false,
+            }],
+            upvalues: Vec::new(),
+            scope_depth: 0,
+        }
+    }
+}
+
+/// Compiles the AST to bytecode. Locals live in stack slots, the ones captured by closures are
+/// moved to the heap when their scope ends.
+pub struct Compiler {
+    functions: Vec<FunctionState>,
+}
+
+impl Compiler {
+    fn new() -> Self {
+        Self {
+            functions: vec![FunctionState::new(String::new(), 0)],
+        }
+    }
+
+    /// Compiles the statements as the top level script.
+    pub fn compile(statements: &[Statement]) -> Result<FunctionProto> {
+        let mut compiler = Self::new();
+
+        for statement in statements {
+            statement.accept(&mut compiler)?;
+        }
+
+        let end = statements.last().map(Spanned::span).unwrap_or_default();
+        compiler.emit(OpCode::Nil, end);
+        compiler.emit(OpCode::Return, end);
+
+        Ok(compiler.finish().0)
+    }
+
+    /// Compiles a script returning the value of the expression.
+    pub fn compile_expression(expression: &Expression) -> Result<FunctionProto> {
+        let mut compiler = Self::new();
+
+        expression.accept(&mut compiler)?;
+        compiler.emit(OpCode::Return, expression.span());
+
+        Ok(compiler.finish().0)
+    }
+
+    fn current(&mut self) -> &mut FunctionState {
+        self.functions
+            .last_mut()
+            .expect("there's always a function")
+    }
+
+    fn chunk(&mut self) -> &mut Chunk {
+        &mut self.current().proto.chunk
+    }
+
+    fn finish(&mut self) -> (FunctionProto, Vec<UpvalueRef>) {
+        let mut state = self.functions.pop().expect("there's always a function");
+        state.proto.upvalue_count = state.upvalues.len();
+
+        (state.proto, state.upvalues)
+    }
+
+    fn emit(&mut self, op: OpCode, span: TextSection) {
+        self.chunk().write(op as u8, span);
+    }
+
+    fn emit_byte(&mut self, byte: u8, span: TextSection) {
+        self.chunk().write(byte, span);
+    }
+
+    fn emit_u16(&mut self, value: u16, span: TextSection) {
+        for byte in value.to_be_bytes() {
+            self.emit_byte(byte, span);
+        }
+    }
+
+    fn emit_constant(&mut self, constant: Constant, span: TextSection) -> Result<()> {
+        let index = self.make_constant(constant, span)?;
+
+        self.emit(OpCode::Constant, span);
+        self.emit_u16(index, span);
+
+        Ok(())
+    }
+
+    fn make_constant(&mut self, constant: Constant, span: TextSection) -> Result<u16> {
+        let index = self.chunk().add_constant(constant);
+
+        u16::try_from(index).map_err(|_| error_at("Too many constants in one chunk", span))
+    }
+
+    fn name_constant(&mut self, name: &Token) -> Result<u16> {
+        self.make_constant(Constant::String(name.lexeme.clone()), name.section)
+    }
+
+    /// Emits a jump to be patched once the target is known, returns where its offset is.
+    fn emit_jump(&mut self, op: OpCode, span: TextSection) -> usize {
+        self.emit(op, span);
+        self.emit_u16(u16::MAX, span);
+
+        self.chunk().code.len() - 2
+    }
+
+    fn patch_jump(&mut self, offset: usize, span: TextSection) -> Result<()> {
+        let jump = self.chunk().code.len() - offset - 2;
+        let jump = u16::try_from(jump).map_err(|_| error_at("Too much code to jump over", span))?;
+
+        self.chunk().code[offset..offset + 2].copy_from_slice(&jump.to_be_bytes());
+
+        Ok(())
+    }
+
+    fn emit_loop(&mut self, start: usize, span: TextSection) -> Result<()> {
+        self.emit(OpCode::Loop, span);
+
+        let jump = self.chunk().code.len() - start + 2;
+        let jump = u16::try_from(jump).map_err(|_| error_at("Loop body too large", span))?;
+
+        self.emit_u16(jump, span);
+
+        Ok(())
+    }
+
+    fn begin_scope(&mut self) {
+        self.current().scope_depth += 1;
+    }
+
+    fn end_scope(&mut self, span: TextSection) {
+        let state = self.current();
+        state.scope_depth -= 1;
+
+        let depth = state.scope_depth;
+        let mut ops = Vec::new();
+
+        while let Some(local) = state.locals.last() {
+            if local.depth.is_some_and(|local| local <= depth) {
+                break;
+            }
+
+            ops.push(if local.captured {
+                OpCode::CloseUpvalue
+            } else {
+                OpCode::Pop
+            });
+
+            state.locals.pop();
+        }
+
+        for op in ops {
+            self.emit(op, span);
+        }
+    }
+
+    /// Adds the variable to the current scope, globals are looked up by name instead.
+    fn declare(&mut self, name: &Token) -> Result<()> {
+        let state = self.current();
+
+        if state.scope_depth == 0 {
+            return Ok(());
+        }
+
+        let depth = state.scope_depth;
+
+        let redeclared = state
+            .locals
+            .iter()
+            .rev()
+            .take_while(|local| local.depth.is_none_or(|local| local >= depth))
+            .any(|local| local.name == name.lexeme);
+
+        if redeclared {
+            return Err(error_at(
+                format!("Already a variable named '{}' in this scope", name.lexeme),
+                name.section,
+            ));
+        }
+
+        if state.locals.len() == MAX_LOCALS {
+            return Err(error_at(
+                "Too many local variables in function",
+                name.section,
+            ));
+        }
+
+        state.locals.push(Local {
+            name: name.lexeme.clone(),
+            depth: None,
+            captured: false,
+        });
+
+        Ok(())
+    }
+
+    /// Makes the declared variable usable, the value is on top of the stack.
+    fn define(&mut self, name: &Token) -> Result<()> {
+        let state = self.current();
+
+        if state.scope_depth > 0 {
+            let depth = state.scope_depth;
+
+            if let Some(local) = state.locals.last_mut() {
+                local.depth = Some(depth);
+            }
+
+            return Ok(());
+        }
+
+        let constant = self.name_constant(name)?;
+
+        self.emit(OpCode::DefineGlobal, name.section);
+        self.emit_u16(constant, name.section);
+
+        Ok(())
+    }
+
+    fn resolve_local(&self, function: usize, name: &Token) -> Result<Option<u8>> {
+        let locals = &self.functions[function].locals;
+
+        match locals.iter().rposition(|local| local.name == name.lexeme) {
+            Some(slot) if locals[slot].depth.is_none() => Err(error_at(
+                "Can't read local variable in its own initializer",
+                name.section,
+            )),
+            Some(slot) => Ok(Some(slot as u8)),
+            None => Ok(None),
+        }
+    }
+
+    fn resolve_upvalue(&mut self, function: usize, name: &Token) -> Result<Option<u8>> {
+        if function == 0 {
+            return Ok(None);
+        }
+
+        if let Some(slot) = self.resolve_local(function - 1, name)? {
+            self.functions[function - 1].locals[slot as usize].captured = true;
+
+            return self.add_upvalue(function, slot, true, name).map(Some);
+        }
+
+        match self.resolve_upvalue(function - 1, name)? {
+            Some(index) => self.add_upvalue(function, index, false, name).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn add_upvalue(
+        &mut self,
+        function: usize,
+        index: u8,
+        is_local: bool,
+        name: &Token,
+    ) -> Result<u8> {
+        let upvalues = &mut self.functions[function].upvalues;
+        let upvalue = UpvalueRef { index, is_local };
+
+        if let Some(existing) = upvalues.iter().position(|other| *other == upvalue) {
+            return Ok(existing as u8);
+        }
+
+        if upvalues.len() == MAX_UPVALUES {
+            return Err(error_at(
+                "Too many closure variables in function",
+                name.section,
+            ));
+        }
+
+        upvalues.push(upvalue);
+
+        Ok((upvalues.len() - 1) as u8)
+    }
+
+    /// Emits the instruction reading (or writing) the variable, wherever it is.
+    fn variable(&mut self, name: &Token, set: bool) -> Result<()> {
+        let function = self.functions.len() - 1;
+
+        let (op, operand) = if let Some(slot) = self.resolve_local(function, name)? {
+            let op = if set {
+                OpCode::SetLocal
+            } else {
+                OpCode::GetLocal
+            };
+
+            (op, slot as u16)
+        } else if let Some(index) = self.resolve_upvalue(function, name)? {
+            let op = if set {
+                OpCode::SetUpvalue
+            } else {
+                OpCode::GetUpvalue
+            };
+
+            (op, index as u16)
+        } else {
+            let op = if set {
+                OpCode::SetGlobal
+            } else {
+                OpCode::GetGlobal
+            };
+
+            (op, self.name_constant(name)?)
+        };
+
+        self.emit(op, name.section);
+
+        match op {
+            OpCode::GetGlobal | OpCode::SetGlobal => self.emit_u16(operand, name.section),
+            _ => self.emit_byte(operand as u8, name.section),
+        }
+
+        Ok(())
+    }
+
+    fn function(&mut self, function: &Function) -> Result<()> {
+        // The parser caps the parameters at 255.
+        self.functions.push(FunctionState::new(
+            function.name.lexeme.to_string(),
+            function.params.len() as u8,
+        ));
+        self.begin_scope();
+
+        for param in function.params.iter() {
+            self.declare(param)?;
+            self.define(param)?;
+        }
+
+        for statement in function.body.statements.iter() {
+            statement.accept(self)?;
+        }
+
+        let end = function.body.span;
+        self.emit(OpCode::Nil, end);
+        self.emit(OpCode::Return, end);
+
+        let (proto, upvalues) = self.finish();
+        let span = function.name.section;
+        let constant = self.make_constant(Constant::Function(Rc::new(proto)), span)?;
+
+        self.emit(OpCode::Closure, span);
+        self.emit_u16(constant, span);
+
+        for upvalue in upvalues {
+            self.emit_byte(upvalue.is_local as u8, span);
+            self.emit_byte(upvalue.index, span);
+        }
+
+        Ok(())
+    }
+}
+
+impl SyntaxVisitor<Result<()>> for Compiler {
+    fn visit_expression_statement(&mut self, statement: &ExpressionStatement) -> Result<()> {
+        statement.expression.accept(self)?;
+        self.emit(OpCode::Pop, statement.span);
+
+        Ok(())
+    }
+
+    fn visit_print(&mut self, print: &Print) -> Result<()> {
+        print.expression.accept(self)?;
+        self.emit(OpCode::Print, print.keyword.section);
+
+        Ok(())
+    }
+
+    fn visit_var(&mut self, var: &Var) -> Result<()> {
+        self.declare(&var.name)?;
+
+        match &var.initializer {
+            Some(initializer) => initializer.accept(self)?,
+            None => self.emit(OpCode::Nil, var.name.section),
+        }
+
+        self.define(&var.name)
+    }
+
+    fn visit_block(&mut self, block: &Block) -> Result<()> {
+        self.begin_scope();
+
+        for statement in block.statements.iter() {
+            statement.accept(self)?;
+        }
+
+        self.end_scope(block.span);
+
+        Ok(())
+    }
+
+    fn visit_if(&mut self, statement: &If) -> Result<()> {
+        let span = statement.span;
+
+        statement.condition.accept(self)?;
+
+        let then_jump = self.emit_jump(OpCode::JumpIfFalse, span);
+        self.emit(OpCode::Pop, span);
+        statement.then_branch.accept(self)?;
+
+        let else_jump = self.emit_jump(OpCode::Jump, span);
+        self.patch_jump(then_jump, span)?;
+        self.emit(OpCode::Pop, span);
+
+        if let Some(else_branch) = &statement.else_branch {
+            else_branch.accept(self)?;
+        }
+
+        self.patch_jump(else_jump, span)
+    }
+
+    fn visit_while(&mut self, statement: &While) -> Result<()> {
+        let span = statement.span;
+        let start = self.chunk().code.len();
+
+        statement.condition.accept(self)?;
+
+        let exit = self.emit_jump(OpCode::JumpIfFalse, span);
+        self.emit(OpCode::Pop, span);
+        statement.body.accept(self)?;
+        self.emit_loop(start, span)?;
+
+        self.patch_jump(exit, span)?;
+        self.emit(OpCode::Pop, span);
+
+        Ok(())
+    }
+
+    fn visit_function(&mut self, function: &Function) -> Result<()> {
+        self.declare(&function.name)?;
+
+        // Usable right away, so the function can call itself.
+        let state = self.current();
+        if state.scope_depth > 0 {
+            let depth = state.scope_depth;
+
+            if let Some(local) = state.locals.last_mut() {
+                local.depth = Some(depth);
+            }
+        }
+
+        self.function(function)?;
+        self.define(&function.name)
+    }
+
+    fn visit_return(&mut self, statement: &Return) -> Result<()> {
+        if self.functions.len() == 1 {
+            return Err(error_at(
+                "Can't return from top-level code",
+                statement.keyword.section,
+            ));
+        }
+
+        match &statement.value {
+            Some(value) => value.accept(self)?,
+            None => self.emit(OpCode::Nil, statement.keyword.section),
+        }
+
+        self.emit(OpCode::Return, statement.keyword.section);
+
+        Ok(())
+    }
+
+    fn visit_binary(&mut self, binary: &Binary) -> Result<()> {
+        binary.left.accept(self)?;
+        binary.right.accept(self)?;
+
+        let op = match binary.operator.kind {
+            TokenKind::BangEqual => OpCode::NotEqual,
+            TokenKind::EqualEqual => OpCode::Equal,
+            TokenKind::Greater => OpCode::Greater,
+            TokenKind::GreaterEqual => OpCode::GreaterEqual,
+            TokenKind::Less => OpCode::Less,
+            TokenKind::LessEqual => OpCode::LessEqual,
+            TokenKind::Plus => OpCode::Add,
+            TokenKind::Minus => OpCode::Subtract,
+            TokenKind::Star => OpCode::Multiply,
+            TokenKind::Slash => OpCode::Divide,
+            _ => {
+                return Err(error_at(
+                    format!("Binary operator {} not supported", binary.operator.lexeme),
+                    binary.operator.section,
+                ))
+            }
+        };
+
+        self.emit(op, binary.operator.section);
+
+        Ok(())
+    }
+
+    fn visit_unary(&mut self, unary: &Unary) -> Result<()> {
+        unary.expression.accept(self)?;
+
+        let op = match unary.operator.kind {
+            TokenKind::Minus => OpCode::Negate,
+            TokenKind::Bang => OpCode::Not,
+            _ => {
+                return Err(error_at(
+                    format!("Unary operator {} not supported", unary.operator.lexeme),
+                    unary.operator.section,
+                ))
+            }
+        };
+
+        self.emit(op, unary.operator.section);
+
+        Ok(())
+    }
+
+    fn visit_grouping(&mut self, grouping: &Grouping) -> Result<()> {
+        grouping.expression.accept(self)
+    }
+
+    fn visit_literal(&mut self, literal: &Literal) -> Result<()> {
+        match literal {
+            Literal::Number(value, span) => self.emit_constant(Constant::Number(*value), *span)?,
+            Literal::String(value, span) => {
+                self.emit_constant(Constant::String(value.clone()), *span)?
+            }
+            Literal::True(span) => self.emit(OpCode::True, *span),
+            Literal::False(span) => self.emit(OpCode::False, *span),
+            Literal::Nil(span) => self.emit(OpCode::Nil, *span),
+        }
+
+        Ok(())
+    }
+
+    fn visit_variable(&mut self, variable: &Variable) -> Result<()> {
+        self.variable(&variable.name, false)
+    }
+
+    fn visit_assign(&mut self, assign: &Assign) -> Result<()> {
+        assign.value.accept(self)?;
+
+        self.variable(&assign.name, true)
+    }
+
+    fn visit_logical(&mut self, logical: &Logical) -> Result<()> {
+        let span = logical.operator.section;
+
+        logical.left.accept(self)?;
+
+        if logical.operator.kind == TokenKind::Or {
+            let else_jump = self.emit_jump(OpCode::JumpIfFalse, span);
+            let end_jump = self.emit_jump(OpCode::Jump, span);
+
+            self.patch_jump(else_jump, span)?;
+            self.emit(OpCode::Pop, span);
+            logical.right.accept(self)?;
+
+            self.patch_jump(end_jump, span)
+        } else {
+            let end_jump = self.emit_jump(OpCode::JumpIfFalse, span);
+
+            self.emit(OpCode::Pop, span);
+            logical.right.accept(self)?;
+
+            self.patch_jump(end_jump, span)
+        }
+    }
+
+    fn visit_call(&mut self, call: &Call) -> Result<()> {
+        call.callee.accept(self)?;
+
+        for argument in call.arguments.iter() {
+            argument.accept(self)?;
+        }
+
+        // The parser caps the arguments at 255.
+        self.emit(OpCode::Call, call.paren.section);
+        self.emit_byte(call.arguments.len() as u8, call.paren.section);
+
+        Ok(())
+    }
+
+    fn visit_get(&mut self, get: &Get) -> Result<()> {
+        get.object.accept(self)?;
+
+        let name = self.name_constant(&get.name)?;
+        self.emit(OpCode::GetProperty, get.name.section);
+        self.emit_u16(name, get.name.section);
+
+        Ok(())
+    }
+
+    fn visit_set(&mut self, set: &Set) -> Result<()> {
+        set.object.accept(self)?;
+        set.value.accept(self)?;
+
+        let name = self.name_constant(&set.name)?;
+        self.emit(OpCode::SetProperty, set.name.section);
+        self.emit_u16(name, set.name.section);
+
+        Ok(())
+    }
+}
+
+fn error_at(msg: impl Into<std::borrow::Cow<'static, str>>, section: TextSection) -> Error {
+    ErrorBuilder::new().message(msg).section(section).build()
+}
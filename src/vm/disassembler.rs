@@ -0,0 +1,163 @@
+use std::fmt::Write;
+
+use crate::vm::chunk::Chunk;
+use crate::vm::chunk::Constant;
+use crate::vm::chunk::FunctionProto;
+use crate::vm::chunk::OpCode;
+
+/// Lists the instructions of the function, followed by the ones of every function declared in it.
+pub fn disassemble(function: &FunctionProto) -> String {
+    let mut out = String::new();
+
+    let name = match function.name.as_str() {
+        "" => "<script>",
+        name => name,
+    };
+    writeln!(out, "== {} ==", name).unwrap();
+
+    let mut offset = 0;
+    while offset < function.chunk.code.len() {
+        let (instruction, next) = disassemble_instruction(&function.chunk, offset);
+        writeln!(out, "{}", instruction).unwrap();
+
+        offset = next;
+    }
+
+    for constant in function.chunk.constants.iter() {
+        if let Constant::Function(function) = constant {
+            out.push('\n');
+            out.push_str(&disassemble(function));
+        }
+    }
+
+    out
+}
+
+/// One instruction as `offset line name operands`, along with the offset of the next one. The line
+/// is `|` when it's the same as the previous instruction's.
+pub fn disassemble_instruction(chunk: &Chunk, offset: usize) -> (String, usize) {
+    let line = chunk.line(offset);
+    let line = if offset > 0 && line == chunk.line(offset - 1) {
+        "   |".to_string()
+    } else {
+        format!("{:4}", line)
+    };
+
+    let prefix = format!("{:04} {}", offset, line);
+
+    let op = match OpCode::try_from(chunk.code[offset]) {
+        Ok(op) => op,
+        Err(byte) => return (format!("{} Unknown opcode {}", prefix, byte), offset + 1),
+    };
+
+    let name = format!("{:?}", op);
+
+    let (operands, next) = match op {
+        OpCode::Constant
+        | OpCode::GetGlobal
+        | OpCode::DefineGlobal
+        | OpCode::SetGlobal
+        | OpCode::GetProperty
+        | OpCode::SetProperty => {
+            let index = chunk.read_u16(offset + 1);
+
+            (
+                format!("{:4} '{}'", index, chunk.constants[index as usize]),
+                offset + 3,
+            )
+        }
+        OpCode::GetLocal
+        | OpCode::SetLocal
+        | OpCode::GetUpvalue
+        | OpCode::SetUpvalue
+        | OpCode::Call => (format!("{:4}", chunk.code[offset + 1]), offset + 2),
+        OpCode::Jump | OpCode::JumpIfFalse => {
+            let jump = chunk.read_u16(offset + 1) as usize;
+
+            (format!("{:4} -> {}", offset, offset + 3 + jump), offset + 3)
+        }
+        OpCode::Loop => {
+            let jump = chunk.read_u16(offset + 1) as usize;
+
+            (format!("{:4} -> {}", offset, offset + 3 - jump), offset + 3)
+        }
+        OpCode::Closure => {
+            let index = chunk.read_u16(offset + 1);
+            let constant = &chunk.constants[index as usize];
+
+            let mut operands = format!("{:4} '{}'", index, constant);
+            let mut next = offset + 3;
+
+            if let Constant::Function(function) = constant {
+                for _ in 0..function.upvalue_count {
+                    let kind = match chunk.code[next] {
+                        1 => "local",
+                        _ => "upvalue",
+                    };
+
+                    write!(operands, " {} {}", kind, chunk.code[next + 1]).unwrap();
+                    next += 2;
+                }
+            }
+
+            (operands, next)
+        }
+        _ => (String::new(), offset + 1),
+    };
+
+    let instruction = if operands.is_empty() {
+        format!("{} {}", prefix, name)
+    } else {
+        format!("{} {:<16} {}", prefix, name, operands)
+    };
+
+    (instruction, next)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Scanner;
+    use crate::parser::Parser;
+    use crate::text::SourceMap;
+    use crate::vm::compiler::Compiler;
+
+    #[test]
+    fn instructions_show_lines_and_operands() {
+        let mut sources = SourceMap::new();
+        let file = sources.add(
+            "test.lox",
+            "var a = 1;\nfun f(b) {\n  print a or b;\n}\nf(\"x\");",
+        );
+
+        let tokens = Scanner::new(sources.file(file)).scan_tokens().tokens;
+        let statements = Parser::new(tokens).parse_program().expect("valid program");
+        let script = Compiler::compile(&statements).expect("compiled program");
+
+        let expected = "\
+== <script> ==
+0000    1 Constant            0 '1'
+0003    | DefineGlobal        1 'a'
+0006    2 Closure             2 '<fn f>'
+0009    | DefineGlobal        3 'f'
+0012    5 GetGlobal           4 'f'
+0015    | Constant            5 'x'
+0018    | Call                1
+0020    | Pop
+0021    | Nil
+0022    | Return
+
+== f ==
+0000    3 GetGlobal           0 'a'
+0003    | JumpIfFalse         3 -> 9
+0006    | Jump                6 -> 12
+0009    | Pop
+0010    | GetLocal            1
+0012    | Print
+0013    2 Nil
+0014    | Return
+";
+
+        assert_eq!(expected, disassemble(&script));
+    }
+}
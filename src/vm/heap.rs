@@ -0,0 +1,401 @@
+use std::collections::HashSet;
+use std::rc::Rc;
+
+use crate::intern::Symbol;
+use crate::vm::chunk::FunctionProto;
+use crate::vm::value::Packed;
+use crate::vm::value::Value;
+
+/// Points to an object in the [`Heap`]. Slots are reused once collected, the generation tells a
+/// stale reference apart from the object now living there. Both fit in 48 bits, for NaN-boxing, so
+/// a slot whose generation can't grow anymore is retired instead of wrapping around.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ObjRef {
+    index: u32,
+    generation: u16,
+}
+
+impl ObjRef {
+    pub fn index(&self) -> usize {
+        self.index as usize
+    }
+
+    pub fn to_bits(self) -> u64 {
+        (self.generation as u64) << 32 | self.index as u64
+    }
+
+    pub fn from_bits(bits: u64) -> Self {
+        Self {
+            index: bits as u32,
+            generation: (bits >> 32) as u16,
+        }
+    }
+}
+
+/// Everything the VM allocates that can point back to other objects, closures capture upvalues
+/// and upvalues can hold closures, so they can form cycles.
+pub enum Object {
+    Closure(Closure),
+    Upvalue(Upvalue),
+}
+
+/// A function along with the variables it captured.
+pub struct Closure {
+    pub function: Rc<FunctionProto>,
+    pub upvalues: Vec<ObjRef>,
+}
+
+/// A captured variable, it stays on the stack until its scope ends.
+pub enum Upvalue {
+    Open(usize),
+    Closed(Packed),
+}
+
+/// When the heap collects.
+#[derive(Clone, Copy, Debug)]
+pub struct GcConfig {
+    /// Bytes allocated before the first collection.
+    pub initial_threshold: usize,
+    /// The next collection happens once the heap grows this many times over what survived.
+    pub growth_factor: usize,
+    /// Collects before every allocation, for shaking out objects the VM forgot to root.
+    pub stress: bool,
+}
+
+impl Default for GcConfig {
+    fn default() -> Self {
+        Self {
+            initial_threshold: 1024 * 1024,
+            growth_factor: 2,
+            stress: false,
+        }
+    }
+}
+
+struct Slot {
+    generation: u16,
+    marked: bool,
+    object: Option<Object>,
+}
+
+/// Objects of the VM, collected with mark and sweep. The VM gives the roots, everything not
+/// reachable from them is freed.
+pub struct Heap {
+    slots: Vec<Slot>,
+    free: Vec<u32>,
+    /// Slots out of generations, never used again.
+    retired: usize,
+    gray: Vec<ObjRef>,
+    /// Strings reached while marking.
+    strings: HashSet<Symbol>,
+    /// Part of the bytes allocated taken by strings, the ones reached by the last collection and
+    /// the ones made since.
+    string_bytes: usize,
+    bytes_allocated: usize,
+    next_gc: usize,
+    config: GcConfig,
+}
+
+impl Default for Heap {
+    fn default() -> Self {
+        Self::new(GcConfig::default())
+    }
+}
+
+impl Heap {
+    pub fn new(config: GcConfig) -> Self {
+        Self {
+            slots: Vec::new(),
+            free: Vec::new(),
+            retired: 0,
+            gray: Vec::new(),
+            strings: HashSet::new(),
+            string_bytes: 0,
+            bytes_allocated: 0,
+            next_gc: config.initial_threshold,
+            config,
+        }
+    }
+
+    /// Whether the next allocation should collect first.
+    pub fn should_collect(&self) -> bool {
+        self.config.stress || self.bytes_allocated > self.next_gc
+    }
+
+    pub fn alloc(&mut self, object: Object) -> ObjRef {
+        self.bytes_allocated += size_of(&object);
+
+        match self.free.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index as usize];
+                slot.object = Some(object);
+
+                ObjRef {
+                    index,
+                    generation: slot.generation,
+                }
+            }
+            None => {
+                self.slots.push(Slot {
+                    generation: 0,
+                    marked: false,
+                    object: Some(object),
+                });
+
+                ObjRef {
+                    index: (self.slots.len() - 1) as u32,
+                    generation: 0,
+                }
+            }
+        }
+    }
+
+    pub fn get(&self, object: ObjRef) -> &Object {
+        let slot = &self.slots[object.index()];
+
+        match &slot.object {
+            Some(found) if slot.generation == object.generation => found,
+            _ => panic!("use of a collected object {:?}", object),
+        }
+    }
+
+    pub fn get_mut(&mut self, object: ObjRef) -> &mut Object {
+        let slot = &mut self.slots[object.index()];
+
+        match &mut slot.object {
+            Some(found) if slot.generation == object.generation => found,
+            _ => panic!("use of a collected object {:?}", object),
+        }
+    }
+
+    pub fn closure(&self, object: ObjRef) -> &Closure {
+        match self.get(object) {
+            Object::Closure(closure) => closure,
+            _ => panic!("{:?} is not a closure", object),
+        }
+    }
+
+    pub fn upvalue(&self, object: ObjRef) -> &Upvalue {
+        match self.get(object) {
+            Object::Upvalue(upvalue) => upvalue,
+            _ => panic!("{:?} is not an upvalue", object),
+        }
+    }
+
+    pub fn upvalue_mut(&mut self, object: ObjRef) -> &mut Upvalue {
+        match self.get_mut(object) {
+            Object::Upvalue(upvalue) => upvalue,
+            _ => panic!("{:?} is not an upvalue", object),
+        }
+    }
+
+    /// Objects alive, collected or not yet.
+    pub fn len(&self) -> usize {
+        self.slots.len() - self.free.len() - self.retired
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn bytes_allocated(&self) -> usize {
+        self.bytes_allocated
+    }
+
+    /// Counts a string the VM made, strings live outside the heap but count towards it until a
+    /// collection finds them unreachable.
+    pub fn track_string(&mut self, string: &Symbol) {
+        self.string_bytes += string.len();
+        self.bytes_allocated += string.len();
+    }
+
+    /// Frees every object not reachable from the given roots.
+    pub fn collect<'a>(
+        &mut self,
+        values: impl IntoIterator<Item = &'a Packed>,
+        objects: impl IntoIterator<Item = ObjRef>,
+    ) {
+        for value in values {
+            self.mark_value(value);
+        }
+
+        for object in objects {
+            self.mark(object);
+        }
+
+        while let Some(object) = self.gray.pop() {
+            self.trace(object);
+        }
+
+        self.sweep();
+
+        self.bytes_allocated -= self.string_bytes;
+        self.string_bytes = self.strings.drain().map(|string| string.len()).sum();
+        self.bytes_allocated += self.string_bytes;
+
+        self.next_gc =
+            (self.bytes_allocated * self.config.growth_factor).max(self.config.initial_threshold);
+    }
+
+    fn mark_value(&mut self, value: &Packed) {
+        if let Some(object) = value.as_closure() {
+            self.mark(object);
+        }
+
+        if let Some(string) = value.as_string() {
+            self.strings.insert(string);
+        }
+    }
+
+    fn mark(&mut self, object: ObjRef) {
+        let slot = &mut self.slots[object.index()];
+
+        if !slot.marked {
+            slot.marked = true;
+            self.gray.push(object);
+        }
+    }
+
+    fn trace(&mut self, object: ObjRef) {
+        match self.get(object) {
+            Object::Closure(closure) => {
+                for upvalue in closure.upvalues.clone() {
+                    self.mark(upvalue);
+                }
+            }
+            Object::Upvalue(Upvalue::Closed(value)) => {
+                let value = value.clone();
+
+                self.mark_value(&value);
+            }
+            Object::Upvalue(Upvalue::Open(_)) => (),
+        }
+    }
+
+    fn sweep(&mut self) {
+        for (index, slot) in self.slots.iter_mut().enumerate() {
+            if slot.marked {
+                slot.marked = false;
+
+                continue;
+            }
+
+            if let Some(object) = slot.object.take() {
+                self.bytes_allocated -= size_of(&object);
+
+                match slot.generation.checked_add(1) {
+                    Some(generation) => {
+                        slot.generation = generation;
+                        self.free.push(index as u32);
+                    }
+                    None => self.retired += 1,
+                }
+            }
+        }
+    }
+
+    /// Shows the value the way `print` does, closures need the heap for their name.
+    pub fn display(&self, value: &Value) -> String {
+        match value {
+            Value::Closure(object) => {
+                let function = &self.closure(*object).function;
+
+                match function.name.as_str() {
+                    "" => "<script>".to_string(),
+                    name => format!("<fn {}>", name),
+                }
+            }
+            value => value.to_string(),
+        }
+    }
+
+    /// Shows the value the way runtime errors quote it.
+    pub fn debug(&self, value: &Value) -> String {
+        match value {
+            Value::Closure(object) => format!("Function({})", self.closure(*object).function.name),
+            value => format!("{:?}", value),
+        }
+    }
+}
+
+/// What an object costs, counting what it owns.
+fn size_of(object: &Object) -> usize {
+    let owned = match object {
+        Object::Closure(closure) => closure.upvalues.len() * std::mem::size_of::<ObjRef>(),
+        Object::Upvalue(_) => 0,
+    };
+
+    std::mem::size_of::<Slot>() + owned
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::chunk::Chunk;
+
+    fn function() -> Rc<FunctionProto> {
+        Rc::new(FunctionProto {
+            name: "f".to_string(),
+            arity: 0,
+            upvalue_count: 1,
+            chunk: Chunk::new(),
+        })
+    }
+
+    #[test]
+    fn unreachable_cycles_are_collected() {
+        let mut heap = Heap::default();
+
+        // A closure capturing itself, like a local recursive function.
+        let upvalue = heap.alloc(Object::Upvalue(Upvalue::Closed(Value::Nil.pack())));
+        let closure = heap.alloc(Object::Closure(Closure {
+            function: function(),
+            upvalues: vec![upvalue],
+        }));
+        *heap.upvalue_mut(upvalue) = Upvalue::Closed(Value::Closure(closure).pack());
+
+        let root = heap.alloc(Object::Upvalue(Upvalue::Closed(Value::Number(1.0).pack())));
+
+        heap.collect([&Value::Closure(closure).pack()], [root]);
+        assert_eq!(3, heap.len());
+
+        heap.collect([], [root]);
+        assert_eq!(1, heap.len());
+        assert!(matches!(
+            heap.upvalue(root),
+            Upvalue::Closed(value) if value.as_number() == Some(1.0)
+        ));
+
+        heap.collect([], []);
+        assert!(heap.is_empty());
+        assert_eq!(0, heap.bytes_allocated());
+    }
+
+    #[test]
+    #[should_panic(expected = "use of a collected object")]
+    fn stale_references_are_caught() {
+        let mut heap = Heap::default();
+
+        let stale = heap.alloc(Object::Upvalue(Upvalue::Open(0)));
+        heap.collect([], []);
+
+        let reused = heap.alloc(Object::Upvalue(Upvalue::Open(1)));
+        assert_eq!(stale.index(), reused.index());
+
+        heap.upvalue(stale);
+    }
+
+    #[test]
+    fn slots_out_of_generations_are_retired() {
+        let mut heap = Heap::default();
+
+        let old = heap.alloc(Object::Upvalue(Upvalue::Open(0)));
+        heap.slots[old.index()].generation = u16::MAX;
+        heap.collect([], []);
+
+        let new = heap.alloc(Object::Upvalue(Upvalue::Open(1)));
+
+        assert_ne!(old.index(), new.index());
+        assert_eq!(1, heap.len());
+    }
+}
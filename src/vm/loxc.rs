@@ -0,0 +1,765 @@
+//! The `.loxc` format, compiled scripts ready to run without parsing them again.
+//!
+//! Everything is little endian. After the header comes a table with every string the script uses,
+//! the names of the files its spans point into and the script itself, nested functions inline:
+//!
+//! ```text
+//! "LOXC" version:u16 checksum:u32 strings files function
+//! strings  = count:u32 (len:u32 utf8)*
+//! files    = count:u32 (name:u32)*
+//! function = name:u32 arity:u8 upvalues:u16 code:u32 byte* constants:u32 constant* spans:u32 span*
+//! constant = 0 f64 | 1 string:u32 | 2 function
+//! span     = file:u32 start:position end:position run:u32
+//! position = line:u32 column:u32 offset:u32
+//! ```
+//!
+//! The checksum is FNV-1a over everything after it.
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::error::Error;
+use crate::error::ErrorBuilder;
+use crate::intern::Symbol;
+use crate::text::FileId;
+use crate::text::Position;
+use crate::text::SourceMap;
+use crate::text::TextSection;
+use crate::vm::chunk::Chunk;
+use crate::vm::chunk::Constant;
+use crate::vm::chunk::FunctionProto;
+use crate::vm::chunk::OpCode;
+
+type Result<T> = std::result::Result<T, Error>;
+
+pub const MAGIC: &[u8; 4] = b"LOXC";
+
+/// Bumped whenever the format or the instruction set changes.
+pub const VERSION: u16 = 2;
+
+const HEADER_LEN: usize = 10;
+
+/// How deep functions can be declared inside each other, so reading doesn't overflow the stack.
+const MAX_NESTING: usize = 256;
+
+/// Serializes the script, `sources` gives the names of the files its spans point into.
+pub fn write(script: &FunctionProto, sources: &SourceMap) -> Vec<u8> {
+    let mut writer = Writer::default();
+    let mut function = Vec::new();
+    writer.function(&mut function, script);
+
+    let files: Vec<usize> = writer
+        .files
+        .clone()
+        .into_iter()
+        .map(|file| writer.string(sources.get(file).map_or("<unknown>", |file| file.name())))
+        .collect();
+
+    let mut body = Vec::new();
+
+    put_u32(&mut body, writer.strings.len());
+    for string in writer.strings.iter() {
+        put_u32(&mut body, string.len());
+        body.extend_from_slice(string.as_bytes());
+    }
+
+    put_u32(&mut body, files.len());
+    for name in files {
+        put_u32(&mut body, name);
+    }
+
+    body.extend_from_slice(&function);
+
+    let mut out = Vec::with_capacity(HEADER_LEN + body.len());
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&VERSION.to_le_bytes());
+    out.extend_from_slice(&checksum(&body).to_le_bytes());
+    out.extend_from_slice(&body);
+
+    out
+}
+
+/// Loads a script written by [`write`], adding the files it points into to `sources`. The bytecode
+/// is checked so a damaged or tampered file is an error instead of crashing the VM.
+pub fn read(bytes: &[u8], sources: &mut SourceMap) -> Result<FunctionProto> {
+    if bytes.len() < HEADER_LEN || &bytes[..4] != MAGIC {
+        return Err(error("Not a compiled Lox file"));
+    }
+
+    let version = u16::from_le_bytes([bytes[4], bytes[5]]);
+    if version != VERSION {
+        return Err(error(format!(
+            "Unsupported bytecode version {}, expected {}",
+            version, VERSION
+        )));
+    }
+
+    let expected = u32::from_le_bytes([bytes[6], bytes[7], bytes[8], bytes[9]]);
+    let body = &bytes[HEADER_LEN..];
+    if checksum(body) != expected {
+        return Err(error("Corrupted bytecode, checksum mismatch"));
+    }
+
+    let mut reader = Reader {
+        bytes: body,
+        position: 0,
+        strings: Vec::new(),
+        files: Vec::new(),
+    };
+
+    for _ in 0..reader.u32()? {
+        let len = reader.u32()? as usize;
+        let string = std::str::from_utf8(reader.take(len)?)
+            .map_err(|_| error("Corrupted bytecode, invalid string"))?;
+
+        reader.strings.push(string.into());
+    }
+
+    for _ in 0..reader.u32()? {
+        let name = reader.string()?;
+
+        reader.files.push(sources.add(name.as_ref(), ""));
+    }
+
+    let script = reader.function(0)?;
+
+    if reader.position != body.len() {
+        return Err(error(
+            "Corrupted bytecode, unexpected data after the script",
+        ));
+    }
+
+    Ok(script)
+}
+
+#[derive(Default)]
+struct Writer {
+    strings: Vec<Rc<str>>,
+    string_ids: HashMap<Rc<str>, usize>,
+    files: Vec<FileId>,
+}
+
+impl Writer {
+    fn string(&mut self, string: &str) -> usize {
+        if let Some(id) = self.string_ids.get(string) {
+            return *id;
+        }
+
+        let string: Rc<str> = string.into();
+        self.strings.push(string.clone());
+        self.string_ids.insert(string, self.strings.len() - 1);
+
+        self.strings.len() - 1
+    }
+
+    fn file(&mut self, file: FileId) -> usize {
+        match self.files.iter().position(|other| *other == file) {
+            Some(index) => index,
+            None => {
+                self.files.push(file);
+
+                self.files.len() - 1
+            }
+        }
+    }
+
+    fn function(&mut self, out: &mut Vec<u8>, function: &FunctionProto) {
+        let name = self.string(&function.name);
+        put_u32(out, name);
+        out.push(function.arity);
+        out.extend_from_slice(&(function.upvalue_count as u16).to_le_bytes());
+
+        let chunk = &function.chunk;
+        put_u32(out, chunk.code.len());
+        out.extend_from_slice(&chunk.code);
+
+        put_u32(out, chunk.constants.len());
+        for constant in chunk.constants.iter() {
+            match constant {
+                Constant::Number(value) => {
+                    out.push(0);
+                    out.extend_from_slice(&value.to_le_bytes());
+                }
+                Constant::String(value) => {
+                    out.push(1);
+                    let index = self.string(value);
+                    put_u32(out, index);
+                }
+                Constant::Function(function) => {
+                    out.push(2);
+                    self.function(out, function);
+                }
+            }
+        }
+
+        put_u32(out, chunk.spans().count());
+        for (span, run) in chunk.spans() {
+            let file = self.file(span.file);
+            put_u32(out, file);
+
+            for position in [span.start, span.end] {
+                put_u32(out, position.line);
+                put_u32(out, position.column);
+                put_u32(out, position.offset);
+            }
+
+            put_u32(out, *run);
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    strings: Vec<Symbol>,
+    files: Vec<FileId>,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self
+            .position
+            .checked_add(len)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or_else(|| error("Corrupted bytecode, unexpected end of file"))?;
+
+        let bytes = &self.bytes[self.position..end];
+        self.position = end;
+
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        let bytes = self.take(2)?;
+
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let bytes = self.take(4)?;
+
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn f64(&mut self) -> Result<f64> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+
+        Ok(f64::from_le_bytes(bytes))
+    }
+
+    fn string(&mut self) -> Result<Symbol> {
+        let index = self.u32()? as usize;
+
+        self.strings
+            .get(index)
+            .cloned()
+            .ok_or_else(|| error("Corrupted bytecode, string out of range"))
+    }
+
+    fn position(&mut self) -> Result<Position> {
+        Ok(Position {
+            line: self.u32()? as usize,
+            column: self.u32()? as usize,
+            offset: self.u32()? as usize,
+        })
+    }
+
+    /// Reads a function declared inside `nesting` others.
+    fn function(&mut self, nesting: usize) -> Result<FunctionProto> {
+        if nesting > MAX_NESTING {
+            return Err(error("Corrupted bytecode, functions nested too deep"));
+        }
+
+        let name = self.string()?.to_string();
+        let arity = self.u8()?;
+        let upvalue_count = self.u16()? as usize;
+
+        let len = self.u32()? as usize;
+        let code = self.take(len)?.to_vec();
+
+        let mut constants = Vec::new();
+        for _ in 0..self.u32()? {
+            let constant = match self.u8()? {
+                0 => Constant::Number(self.f64()?),
+                1 => Constant::String(self.string()?),
+                2 => Constant::Function(Rc::new(self.function(nesting + 1)?)),
+                tag => {
+                    return Err(error(format!(
+                        "Corrupted bytecode, unknown constant kind {}",
+                        tag
+                    )))
+                }
+            };
+
+            constants.push(constant);
+        }
+
+        let mut spans = Vec::new();
+        for _ in 0..self.u32()? {
+            let file = self.u32()? as usize;
+            let file = *self
+                .files
+                .get(file)
+                .ok_or_else(|| error("Corrupted bytecode, file out of range"))?;
+            let start = self.position()?;
+            let end = self.position()?;
+            let run = self.u32()? as usize;
+
+            spans.push((TextSection { file, start, end }, run));
+        }
+
+        let function = FunctionProto {
+            name,
+            arity,
+            upvalue_count,
+            chunk: Chunk::from_parts(code, constants, spans),
+        };
+
+        verify(&function)?;
+
+        Ok(function)
+    }
+}
+
+/// Checks the VM can run the function without reading past its code, constants or stack, nested
+/// functions are checked as they are read.
+fn verify(function: &FunctionProto) -> Result<()> {
+    let chunk = &function.chunk;
+    let code = &chunk.code;
+
+    let covered: usize = chunk.spans().map(|(_, run)| run).sum();
+    if covered != code.len() {
+        return Err(invalid(function, "the line table doesn't match the code"));
+    }
+
+    // The instruction starting at each offset, with where it goes next.
+    let mut decoded = vec![None; code.len() + 1];
+    let mut offset = 0;
+    let mut last = None;
+
+    while offset < code.len() {
+        let op = OpCode::try_from(code[offset])
+            .map_err(|byte| invalid(function, format!("unknown opcode {}", byte)))?;
+
+        let operand = |at: usize, len: usize| {
+            if offset + at + len <= code.len() {
+                Ok(())
+            } else {
+                Err(invalid(function, format!("{:?} is missing operands", op)))
+            }
+        };
+
+        let constant = |index: u16| {
+            chunk
+                .constants
+                .get(index as usize)
+                .ok_or_else(|| invalid(function, format!("{:?} constant out of range", op)))
+        };
+
+        let mut jump = None;
+
+        let next = match op {
+            OpCode::Constant => {
+                operand(1, 2)?;
+
+                match constant(chunk.read_u16(offset + 1))? {
+                    Constant::Function(_) => {
+                        return Err(invalid(function, "functions can only be closures"))
+                    }
+                    _ => offset + 3,
+                }
+            }
+            OpCode::GetGlobal | OpCode::DefineGlobal | OpCode::SetGlobal => {
+                operand(1, 2)?;
+
+                match constant(chunk.read_u16(offset + 1))? {
+                    Constant::String(_) => offset + 3,
+                    _ => return Err(invalid(function, "globals must be named by strings")),
+                }
+            }
+            OpCode::GetProperty | OpCode::SetProperty => {
+                operand(1, 2)?;
+
+                match constant(chunk.read_u16(offset + 1))? {
+                    Constant::String(_) => offset + 3,
+                    _ => return Err(invalid(function, "properties must be named by strings")),
+                }
+            }
+            OpCode::GetUpvalue | OpCode::SetUpvalue => {
+                operand(1, 1)?;
+
+                if code[offset + 1] as usize >= function.upvalue_count {
+                    return Err(invalid(function, "upvalue out of range"));
+                }
+
+                offset + 2
+            }
+            OpCode::GetLocal | OpCode::SetLocal | OpCode::Call => {
+                operand(1, 1)?;
+
+                offset + 2
+            }
+            OpCode::Jump | OpCode::JumpIfFalse => {
+                operand(1, 2)?;
+
+                jump = Some(offset + 3 + chunk.read_u16(offset + 1) as usize);
+
+                offset + 3
+            }
+            OpCode::Loop => {
+                operand(1, 2)?;
+
+                let back = chunk.read_u16(offset + 1) as usize;
+                let target = (offset + 3)
+                    .checked_sub(back)
+                    .ok_or_else(|| invalid(function, "loop before the start of the code"))?;
+                jump = Some(target);
+
+                offset + 3
+            }
+            OpCode::Closure => {
+                operand(1, 2)?;
+
+                let Constant::Function(closed) = constant(chunk.read_u16(offset + 1))? else {
+                    return Err(invalid(function, "closures must be made out of functions"));
+                };
+
+                operand(3, closed.upvalue_count * 2)?;
+
+                for upvalue in 0..closed.upvalue_count {
+                    let at = offset + 3 + upvalue * 2;
+
+                    match code[at] {
+                        1 => (),
+                        0 if (code[at + 1] as usize) < function.upvalue_count => (),
+                        _ => return Err(invalid(function, "closure captures an unknown upvalue")),
+                    }
+                }
+
+                offset + 3 + closed.upvalue_count * 2
+            }
+            _ => offset + 1,
+        };
+
+        decoded[offset] = Some((op, next, jump));
+        last = Some(op);
+        offset = next;
+    }
+
+    if last != Some(OpCode::Return) {
+        return Err(invalid(function, "code must end with a return"));
+    }
+
+    if decoded
+        .iter()
+        .flatten()
+        .filter_map(|(_, _, jump)| *jump)
+        .any(|target| decoded.get(target).is_none_or(Option::is_none))
+    {
+        return Err(invalid(function, "jump into the middle of an instruction"));
+    }
+
+    verify_stack(function, &decoded)
+}
+
+/// Follows every path through the code, checking no instruction pops more than the stack has or
+/// reaches a local past its top, and that paths meet with the same stack depth so loops can't
+/// grow it. The frame starts with the callee and its arguments.
+fn verify_stack(
+    function: &FunctionProto,
+    decoded: &[Option<(OpCode, usize, Option<usize>)>],
+) -> Result<()> {
+    let code = &function.chunk.code;
+
+    let mut depths = vec![None; code.len()];
+    let mut pending = vec![(0, function.arity as usize + 1)];
+
+    while let Some((offset, depth)) = pending.pop() {
+        match depths[offset] {
+            Some(known) if known == depth => continue,
+            Some(_) => return Err(invalid(function, "stack depth differs between paths")),
+            None => depths[offset] = Some(depth),
+        }
+
+        let (op, next, jump) = decoded[offset].expect("paths only reach instruction starts");
+
+        let (pops, pushes) = match op {
+            OpCode::Constant
+            | OpCode::Nil
+            | OpCode::True
+            | OpCode::False
+            | OpCode::GetLocal
+            | OpCode::GetGlobal
+            | OpCode::GetUpvalue
+            | OpCode::Closure => (0, 1),
+            OpCode::Pop
+            | OpCode::DefineGlobal
+            | OpCode::Print
+            | OpCode::CloseUpvalue
+            | OpCode::Return => (1, 0),
+            OpCode::SetLocal
+            | OpCode::SetGlobal
+            | OpCode::SetUpvalue
+            | OpCode::Not
+            | OpCode::Negate
+            | OpCode::JumpIfFalse
+            | OpCode::GetProperty => (1, 1),
+            OpCode::Equal
+            | OpCode::NotEqual
+            | OpCode::Greater
+            | OpCode::GreaterEqual
+            | OpCode::Less
+            | OpCode::LessEqual
+            | OpCode::Add
+            | OpCode::Subtract
+            | OpCode::Multiply
+            | OpCode::Divide
+            | OpCode::SetProperty => (2, 1),
+            OpCode::Call => (code[offset + 1] as usize + 1, 1),
+            OpCode::Jump | OpCode::Loop => (0, 0),
+        };
+
+        if depth < pops {
+            return Err(invalid(function, format!("{:?} pops an empty stack", op)));
+        }
+
+        // A closure can capture the slot it's about to be pushed to, to call itself.
+        let (locals, slots) = match op {
+            OpCode::GetLocal | OpCode::SetLocal => (vec![code[offset + 1]], depth),
+            OpCode::Closure => (
+                code[offset + 3..next]
+                    .chunks(2)
+                    .filter(|upvalue| upvalue[0] == 1)
+                    .map(|upvalue| upvalue[1])
+                    .collect(),
+                depth + 1,
+            ),
+            _ => (Vec::new(), depth),
+        };
+
+        if locals.into_iter().any(|slot| slot as usize >= slots) {
+            return Err(invalid(function, format!("{:?} local out of range", op)));
+        }
+
+        let depth = depth - pops + pushes;
+
+        match op {
+            OpCode::Return => (),
+            OpCode::Jump | OpCode::Loop => pending.extend(jump.map(|target| (target, depth))),
+            _ => {
+                pending.push((next, depth));
+                pending.extend(jump.map(|target| (target, depth)));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// FNV-1a, catches damaged files, not a signature.
+fn checksum(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c9dc5, |hash, byte| {
+        (hash ^ *byte as u32).wrapping_mul(0x01000193)
+    })
+}
+
+fn put_u32(out: &mut Vec<u8>, value: usize) {
+    out.extend_from_slice(&(value as u32).to_le_bytes());
+}
+
+fn invalid(function: &FunctionProto, reason: impl std::fmt::Display) -> Error {
+    let name = match function.name.as_str() {
+        "" => "<script>",
+        name => name,
+    };
+
+    error(format!("Invalid bytecode in {}: {}", name, reason))
+}
+
+fn error(msg: impl Into<std::borrow::Cow<'static, str>>) -> Error {
+    ErrorBuilder::new().message(msg).build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Scanner;
+    use crate::parser::Parser;
+    use crate::vm::compiler::Compiler;
+    use crate::vm::disassembler::disassemble;
+
+    fn compile(source: &str) -> (FunctionProto, SourceMap) {
+        let mut sources = SourceMap::new();
+        let file = sources.add("test.lox", source);
+
+        let tokens = Scanner::new(sources.file(file)).scan_tokens().tokens;
+        let statements = Parser::new(tokens).parse_program().expect("valid program");
+
+        (
+            Compiler::compile(&statements).expect("compiled program"),
+            sources,
+        )
+    }
+
+    /// A script made of the given code, with every byte on line 1.
+    fn script(code: Vec<u8>, constants: Vec<Constant>) -> FunctionProto {
+        let spans = vec![(TextSection::default(), code.len())];
+
+        FunctionProto {
+            name: String::new(),
+            arity: 0,
+            upvalue_count: 0,
+            chunk: Chunk::from_parts(code, constants, spans),
+        }
+    }
+
+    #[test]
+    fn scripts_round_trip() {
+        let (script, sources) = compile(
+            "var greeting = \"hi\";\nfun f(n) {\n  fun g() { return n * 1.5; }\n  return g;\n}\nprint f(2)();",
+        );
+
+        let bytes = write(&script, &sources);
+        let mut loaded_sources = SourceMap::new();
+        let loaded = read(&bytes, &mut loaded_sources).expect("valid bytecode");
+
+        assert_eq!(disassemble(&script), disassemble(&loaded));
+        assert_eq!(
+            "test.lox:6:12",
+            loaded_sources
+                .location(&loaded.chunk.span(loaded.chunk.code.len() - 5))
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn damaged_files_are_rejected() {
+        let (script, sources) = compile("print 1;");
+        let bytes = write(&script, &sources);
+
+        let mut wrong_version = bytes.clone();
+        wrong_version[4] = 9;
+
+        let mut flipped = bytes.clone();
+        let last = flipped.len() - 1;
+        flipped[last] ^= 1;
+
+        let cases = [
+            (&b"print 1;"[..], "Not a compiled Lox file"),
+            (&wrong_version, "Unsupported bytecode version 9, expected 2"),
+            (&flipped, "Corrupted bytecode, checksum mismatch"),
+        ];
+
+        for (bytes, message) in cases {
+            let error = read(bytes, &mut SourceMap::new()).expect_err(message);
+
+            assert_eq!(message, error.message);
+        }
+    }
+
+    #[test]
+    fn invalid_bytecode_is_rejected() {
+        let nil = OpCode::Nil as u8;
+        let pop = OpCode::Pop as u8;
+        let ret = OpCode::Return as u8;
+
+        let cases = [
+            (script(vec![200, ret], vec![]), "unknown opcode 200"),
+            (script(vec![nil], vec![]), "code must end with a return"),
+            (
+                script(vec![OpCode::Constant as u8, 0, 1, ret], vec![]),
+                "Constant constant out of range",
+            ),
+            (
+                script(
+                    vec![OpCode::GetGlobal as u8, 0, 0, ret],
+                    vec![Constant::Number(1.0)],
+                ),
+                "globals must be named by strings",
+            ),
+            (
+                script(
+                    vec![OpCode::Jump as u8, 0, 1, OpCode::Constant as u8, 0, 0, ret],
+                    vec![Constant::Number(1.0)],
+                ),
+                "jump into the middle of an instruction",
+            ),
+            (
+                script(vec![OpCode::Loop as u8, 0, 9, ret], vec![]),
+                "loop before the start of the code",
+            ),
+            (
+                script(vec![OpCode::GetUpvalue as u8, 0, ret], vec![]),
+                "upvalue out of range",
+            ),
+            (
+                script(
+                    vec![OpCode::Constant as u8, 0, 0, pop, pop, pop, ret],
+                    vec![Constant::Number(1.0)],
+                ),
+                "Pop pops an empty stack",
+            ),
+            (
+                script(vec![OpCode::Call as u8, 1, ret], vec![]),
+                "Call pops an empty stack",
+            ),
+            (
+                script(vec![OpCode::GetLocal as u8, 1, ret], vec![]),
+                "GetLocal local out of range",
+            ),
+            (
+                script(vec![nil, OpCode::SetLocal as u8, 2, ret], vec![]),
+                "SetLocal local out of range",
+            ),
+            (
+                script(vec![nil, OpCode::Loop as u8, 0, 4, ret], vec![]),
+                "stack depth differs between paths",
+            ),
+        ];
+
+        for (script, reason) in cases {
+            let bytes = write(&script, &SourceMap::new());
+            let error = read(&bytes, &mut SourceMap::new()).expect_err(reason);
+
+            assert_eq!(
+                format!("Invalid bytecode in <script>: {}", reason),
+                error.message
+            );
+        }
+    }
+
+    #[test]
+    fn compiled_control_flow_passes_verification() {
+        let (script, sources) = compile(
+            "fun count(n) {\n  fun down(i) { if (i > 0 and n) return down(i - 1); return i; }\n  return down(n);\n}\nfor (var i = 0; i < 3 or false; i = i + 1) { var a = count(i); { var b = a; print b; } }",
+        );
+
+        let bytes = write(&script, &sources);
+
+        assert!(read(&bytes, &mut SourceMap::new()).is_ok());
+    }
+
+    #[test]
+    fn deeply_nested_functions_are_rejected() {
+        let mut function = script(vec![OpCode::Nil as u8, OpCode::Return as u8], vec![]);
+
+        for _ in 0..=MAX_NESTING {
+            let closure = vec![OpCode::Closure as u8, 0, 0, OpCode::Return as u8];
+
+            function = script(closure, vec![Constant::Function(Rc::new(function))]);
+        }
+
+        let bytes = write(&function, &SourceMap::new());
+        let error = read(&bytes, &mut SourceMap::new()).unwrap_err();
+
+        assert_eq!(
+            "Corrupted bytecode, functions nested too deep",
+            error.message
+        );
+    }
+}
@@ -0,0 +1,977 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::rc::Rc;
+
+use crate::error::Error;
+use crate::error::ErrorBuilder;
+use crate::intern::Symbol;
+use crate::interpreter::InterpreterLimits;
+use crate::interpreter::InterruptHandle;
+use crate::parser::ast::Expression;
+use crate::parser::ast::Statement;
+use crate::text::TextSection;
+use crate::vm::chunk::Constant;
+use crate::vm::chunk::FunctionProto;
+use crate::vm::chunk::OpCode;
+use crate::vm::compiler::Compiler;
+use crate::vm::disassembler::disassemble_instruction;
+use crate::vm::heap::Closure;
+use crate::vm::heap::GcConfig;
+use crate::vm::heap::Heap;
+use crate::vm::heap::ObjRef;
+use crate::vm::heap::Object;
+use crate::vm::heap::Upvalue;
+use crate::vm::value::clock;
+use crate::vm::value::NativeFunction;
+use crate::vm::value::Packed;
+use crate::vm::value::Value;
+
+type Result<T> = std::result::Result<T, Error>;
+
+struct CallFrame {
+    closure: ObjRef,
+    /// The closure's function, kept at hand to read the code without going through the heap.
+    function: Rc<FunctionProto>,
+    ip: usize,
+    /// Where the frame's locals start in the stack, the callee itself is the first one.
+    slots: usize,
+}
+
+/// Runs the bytecode the compiler produces, globals stay around between runs.
+pub struct Vm {
+    stack: Vec<Packed>,
+    frames: Vec<CallFrame>,
+    globals: HashMap<Symbol, Packed>,
+    /// Captured variables still living in the stack.
+    open_upvalues: Vec<ObjRef>,
+    heap: Heap,
+    /// Prints the stack and each instruction before running it.
+    trace: bool,
+    limits: InterpreterLimits,
+    /// Instructions run in the current run.
+    steps: u64,
+    interrupt: InterruptHandle,
+    /// Where `print` writes to.
+    output: Box<dyn Write>,
+}
+
+impl Default for Vm {
+    fn default() -> Self {
+        Self::with_gc(GcConfig::default())
+    }
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A VM whose heap collects as configured.
+    pub fn with_gc(config: GcConfig) -> Self {
+        let mut globals = HashMap::new();
+
+        globals.insert(
+            "clock".into(),
+            Value::Native(Rc::new(NativeFunction {
+                name: "clock",
+                arity: 0,
+                function: clock,
+            }))
+            .pack(),
+        );
+
+        Self {
+            stack: Vec::new(),
+            frames: Vec::new(),
+            globals,
+            open_upvalues: Vec::new(),
+            heap: Heap::new(config),
+            trace: false,
+            limits: InterpreterLimits::default(),
+            steps: 0,
+            interrupt: InterruptHandle::default(),
+            output: Box::new(std::io::stdout()),
+        }
+    }
+
+    /// Dumps the stack and the instruction about to run to stderr, as the code runs.
+    pub fn trace_execution(&mut self, enabled: bool) {
+        self.trace = enabled;
+    }
+
+    /// Bounds what the code run from now on can use.
+    pub fn set_limits(&mut self, limits: InterpreterLimits) {
+        self.limits = limits;
+    }
+
+    /// Lets another thread stop the code running.
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.interrupt.clone()
+    }
+
+    /// Sends what `print` writes somewhere else than stdout.
+    pub fn set_output(&mut self, output: impl Write + 'static) {
+        self.output = Box::new(output);
+    }
+
+    pub fn eval(&mut self, expression: &Expression) -> Result<Value> {
+        let script = Compiler::compile_expression(expression)?;
+
+        self.interpret(script)
+    }
+
+    /// Shows the value the way `print` does.
+    pub fn display(&self, value: &Value) -> String {
+        self.heap.display(value)
+    }
+
+    /// Runs the statements in the global scope, definitions stay around for the next call.
+    pub fn execute(&mut self, statements: &[Statement]) -> Result<()> {
+        let script = Compiler::compile(statements)?;
+
+        self.interpret(script).map(|_| ())
+    }
+
+    /// Runs an already compiled script, like one loaded from a `.loxc` file.
+    pub fn run_compiled(&mut self, script: FunctionProto) -> Result<()> {
+        self.interpret(script).map(|_| ())
+    }
+
+    /// Runs the script, returning what it returns.
+    fn interpret(&mut self, script: FunctionProto) -> Result<Value> {
+        self.steps = 0;
+
+        let function = Rc::new(script);
+        let closure = self.alloc(Object::Closure(Closure {
+            function: function.clone(),
+            upvalues: Vec::new(),
+        }));
+
+        self.frames.push(CallFrame {
+            closure,
+            function,
+            ip: 0,
+            slots: self.stack.len(),
+        });
+        self.push(Value::Closure(closure));
+
+        let result = self.run();
+
+        if result.is_err() {
+            self.stack.clear();
+            self.frames.clear();
+            self.open_upvalues.clear();
+        }
+
+        result
+    }
+
+    fn run(&mut self) -> Result<Value> {
+        loop {
+            let start = self.frame().ip;
+
+            if self.trace {
+                self.trace_instruction(start);
+            }
+
+            self.step(start)?;
+
+            let byte = self.read_byte();
+            let op = OpCode::try_from(byte).unwrap_or_else(|byte| {
+                unreachable!("the compiler only emits valid opcodes, found {}", byte)
+            });
+
+            match op {
+                OpCode::Constant => {
+                    let value = match self.read_constant() {
+                        Constant::Number(value) => Value::Number(value),
+                        Constant::String(value) => Value::String(value),
+                        Constant::Function(_) => unreachable!("functions are loaded as closures"),
+                    };
+
+                    self.push(value);
+                }
+                OpCode::Nil => self.push(Value::Nil),
+                OpCode::True => self.push(Value::Bool(true)),
+                OpCode::False => self.push(Value::Bool(false)),
+                OpCode::Pop => {
+                    self.pop_packed();
+                }
+                OpCode::GetLocal => {
+                    let slot = self.frame().slots + self.read_byte() as usize;
+
+                    self.push(self.stack[slot].clone());
+                }
+                OpCode::SetLocal => {
+                    let slot = self.frame().slots + self.read_byte() as usize;
+
+                    self.stack[slot] = self.peek(0).clone();
+                }
+                OpCode::GetGlobal => {
+                    let name = self.read_name();
+
+                    match self.globals.get(&name) {
+                        Some(value) => self.push(value.clone()),
+                        None => return Err(self.undefined(&name, start)),
+                    }
+                }
+                OpCode::DefineGlobal => {
+                    let name = self.read_name();
+                    let value = self.pop_packed();
+
+                    self.globals.insert(name, value);
+                }
+                OpCode::SetGlobal => {
+                    let name = self.read_name();
+                    let value = self.peek(0).clone();
+
+                    match self.globals.get_mut(&name) {
+                        Some(global) => *global = value,
+                        None => return Err(self.undefined(&name, start)),
+                    }
+                }
+                OpCode::GetUpvalue => {
+                    let index = self.read_byte() as usize;
+                    let upvalue = self.heap.closure(self.frame().closure).upvalues[index];
+
+                    let value = match self.heap.upvalue(upvalue) {
+                        Upvalue::Open(slot) => self.stack[*slot].clone(),
+                        Upvalue::Closed(value) => value.clone(),
+                    };
+
+                    self.push(value);
+                }
+                OpCode::SetUpvalue => {
+                    let index = self.read_byte() as usize;
+                    let upvalue = self.heap.closure(self.frame().closure).upvalues[index];
+                    let value = self.peek(0).clone();
+
+                    match self.heap.upvalue_mut(upvalue) {
+                        Upvalue::Open(slot) => self.stack[*slot] = value,
+                        Upvalue::Closed(closed) => *closed = value,
+                    }
+                }
+                OpCode::Equal => {
+                    let right = self.pop_packed();
+                    let left = self.pop_packed();
+
+                    self.push(Value::Bool(left == right));
+                }
+                OpCode::NotEqual => {
+                    let right = self.pop_packed();
+                    let left = self.pop_packed();
+
+                    self.push(Value::Bool(left != right));
+                }
+                OpCode::Greater => self.compare(op, start, |left, right| left > right)?,
+                OpCode::GreaterEqual => self.compare(op, start, |left, right| left >= right)?,
+                OpCode::Less => self.compare(op, start, |left, right| left < right)?,
+                OpCode::LessEqual => self.compare(op, start, |left, right| left <= right)?,
+                OpCode::Add => {
+                    if let (Some(left), Some(right)) =
+                        (self.peek(1).as_number(), self.peek(0).as_number())
+                    {
+                        self.stack.truncate(self.stack.len() - 2);
+                        self.push(Value::Number(left + right));
+
+                        continue;
+                    }
+
+                    let right = self.pop();
+                    let left = self.pop();
+
+                    let value = match (left, right) {
+                        (Value::String(left), Value::String(right)) => {
+                            if !self.string_fits(left.len() + right.len()) {
+                                return Err(self.limits.heap_exceeded(self.span(start)));
+                            }
+
+                            let string = Symbol::from(format!("{}{}", left, right));
+                            self.heap.track_string(&string);
+
+                            Value::String(string)
+                        }
+                        (left, right) => return Err(self.error(
+                            format!(
+                                "Binary operator '+' expects two numbers or two strings, instead got: left='{}' right='{}'",
+                                self.heap.debug(&left), self.heap.debug(&right),
+                            ),
+                            start,
+                        )),
+                    };
+
+                    self.push(value);
+                }
+                OpCode::Subtract => self.arithmetic(op, start, |left, right| left - right)?,
+                OpCode::Multiply => self.arithmetic(op, start, |left, right| left * right)?,
+                OpCode::Divide => self.arithmetic(op, start, |left, right| left / right)?,
+                OpCode::Not => {
+                    let value = self.pop_packed();
+
+                    self.push(Value::Bool(!value.is_truthy()));
+                }
+                OpCode::Negate => match self.pop() {
+                    Value::Number(value) => self.push(Value::Number(-value)),
+                    value => {
+                        return Err(self.error(
+                            format!(
+                                "Unary operator '-' expects a number, instead got: '{}'",
+                                self.heap.debug(&value)
+                            ),
+                            start,
+                        ))
+                    }
+                },
+                OpCode::Print => {
+                    let value = self.pop();
+
+                    let printed = self.heap.display(&value);
+
+                    writeln!(self.output, "{}", printed)
+                        .map_err(|e| self.error(format!("Failed printing: {}", e), start))?;
+                }
+                OpCode::Jump => {
+                    let offset = self.read_u16() as usize;
+
+                    self.frame_mut().ip += offset;
+                }
+                OpCode::JumpIfFalse => {
+                    let offset = self.read_u16() as usize;
+
+                    if !self.peek(0).is_truthy() {
+                        self.frame_mut().ip += offset;
+                    }
+                }
+                OpCode::Loop => {
+                    let offset = self.read_u16() as usize;
+
+                    self.frame_mut().ip -= offset;
+                }
+                OpCode::Call => {
+                    let arguments = self.read_byte() as usize;
+
+                    self.call(arguments, start)?;
+                }
+                OpCode::Closure => {
+                    let function = match self.read_constant() {
+                        Constant::Function(function) => function,
+                        _ => unreachable!("closures are made out of functions"),
+                    };
+
+                    // Captured upvalues are open, so rooted, while the closure is allocated.
+                    let upvalues = (0..function.upvalue_count)
+                        .map(|_| {
+                            let is_local = self.read_byte() == 1;
+                            let index = self.read_byte() as usize;
+
+                            if is_local {
+                                self.capture_upvalue(self.frame().slots + index)
+                            } else {
+                                self.heap.closure(self.frame().closure).upvalues[index]
+                            }
+                        })
+                        .collect();
+
+                    let closure = self.alloc(Object::Closure(Closure { function, upvalues }));
+                    self.push(Value::Closure(closure));
+                }
+                OpCode::CloseUpvalue => {
+                    self.close_upvalues(self.stack.len() - 1);
+                    self.pop_packed();
+                }
+                // Nothing the VM makes is an instance, only the tree walker has host objects.
+                OpCode::GetProperty => {
+                    return Err(self.error("Only instances have properties", start));
+                }
+                OpCode::SetProperty => {
+                    return Err(self.error("Only instances have fields", start));
+                }
+                OpCode::Return => {
+                    let result = self.pop_packed();
+                    let frame = self.frames.pop().expect("returning from a frame");
+
+                    self.close_upvalues(frame.slots);
+                    self.stack.truncate(frame.slots);
+
+                    if self.frames.is_empty() {
+                        return Ok(result.into_value());
+                    }
+
+                    self.push(result);
+                }
+            }
+        }
+    }
+
+    /// Counts the instruction about to run against the limits. Going over the heap limit only
+    /// fails if a collection doesn't get it back under.
+    fn step(&mut self, start: usize) -> Result<()> {
+        if self.interrupt.take() {
+            return Err(self.interrupt.interrupted(self.span(start)));
+        }
+
+        self.steps += 1;
+
+        if self.limits.max_steps.is_some_and(|max| self.steps > max) {
+            return Err(self.limits.steps_exceeded(self.span(start)));
+        }
+
+        if !self.limits.fits(self.heap.bytes_allocated(), 0) {
+            self.collect_garbage();
+
+            if !self.limits.fits(self.heap.bytes_allocated(), 0) {
+                return Err(self.limits.heap_exceeded(self.span(start)));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Whether a new string of `length` bytes fits in the heap limit, collecting first if needed.
+    fn string_fits(&mut self, length: usize) -> bool {
+        if self.limits.fits(self.heap.bytes_allocated(), length) {
+            return true;
+        }
+
+        self.collect_garbage();
+
+        self.limits.fits(self.heap.bytes_allocated(), length)
+    }
+
+    fn trace_instruction(&self, offset: usize) {
+        let stack: String = self
+            .stack
+            .iter()
+            .map(|value| format!("[ {} ]", self.heap.display(&value.unpack())))
+            .collect();
+        let (instruction, _) = disassemble_instruction(&self.frame().function.chunk, offset);
+
+        eprintln!("          {}", stack);
+        eprintln!("{}", instruction);
+    }
+
+    fn call(&mut self, arguments: usize, start: usize) -> Result<()> {
+        let callee = self.peek(arguments).unpack();
+
+        let arity = match &callee {
+            Value::Closure(closure) => self.heap.closure(*closure).function.arity as usize,
+            Value::Native(native) => native.arity,
+            _ => return Err(self.error("Can only call functions and classes", start)),
+        };
+
+        if arity != arguments {
+            return Err(self.error(
+                format!("Expected {} arguments but got {}", arity, arguments),
+                start,
+            ));
+        }
+
+        match callee {
+            Value::Closure(closure) => {
+                if self.frames.len() >= self.limits.max_call_depth {
+                    return Err(self.limits.call_depth_exceeded(self.span(start)));
+                }
+
+                self.frames.push(CallFrame {
+                    function: self.heap.closure(closure).function.clone(),
+                    closure,
+                    ip: 0,
+                    slots: self.stack.len() - arguments - 1,
+                });
+            }
+            Value::Native(native) => {
+                let arguments: Vec<Value> = self
+                    .stack
+                    .split_off(self.stack.len() - arguments)
+                    .iter()
+                    .map(Packed::unpack)
+                    .collect();
+                let result = (native.function)(&arguments);
+
+                self.pop_packed();
+                self.push(result);
+            }
+            _ => unreachable!("only callables have an arity"),
+        }
+
+        Ok(())
+    }
+
+    /// Shares the upvalue between every closure capturing the same slot.
+    fn capture_upvalue(&mut self, slot: usize) -> ObjRef {
+        let existing = self.open_upvalues.iter().find(
+            |upvalue| matches!(self.heap.upvalue(**upvalue), Upvalue::Open(open) if *open == slot),
+        );
+
+        if let Some(upvalue) = existing {
+            return *upvalue;
+        }
+
+        let upvalue = self.alloc(Object::Upvalue(Upvalue::Open(slot)));
+        self.open_upvalues.push(upvalue);
+
+        upvalue
+    }
+
+    /// Moves the values of the slots from `from` up to the heap, they are about to be popped.
+    fn close_upvalues(&mut self, from: usize) {
+        let heap = &mut self.heap;
+        let stack = &self.stack;
+
+        self.open_upvalues.retain(|upvalue| {
+            let upvalue = heap.upvalue_mut(*upvalue);
+
+            match *upvalue {
+                Upvalue::Open(slot) if slot >= from => {
+                    *upvalue = Upvalue::Closed(stack[slot].clone());
+
+                    false
+                }
+                _ => true,
+            }
+        });
+    }
+
+    /// Allocates the object, collecting first when the heap asks for it.
+    fn alloc(&mut self, object: Object) -> ObjRef {
+        if self.heap.should_collect() {
+            self.collect_garbage();
+        }
+
+        self.heap.alloc(object)
+    }
+
+    /// Everything the running code can still reach is kept, the rest is freed.
+    fn collect_garbage(&mut self) {
+        let frames = self.frames.iter().map(|frame| frame.closure);
+        let objects = frames.chain(self.open_upvalues.iter().copied());
+
+        self.heap
+            .collect(self.stack.iter().chain(self.globals.values()), objects);
+    }
+
+    fn arithmetic(&mut self, op: OpCode, start: usize, apply: fn(f64, f64) -> f64) -> Result<()> {
+        let (left, right) = self.numbers(op, start)?;
+
+        self.push(Value::Number(apply(left, right)));
+
+        Ok(())
+    }
+
+    fn compare(&mut self, op: OpCode, start: usize, apply: fn(f64, f64) -> bool) -> Result<()> {
+        let (left, right) = self.numbers(op, start)?;
+
+        self.push(Value::Bool(apply(left, right)));
+
+        Ok(())
+    }
+
+    /// Operands of an arithmetic or comparison operator, which only work on numbers.
+    fn numbers(&mut self, op: OpCode, start: usize) -> Result<(f64, f64)> {
+        let right = self.pop_packed();
+        let left = self.pop_packed();
+
+        match (left.as_number(), right.as_number()) {
+            (Some(left), Some(right)) => Ok((left, right)),
+            _ => Err(self.error(
+                format!(
+                    "Binary operator '{}' expects two numbers, instead got: left='{}' right='{}'",
+                    symbol(op),
+                    self.heap.debug(&left.unpack()),
+                    self.heap.debug(&right.unpack()),
+                ),
+                start,
+            )),
+        }
+    }
+
+    fn frame(&self) -> &CallFrame {
+        self.frames.last().expect("there's always a frame running")
+    }
+
+    fn frame_mut(&mut self) -> &mut CallFrame {
+        self.frames
+            .last_mut()
+            .expect("there's always a frame running")
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let frame = self.frame_mut();
+        let byte = frame.function.chunk.code[frame.ip];
+        frame.ip += 1;
+
+        byte
+    }
+
+    fn read_u16(&mut self) -> u16 {
+        let frame = self.frame_mut();
+        let value = frame.function.chunk.read_u16(frame.ip);
+        frame.ip += 2;
+
+        value
+    }
+
+    fn read_constant(&mut self) -> Constant {
+        let index = self.read_u16() as usize;
+
+        self.frame().function.chunk.constants[index].clone()
+    }
+
+    fn read_name(&mut self) -> Symbol {
+        match self.read_constant() {
+            Constant::String(name) => name,
+            _ => unreachable!("variables are named by string constants"),
+        }
+    }
+
+    fn push(&mut self, value: impl Into<Packed>) {
+        self.stack.push(value.into());
+    }
+
+    fn pop(&mut self) -> Value {
+        self.pop_packed().into_value()
+    }
+
+    /// Pops the value as stored, for moving it somewhere else without unpacking.
+    fn pop_packed(&mut self) -> Packed {
+        self.stack
+            .pop()
+            .expect("the compiler keeps the stack balanced")
+    }
+
+    fn peek(&self, distance: usize) -> &Packed {
+        &self.stack[self.stack.len() - 1 - distance]
+    }
+
+    fn undefined(&self, name: &str, start: usize) -> Error {
+        self.error(format!("Undefined variable '{}'", name), start)
+    }
+
+    /// Points the error at the source of the instruction starting at `start`.
+    fn error(&self, msg: impl Into<std::borrow::Cow<'static, str>>, start: usize) -> Error {
+        ErrorBuilder::new()
+            .message(msg)
+            .section(self.span(start))
+            .build()
+    }
+
+    /// Source of the instruction starting at `start`.
+    fn span(&self, start: usize) -> TextSection {
+        self.frame().function.chunk.span(start)
+    }
+}
+
+fn symbol(op: OpCode) -> &'static str {
+    match op {
+        OpCode::Greater => ">",
+        OpCode::GreaterEqual => ">=",
+        OpCode::Less => "<",
+        OpCode::LessEqual => "<=",
+        OpCode::Subtract => "-",
+        OpCode::Multiply => "*",
+        OpCode::Divide => "/",
+        _ => unreachable!("only numeric operators check their operands"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorKind;
+    use crate::lexer::Scanner;
+    use crate::parser::Parser;
+    use crate::text::SourceMap;
+
+    /// A VM collecting on every allocation, so a value it forgets to root shows up right away.
+    fn stressed() -> Vm {
+        Vm::with_gc(GcConfig {
+            stress: true,
+            ..GcConfig::default()
+        })
+    }
+
+    /// Runs the program and evaluates the expression afterwards, in the same global scope.
+    fn run(program: &str, expression: &str) -> Result<Value> {
+        let mut sources = SourceMap::new();
+        let program = sources.add("test.lox", program);
+        let expression = sources.add("expression.lox", expression);
+
+        let mut vm = stressed();
+
+        let tokens = Scanner::new(sources.file(program)).scan_tokens().tokens;
+        vm.execute(&Parser::new(tokens).parse_program().expect("valid program"))?;
+
+        let tokens = Scanner::new(sources.file(expression)).scan_tokens().tokens;
+        vm.eval(&Parser::new(tokens).parse().expect("valid expression"))
+    }
+
+    #[test]
+    fn blocks_shadow_and_assign_outer_variables() {
+        let program = "var a = 1; var b = 1; { var a = 10; b = a + 1; }";
+
+        assert_eq!(Value::Number(12.0), run(program, "a + b").unwrap());
+    }
+
+    #[test]
+    fn loops_and_logical_operators() {
+        let program = "
+            var total = 0;
+            for (var i = 0; i < 5; i = i + 1) {
+                if (i == 2 or i == 4 and false) total = total + 100;
+                else total = total + i;
+            }";
+
+        assert_eq!(Value::Number(108.0), run(program, "total").unwrap());
+        assert_eq!(Value::String("x".into()), run("", "nil or \"x\"").unwrap());
+        assert_eq!(Value::Nil, run("", "nil and undefined").unwrap());
+    }
+
+    #[test]
+    fn functions_return_and_capture_their_scope() {
+        let program = "
+            fun fib(n) { if (n < 2) return n; return fib(n - 1) + fib(n - 2); }
+            fun counter() {
+                var count = 0;
+                fun increment() { count = count + 1; return count; }
+                return increment;
+            }
+            var next = counter();
+            next();";
+
+        assert_eq!(Value::Number(55.0), run(program, "fib(10)").unwrap());
+        assert_eq!(Value::Number(2.0), run(program, "next()").unwrap());
+        assert_eq!(Value::Bool(true), run(program, "fib == fib").unwrap());
+    }
+
+    #[test]
+    fn closures_keep_the_variable_they_captured() {
+        let program = "
+            var a = \"global\";
+            var seen = \"\";
+            {
+                fun show() { seen = seen + a; }
+                show();
+                var a = \"block\";
+                show();
+            }";
+
+        assert_eq!(
+            Value::String("globalglobal".into()),
+            run(program, "seen").unwrap()
+        );
+    }
+
+    #[test]
+    fn closures_share_variables_after_they_go_out_of_scope() {
+        let program = "
+            var get;
+            var set;
+            {
+                var shared = 1;
+                fun g() { return shared; }
+                fun s(value) { shared = value; }
+                get = g;
+                set = s;
+            }
+            set(42);";
+
+        assert_eq!(Value::Number(42.0), run(program, "get()").unwrap());
+    }
+
+    #[test]
+    fn closures_capturing_themselves_are_collected() {
+        let mut sources = SourceMap::new();
+        let program = sources.add(
+            "test.lox",
+            "for (var i = 0; i < 100; i = i + 1) { fun recurse() { return recurse; } }",
+        );
+
+        let mut vm = stressed();
+
+        let tokens = Scanner::new(sources.file(program)).scan_tokens().tokens;
+        vm.execute(&Parser::new(tokens).parse_program().expect("valid program"))
+            .unwrap();
+
+        assert!(vm.heap.len() <= 3, "{} objects alive", vm.heap.len());
+
+        vm.collect_garbage();
+        assert!(vm.heap.is_empty());
+    }
+
+    #[test]
+    fn limits_stop_runaway_programs() {
+        let limited = |program: &str, limits: InterpreterLimits| {
+            let mut sources = SourceMap::new();
+            let file = sources.add("test.lox", program);
+
+            let tokens = Scanner::new(sources.file(file)).scan_tokens().tokens;
+            let statements = Parser::new(tokens).parse_program().expect("valid program");
+
+            let mut vm = Vm::new();
+            vm.set_limits(limits);
+
+            vm.execute(&statements).expect_err(program)
+        };
+
+        let error = limited(
+            "while (true) {}",
+            InterpreterLimits {
+                max_steps: Some(1000),
+                ..InterpreterLimits::default()
+            },
+        );
+        assert_eq!(ErrorKind::StepLimit, error.kind);
+        assert_eq!("Step limit of 1000 exceeded", error.message);
+
+        let error = limited(
+            "fun f(n) { return f(n + 1); } f(0);",
+            InterpreterLimits {
+                max_call_depth: 1000,
+                ..InterpreterLimits::default()
+            },
+        );
+        assert_eq!(ErrorKind::CallDepth, error.kind);
+        assert_eq!(26, error.section.start.column);
+
+        let heap = InterpreterLimits {
+            max_heap_bytes: Some(64 * 1024),
+            ..InterpreterLimits::default()
+        };
+
+        let error = limited("var s = \"s\"; while (true) s = s + s;", heap);
+        assert_eq!(ErrorKind::HeapLimit, error.kind);
+        assert_eq!(33, error.section.start.column);
+
+        let chain = "
+            var chain = nil;
+            while (true) {
+                var previous = chain;
+                fun link() { return previous; }
+                chain = link;
+            }";
+        assert_eq!(ErrorKind::HeapLimit, limited(chain, heap).kind);
+
+        // Garbage doesn't count.
+        let garbage = "for (var i = 0; i < 10000; i = i + 1) { fun f() {} }";
+        let mut sources = SourceMap::new();
+        let file = sources.add("test.lox", garbage);
+        let tokens = Scanner::new(sources.file(file)).scan_tokens().tokens;
+        let mut vm = Vm::new();
+        vm.set_limits(heap);
+        vm.execute(&Parser::new(tokens).parse_program().unwrap())
+            .unwrap();
+    }
+
+    /// Keeps `count` strings of about 1 KiB alive when `kept` is true, only the last one otherwise.
+    fn strings(kept: bool) -> String {
+        let keep = if kept {
+            "kept = keep(kept, base + piece);"
+        } else {
+            "kept = base + piece;"
+        };
+
+        format!(
+            "var base = \"x\";
+            for (var i = 0; i < 10; i = i + 1) base = base + base;
+            fun keep(previous, text) {{ fun link() {{ previous; return text; }} return link; }}
+            var kept = nil;
+            var piece = \"\";
+            for (var i = 0; i < 100; i = i + 1) {{ piece = piece + \"y\"; {} }}",
+            keep
+        )
+    }
+    #[test]
+    fn strings_alive_count_towards_the_heap() {
+        let run = |program: &str| {
+            let mut sources = SourceMap::new();
+            let file = sources.add("test.lox", program);
+
+            let tokens = Scanner::new(sources.file(file)).scan_tokens().tokens;
+            let statements = Parser::new(tokens).parse_program().expect("valid program");
+
+            let mut vm = Vm::new();
+            vm.set_limits(InterpreterLimits {
+                max_heap_bytes: Some(64 * 1024),
+                ..InterpreterLimits::default()
+            });
+
+            vm.execute(&statements)
+        };
+
+        let error = run(&strings(true)).unwrap_err();
+        assert_eq!(ErrorKind::HeapLimit, error.kind);
+
+        assert!(run(&strings(false)).is_ok());
+    }
+
+    #[test]
+    fn interrupts_stop_the_running_script() {
+        let mut sources = SourceMap::new();
+        let file = sources.add("test.lox", "while (true) {}");
+
+        let tokens = Scanner::new(sources.file(file)).scan_tokens().tokens;
+        let statements = Parser::new(tokens).parse_program().expect("valid program");
+
+        let mut vm = Vm::new();
+        let interrupt = vm.interrupt_handle();
+
+        std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            interrupt.interrupt();
+        });
+
+        let error = vm.execute(&statements).unwrap_err();
+        assert_eq!(ErrorKind::Interrupted, error.kind);
+
+        // The interrupt is spent, the next run goes on.
+        assert!(vm.execute(&[]).is_ok());
+    }
+
+    #[test]
+    fn runtime_errors_point_at_the_culprit() {
+        let cases = [
+            ("", "missing", "Undefined variable 'missing'", 1),
+            ("fun f(a) {}", "f()", "Expected 1 arguments but got 0", 3),
+            ("", "\"f\"()", "Can only call functions and classes", 5),
+            ("", "1 < \"2\"", "Binary operator '<' expects two numbers, instead got: left='Number(1.0)' right='String(\"2\")'", 3),
+            ("fun f() { return f(); }", "f()", "Stack overflow", 20),
+        ];
+
+        for (program, expression, message, column) in cases {
+            let error = run(program, expression).expect_err(expression);
+
+            assert_eq!(message, error.message);
+            assert_eq!(column, error.section.start.column, "{}", expression);
+        }
+    }
+
+    /// Output kept where the test can still read it.
+    #[derive(Clone, Default)]
+    struct Captured(Rc<std::cell::RefCell<Vec<u8>>>);
+
+    impl Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn print_writes_to_the_output() {
+        let mut sources = SourceMap::new();
+        let program = sources.add("test.lox", "print 1 + 2; print \"a\" + \"b\"; print nil;");
+
+        let mut vm = stressed();
+        let output = Captured::default();
+        vm.set_output(output.clone());
+
+        let tokens = Scanner::new(sources.file(program)).scan_tokens().tokens;
+        vm.execute(&Parser::new(tokens).parse_program().unwrap())
+            .unwrap();
+
+        assert_eq!(b"3\nab\nnil\n", output.0.borrow().as_slice());
+    }
+}
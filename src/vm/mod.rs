@@ -0,0 +1,11 @@
+pub mod chunk;
+pub mod compiler;
+pub mod disassembler;
+pub mod heap;
+pub mod loxc;
+mod machine;
+#[cfg(feature = "nan-boxing")]
+pub mod nan_box;
+pub mod value;
+
+pub use machine::Vm;
@@ -0,0 +1,221 @@
+//! Values packed in the 64 bits of a double. Numbers are stored as they are, everything else hides
+//! in the payload of a quiet NaN, which leaves 48 bits for the value:
+//!
+//! ```text
+//! number   any double that isn't one of the below, NaNs are all turned into the same one
+//! nil      0 11111111111 11 00..01
+//! false    0 11111111111 11 00..10
+//! true     0 11111111111 11 00..11
+//! object   1 11111111111 11 tag(2) payload(48)
+//! ```
+//!
+//! Objects are tagged as strings or natives, whose payload is the pointer of the `Rc`, or closures,
+//! whose payload is the [`ObjRef`] bits. Pointers fitting in 48 bits holds on x86-64 and aarch64.
+
+use std::rc::Rc;
+
+use crate::intern::Symbol;
+use crate::vm::heap::ObjRef;
+use crate::vm::value::NativeFunction;
+use crate::vm::value::Value;
+
+const QNAN: u64 = 0x7ffc_0000_0000_0000;
+const SIGN: u64 = 0x8000_0000_0000_0000;
+
+const NIL: u64 = QNAN | 1;
+const FALSE: u64 = QNAN | 2;
+const TRUE: u64 = QNAN | 3;
+
+const OBJECT: u64 = SIGN | QNAN;
+const TAG: u64 = OBJECT | 0b11 << 48;
+const PAYLOAD: u64 = (1 << 48) - 1;
+
+const STRING: u64 = OBJECT;
+const CLOSURE: u64 = OBJECT | 1 << 48;
+const NATIVE: u64 = OBJECT | 2 << 48;
+
+/// A [`Value`] in 64 bits. It owns the strings and natives it points to, like the enum does.
+pub struct NanBox(u64);
+
+impl NanBox {
+    /// Everything is truthy but `false` and `nil`.
+    pub fn is_truthy(&self) -> bool {
+        self.0 != NIL && self.0 != FALSE
+    }
+
+    pub fn as_number(&self) -> Option<f64> {
+        self.is_number().then(|| f64::from_bits(self.0))
+    }
+
+    pub fn as_closure(&self) -> Option<ObjRef> {
+        (self.0 & TAG == CLOSURE).then(|| ObjRef::from_bits(self.0 & PAYLOAD))
+    }
+
+    pub fn as_string(&self) -> Option<Symbol> {
+        match self.unpack() {
+            Value::String(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    /// The value as an enum, sharing what it points to.
+    pub fn unpack(&self) -> Value {
+        self.clone().into_value()
+    }
+
+    pub fn into_value(self) -> Value {
+        let bits = std::mem::ManuallyDrop::new(self).0;
+
+        if bits & QNAN != QNAN {
+            return Value::Number(f64::from_bits(bits));
+        }
+
+        match bits {
+            NIL => return Value::Nil,
+            FALSE => return Value::Bool(false),
+            TRUE => return Value::Bool(true),
+            _ => (),
+        }
+
+        let payload = bits & PAYLOAD;
+
+        // Safety: the reference given up in `from` is taken back, once.
+        match bits & TAG {
+            STRING => Value::String(unsafe { Symbol::from_raw(payload as *const ()) }),
+            CLOSURE => Value::Closure(ObjRef::from_bits(payload)),
+            NATIVE => Value::Native(unsafe { Rc::from_raw(payload as *const NativeFunction) }),
+            _ => unreachable!("invalid NaN-boxed value {:#x}", bits),
+        }
+    }
+
+    fn is_number(&self) -> bool {
+        self.0 & QNAN != QNAN
+    }
+
+    fn pointer(tag: u64, pointer: usize) -> Self {
+        let pointer = pointer as u64;
+        debug_assert_eq!(pointer & PAYLOAD, pointer, "pointer wider than 48 bits");
+
+        Self(tag | pointer)
+    }
+}
+
+impl From<Value> for NanBox {
+    fn from(value: Value) -> Self {
+        match value {
+            Value::Nil => Self(NIL),
+            Value::Bool(false) => Self(FALSE),
+            Value::Bool(true) => Self(TRUE),
+            Value::Number(value) if value.is_nan() => Self(f64::NAN.to_bits()),
+            Value::Number(value) => Self(value.to_bits()),
+            Value::String(value) => Self::pointer(STRING, value.into_raw() as usize),
+            Value::Closure(object) => Self(CLOSURE | object.to_bits()),
+            Value::Native(native) => Self::pointer(NATIVE, Rc::into_raw(native) as usize),
+        }
+    }
+}
+
+impl Clone for NanBox {
+    fn clone(&self) -> Self {
+        let payload = self.0 & PAYLOAD;
+
+        // Safety: the pointer is alive as long as this value is.
+        match self.0 & TAG {
+            STRING => unsafe {
+                let symbol = std::mem::ManuallyDrop::new(Symbol::from_raw(payload as *const ()));
+                std::mem::forget(Symbol::clone(&symbol));
+            },
+            NATIVE => unsafe {
+                Rc::increment_strong_count(payload as *const NativeFunction);
+            },
+            _ => (),
+        }
+
+        Self(self.0)
+    }
+}
+
+impl Drop for NanBox {
+    fn drop(&mut self) {
+        let payload = self.0 & PAYLOAD;
+
+        // Safety: the value owns one reference, given up here.
+        match self.0 & TAG {
+            STRING => unsafe {
+                drop(Symbol::from_raw(payload as *const ()));
+            },
+            NATIVE => unsafe {
+                Rc::decrement_strong_count(payload as *const NativeFunction);
+            },
+            _ => (),
+        }
+    }
+}
+
+/// Strings are interned and everything else is compared by identity, so the bits are enough but
+/// for numbers, where `NaN` isn't equal to itself and `0` is equal to `-0`.
+impl PartialEq for NanBox {
+    fn eq(&self, other: &Self) -> bool {
+        match (self.as_number(), other.as_number()) {
+            (Some(left), Some(right)) => left == right,
+            _ => self.0 == other.0,
+        }
+    }
+}
+
+impl std::fmt::Debug for NanBox {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}", self.unpack())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::value::clock;
+
+    #[test]
+    fn values_survive_the_round_trip() {
+        let native = Rc::new(NativeFunction {
+            name: "clock",
+            arity: 0,
+            function: clock,
+        });
+        let values = [
+            Value::Nil,
+            Value::Bool(true),
+            Value::Bool(false),
+            Value::Number(-1.5),
+            Value::Number(f64::INFINITY),
+            Value::String("packed".into()),
+            Value::Closure(ObjRef::from_bits(0xbeef_0000_0007)),
+            Value::Native(native.clone()),
+        ];
+
+        assert_eq!(8, std::mem::size_of::<NanBox>());
+
+        for value in values {
+            let packed = NanBox::from(value.clone());
+
+            assert_eq!(value, packed.unpack());
+            assert_eq!(value.is_truthy(), packed.is_truthy());
+            assert_eq!(packed, packed.clone());
+        }
+
+        assert_eq!(1, Rc::strong_count(&native));
+    }
+
+    #[test]
+    fn numbers_compare_like_doubles() {
+        let nan = NanBox::from(Value::Number(-f64::NAN));
+
+        assert!(nan.as_number().unwrap().is_nan());
+        assert_ne!(nan, nan.clone());
+        assert_eq!(
+            NanBox::from(Value::Number(0.0)),
+            NanBox::from(Value::Number(-0.0))
+        );
+        assert_ne!(NanBox::from(Value::Number(1.0)), Value::Bool(true).into());
+        assert_eq!(None, NanBox::from(Value::Nil).as_number());
+    }
+}
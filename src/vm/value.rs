@@ -0,0 +1,126 @@
+use std::rc::Rc;
+
+use crate::intern::Symbol;
+use crate::vm::heap::ObjRef;
+
+/// Values on the VM stack.
+#[derive(Clone)]
+pub enum Value {
+    Nil,
+    Bool(bool),
+    Number(f64),
+    String(Symbol),
+    Closure(ObjRef),
+    Native(Rc<NativeFunction>),
+}
+
+/// How the VM stores values in its stack, globals and upvalues. Either the enum itself or, with
+/// the `nan-boxing` feature, the value packed in the 64 bits of a double.
+#[cfg(not(feature = "nan-boxing"))]
+pub type Packed = Value;
+
+#[cfg(feature = "nan-boxing")]
+pub type Packed = crate::vm::nan_box::NanBox;
+
+/// Same API as [`NanBox`](crate::vm::nan_box::NanBox), so the VM works with either.
+impl Value {
+    /// Everything is truthy but `false` and `nil`.
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Value::Nil | Value::Bool(false))
+    }
+
+    /// How the VM stores it.
+    #[allow(clippy::useless_conversion)] // Only without nan-boxing.
+    pub fn pack(self) -> Packed {
+        self.into()
+    }
+
+    pub fn unpack(&self) -> Value {
+        self.clone()
+    }
+
+    pub fn into_value(self) -> Value {
+        self
+    }
+
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            Value::Number(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_closure(&self) -> Option<ObjRef> {
+        match self {
+            Value::Closure(object) => Some(*object),
+            _ => None,
+        }
+    }
+
+    pub fn as_string(&self) -> Option<Symbol> {
+        match self {
+            Value::String(string) => Some(string.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// Functions are only equal to themselves.
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Nil, Value::Nil) => true,
+            (Value::Bool(left), Value::Bool(right)) => left == right,
+            (Value::Number(left), Value::Number(right)) => left == right,
+            (Value::String(left), Value::String(right)) => left == right,
+            (Value::Closure(left), Value::Closure(right)) => left == right,
+            (Value::Native(left), Value::Native(right)) => Rc::ptr_eq(left, right),
+            _ => false,
+        }
+    }
+}
+
+/// Reads the same as the tree walker's values, errors quote them. Closures only show where they
+/// are, [`Heap::debug`](crate::vm::heap::Heap::debug) knows their name.
+impl std::fmt::Debug for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Value::Nil => write!(f, "Nil"),
+            Value::Bool(value) => write!(f, "Bool({:?})", value),
+            Value::Number(value) => write!(f, "Number({:?})", value),
+            Value::String(value) => write!(f, "String({:?})", value),
+            Value::Closure(object) => write!(f, "Function(#{})", object.index()),
+            Value::Native(native) => write!(f, "Native({})", native.name),
+        }
+    }
+}
+
+/// How `print` shows them, except for closures whose name is in the heap, see
+/// [`Heap::display`](crate::vm::heap::Heap::display).
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Value::Nil => write!(f, "nil"),
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::Number(value) => write!(f, "{}", value),
+            Value::String(value) => write!(f, "{}", value),
+            Value::Closure(object) => write!(f, "<fn #{}>", object.index()),
+            Value::Native(_) => write!(f, "<native fn>"),
+        }
+    }
+}
+
+pub struct NativeFunction {
+    pub name: &'static str,
+    pub arity: usize,
+    pub function: fn(&[Value]) -> Value,
+}
+
+/// Seconds since the epoch, `clock()` in Lox.
+pub fn clock(_: &[Value]) -> Value {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default();
+
+    Value::Number(now.as_secs_f64())
+}
@@ -0,0 +1,104 @@
+//! Runs every program in `tests/programs` on both backends, they must print the same and fail the
+//! same way, pointing at the same place. Both run once more collecting garbage all the time.
+
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+
+struct Outcome {
+    stdout: String,
+    stderr: String,
+    success: bool,
+}
+
+fn run(args: &[&str], program: &Path) -> Outcome {
+    let output = Command::new(env!("CARGO_BIN_EXE_jrlox"))
+        .args(args)
+        .arg(program)
+        .env("RUST_BACKTRACE", "0")
+        .env("RUST_LIB_BACKTRACE", "0")
+        .output()
+        .expect("jrlox runs");
+
+    Outcome {
+        stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        success: output.status.success(),
+    }
+}
+
+fn programs() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/programs");
+
+    let mut programs: Vec<_> = std::fs::read_dir(dir)
+        .expect("tests/programs exists")
+        .map(|entry| entry.expect("readable entry").path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "lox"))
+        .collect();
+    programs.sort();
+
+    programs
+}
+
+#[test]
+fn backends_agree_on_every_program() {
+    let programs = programs();
+    assert!(!programs.is_empty(), "no programs to compare");
+
+    let mut mismatches = Vec::new();
+
+    for program in programs.iter() {
+        let tree = run(&["--backend=tree"], program);
+        let vm = run(&["--backend=vm"], program);
+        // Collecting all the time must not change what the program does.
+        let stressed = run(&["--backend=vm", "--gc-stress"], program);
+        let tree_stressed = run(&["--backend=tree", "--gc-stress"], program);
+
+        let name = program.file_name().unwrap().to_string_lossy();
+        let expects_error = name.starts_with("error_");
+
+        assert!(
+            !tree.stdout.is_empty() || !tree.stderr.is_empty(),
+            "{} does nothing",
+            name
+        );
+        assert_eq!(
+            expects_error,
+            !tree.success,
+            "{} should {}: {}",
+            name,
+            if expects_error { "fail" } else { "succeed" },
+            tree.stderr
+        );
+
+        if tree.stdout != vm.stdout {
+            mismatches.push(format!(
+                "{}: stdout differs\n-- tree --\n{}-- vm --\n{}",
+                name, tree.stdout, vm.stdout
+            ));
+        }
+
+        if tree.stderr != vm.stderr || tree.success != vm.success {
+            mismatches.push(format!(
+                "{}: errors differ\n-- tree --\n{}-- vm --\n{}",
+                name, tree.stderr, vm.stderr
+            ));
+        }
+
+        if (&vm.stdout, &vm.stderr) != (&stressed.stdout, &stressed.stderr) {
+            mismatches.push(format!(
+                "{}: --gc-stress changes the outcome\n-- vm --\n{}{}-- stressed --\n{}{}",
+                name, vm.stdout, vm.stderr, stressed.stdout, stressed.stderr
+            ));
+        }
+
+        if (&tree.stdout, &tree.stderr) != (&tree_stressed.stdout, &tree_stressed.stderr) {
+            mismatches.push(format!(
+                "{}: --gc-stress changes the tree walker's outcome\n-- tree --\n{}{}-- stressed --\n{}{}",
+                name, tree.stdout, tree.stderr, tree_stressed.stdout, tree_stressed.stderr
+            ));
+        }
+    }
+
+    assert!(mismatches.is_empty(), "\n{}", mismatches.join("\n"));
+}
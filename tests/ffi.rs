@@ -0,0 +1,48 @@
+//! Builds `tests/ffi/embed.c` against the C library and checks what it prints, the C side
+//! registering natives, running sources and reading errors through `include/jrlox.h`.
+
+#![cfg(unix)]
+
+use std::path::Path;
+use std::process::Command;
+
+#[test]
+fn c_hosts_embed_the_interpreter() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+
+    // Tests run from `target/<profile>/deps`, next to where the library is.
+    let test = std::env::current_exe().expect("test executable");
+    let library = test
+        .parent()
+        .and_then(Path::parent)
+        .expect("target directory");
+
+    let program = Path::new(env!("CARGO_TARGET_TMPDIR")).join("embed");
+
+    let status = Command::new("cc")
+        .arg(root.join("tests/ffi/embed.c"))
+        .arg("-I")
+        .arg(root.join("include"))
+        .arg("-L")
+        .arg(library)
+        .arg(format!("-Wl,-rpath,{}", library.display()))
+        .arg("-ljrlox")
+        .arg("-o")
+        .arg(&program)
+        .status()
+        .expect("a C compiler");
+    assert!(status.success(), "embed.c compiles");
+
+    let output = Command::new(&program).output().expect("embed runs");
+    assert!(output.status.success());
+
+    assert_eq!(
+        "13\n\
+         hi!\n\
+         error: [embed.lox:1:11] Error: add takes two numbers\n\
+         error: [embed.lox:1:7] Error: Undefined variable 'missing'\n\
+         calls: 3, errors left: 1\n\
+         null callback: 1, null interpreter: -1 0 1\n",
+        String::from_utf8_lossy(&output.stdout)
+    );
+}
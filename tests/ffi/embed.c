@@ -0,0 +1,69 @@
+/* Embeds jrlox the way a C host would, run by `tests/ffi.rs` which checks what it prints. */
+#include <stdio.h>
+#include <string.h>
+
+#include "jrlox.h"
+
+/* Adds up its two numbers, counting the calls in `user_data`. */
+static int add(void *user_data, const jrlox_value *arguments, size_t count, jrlox_value *result) {
+    int *calls = user_data;
+    *calls += 1;
+
+    if (count != 2 || arguments[0].kind != JRLOX_NUMBER || arguments[1].kind != JRLOX_NUMBER) {
+        result->kind = JRLOX_STRING;
+        result->string = "add takes two numbers";
+        return 1;
+    }
+
+    result->kind = JRLOX_NUMBER;
+    result->number = arguments[0].number + arguments[1].number;
+    return 0;
+}
+
+/* Shouts its string argument back. */
+static int shout(void *user_data, const jrlox_value *arguments, size_t count, jrlox_value *result) {
+    static char buffer[64];
+    (void)user_data;
+    (void)count;
+
+    snprintf(buffer, sizeof buffer, "%s!", arguments[0].string);
+
+    result->kind = JRLOX_STRING;
+    result->string = buffer;
+    return 0;
+}
+
+static void run(jrlox_interpreter *interpreter, const char *source) {
+    fflush(stdout);
+
+    int errors = jrlox_run(interpreter, "embed.lox", source);
+
+    for (int i = 0; i < errors; i++) {
+        printf("error: %s\n", jrlox_error(interpreter, i));
+    }
+
+    fflush(stdout);
+}
+
+int main(void) {
+    int calls = 0;
+    jrlox_interpreter *interpreter = jrlox_new();
+
+    jrlox_define_native(interpreter, "add", 2, add, &calls);
+    jrlox_define_native(interpreter, "shout", 1, shout, NULL);
+
+    run(interpreter, "var total = add(1, 2);");
+    run(interpreter, "print add(total, 10); print shout(\"hi\");");
+    run(interpreter, "add(\"a\", 1);");
+    run(interpreter, "print missing;");
+    printf("calls: %d, errors left: %zu\n", calls, jrlox_error_count(interpreter));
+
+    /* Nulls are rejected instead of crashing. */
+    printf("null callback: %d, null interpreter: %d %zu %d\n",
+           jrlox_define_native(interpreter, "none", 0, NULL, NULL), jrlox_run(NULL, "embed.lox", ""),
+           jrlox_error_count(NULL), jrlox_error(NULL, 0) == NULL);
+
+    jrlox_free(interpreter);
+
+    return 0;
+}
@@ -0,0 +1,6 @@
+//! Mistakes in host objects exposed to scripts, caught while building them.
+
+#[test]
+fn object_diagnostics() {
+    trybuild::TestCases::new().compile_fail("tests/ui/*.rs");
+}
@@ -0,0 +1,24 @@
+use jrlox::interpreter::lox_methods;
+use jrlox::interpreter::LoxObject;
+use jrlox::interpreter::NativeResult;
+
+#[derive(LoxObject)]
+struct Rectangle {
+    width: f64,
+    area: f64,
+}
+
+#[lox_methods]
+impl Rectangle {
+    #[lox]
+    fn width(&self) -> NativeResult<f64> {
+        Ok(self.width)
+    }
+
+    #[lox]
+    fn perimeter(&self) -> NativeResult<f64> {
+        Ok(self.area)
+    }
+}
+
+fn main() {}